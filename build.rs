@@ -2,7 +2,12 @@ use std::path::PathBuf;
 
 fn main() {
     let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
-    let kernel = PathBuf::from(std::env::var_os("CARGO_BIN_FILE_KERNEL_kernel").unwrap());
+    let kernel_binary = PathBuf::from(std::env::var_os("CARGO_BIN_FILE_KERNEL_kernel").unwrap());
+
+    // Embed the symbols into a copy of the kernel, so that it can print
+    // symbolized backtraces.
+    let kernel = out_dir.join("kernel");
+    kernel_symbols::embed(&kernel_binary, &kernel).unwrap();
 
     let bios_path = out_dir.join("bios.img");
     bootloader::BiosBoot::new(&kernel)
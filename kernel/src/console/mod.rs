@@ -0,0 +1,257 @@
+pub mod ring;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::devices::{serial, shutdown};
+use crate::threads::Mutex;
+
+/// A device the console writes to, besides the serial port.
+///
+/// Sinks are called with the console locked, so they see the output of one
+/// [`print`] call at a time.
+pub trait Sink: Sync {
+    /// Writes `s` to the device.
+    fn write_str(&self, s: &str);
+}
+
+/// A sink registered to the console.
+struct Registration {
+    name: &'static str,
+    sink: &'static dyn Sink,
+    enabled: bool,
+}
+
+/// Console writer for kernel.
+///
+/// It writes to the serial port, and fans the output out to the registered
+/// [`Sink`]s, such as the framebuffer and the in-memory [`ring::RING`]. Each
+/// of them, the serial port included, can be disabled by name.
+pub struct Console {
+    // Serial port device used for console output.
+    serial: serial::Serial,
+    serial_enabled: bool,
+
+    // Other devices the output goes to.
+    sinks: [Option<Registration>; Console::MAX_SINKS],
+
+    // Number of characters written to console.
+    write_cnt: usize,
+}
+
+impl Console {
+    /// Maximum number of sinks, besides the serial port.
+    pub const MAX_SINKS: usize = 4;
+
+    /// Name of the serial port, for [`Console::set_enabled()`].
+    pub const SERIAL: &'static str = "serial";
+
+    /// Creates a new console writer.
+    #[must_use = "Console must be initialized before use"]
+    pub const fn new() -> Self {
+        Self {
+            serial: serial::Serial::new(serial::Config::new()),
+            serial_enabled: true,
+            sinks: [None, None, None, None],
+            write_cnt: 0,
+        }
+    }
+
+    /// Initializes the console, on the serial port configured by `config`.
+    pub fn init(&mut self, config: serial::Config) {
+        // Initialize serial port to polling mode:
+        // so that we can write to it before interrupts are enabled.
+        self.serial = serial::Serial::new(config);
+        self.serial.init_poll();
+
+        // Keep the output in memory as well.
+        self.register("ring", &ring::RING);
+    }
+
+    /// Switches the console to interrupt-driven output, once the interrupt
+    /// system is initialized.
+    pub fn init_queue(&mut self) {
+        self.serial.init_queue();
+    }
+
+    /// Adds `sink` to the devices the console writes to, enabled. Panics if
+    /// there are already [`Console::MAX_SINKS`] of them.
+    pub fn register(&mut self, name: &'static str, sink: &'static dyn Sink) {
+        let slot = self
+            .sinks
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .expect("too many console sinks");
+
+        *slot = Some(Registration {
+            name,
+            sink,
+            enabled: true,
+        });
+    }
+
+    /// Enables or disables the sink called `name`, or the serial port if it is
+    /// [`Console::SERIAL`]. Returns `false` if there is no such sink.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        if name == Self::SERIAL {
+            self.serial_enabled = enabled;
+            return true;
+        }
+
+        match self.registration(name) {
+            Some(registration) => {
+                registration.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the sink called `name` is registered and enabled.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        if name == Self::SERIAL {
+            return self.serial_enabled;
+        }
+
+        self.sinks
+            .iter()
+            .flatten()
+            .any(|registration| registration.name == name && registration.enabled)
+    }
+
+    fn registration(&mut self, name: &str) -> Option<&mut Registration> {
+        self.sinks
+            .iter_mut()
+            .flatten()
+            .find(|registration| registration.name == name)
+    }
+
+    /// Prints console statistics.
+    pub fn print_stats(&mut self) {
+        use core::fmt::Write;
+        let write_cnt = self.write_cnt;
+        writeln!(self, "Console: {write_cnt} characters output")
+            .expect("Failed to write to console");
+    }
+}
+
+impl core::default::Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // In emergency mode, the sinks' locks may be held by the code that
+        // panicked, so only the serial port is used.
+        if is_emergency() {
+            self.serial.write_str(s)?;
+            self.write_cnt += s.len();
+            return Ok(());
+        }
+
+        if self.serial_enabled {
+            self.serial.write_str(s)?;
+        }
+        for registration in self.sinks.iter().flatten() {
+            if registration.enabled {
+                registration.sink.write_str(s);
+            }
+        }
+        self.write_cnt += s.len();
+        Ok(())
+    }
+}
+
+/// Global console writer.
+///
+/// The serial layer do their own locking, so it's safe to call them at any
+/// time. But this [`Mutex`] is useful to prevent simultaneous [`print`] calls
+/// from mixing their output, which looks confusing.
+pub static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
+
+/// Whether the console is in emergency mode.
+static EMERGENCY: AtomicBool = AtomicBool::new(false);
+
+/// Number of times [`panic()`] was called.
+static PANIC_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Tells the console that a kernel panic is underway, like Pintos'
+/// `console_panic()`, switching to [`emergency()`] mode. Panic handlers call
+/// this first.
+///
+/// If a panic is already underway, the panic handler itself must have
+/// panicked, and may do so again, so it prints a minimal message straight to
+/// the serial port, and powers off with failure.
+pub fn panic() {
+    if PANIC_COUNT.fetch_add(1, Ordering::SeqCst) > 0 {
+        use core::fmt::Write;
+
+        let config = unsafe { CONSOLE.steal() }.serial.config();
+        let _ = serial::Serial::attach(config).write_str("Kernel PANIC recursion!\n");
+        shutdown::abort();
+    }
+
+    emergency();
+}
+
+/// Switches the console to emergency mode, for reporting a fatal error.
+///
+/// From now on, output goes straight to the serial port, by polling, since
+/// interrupts may never come back to drain the queue. The console lock is
+/// bypassed as well, since the code that failed may hold it.
+pub fn emergency() {
+    EMERGENCY.store(true, Ordering::SeqCst);
+    serial::force_poll();
+}
+
+/// Returns `true` if the console is in emergency mode.
+pub fn is_emergency() -> bool {
+    EMERGENCY.load(Ordering::SeqCst)
+}
+
+/// Runs `f` with the console locked, or in emergency mode, with the console
+/// as it is, without waiting for the lock.
+pub fn with_console<R>(f: impl FnOnce(&mut Console) -> R) -> R {
+    if is_emergency() {
+        f(unsafe { CONSOLE.steal() })
+    } else {
+        f(&mut CONSOLE.lock())
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: core::fmt::Arguments) {
+    use core::fmt::Write;
+
+    with_console(|console| console.write_fmt(args)).expect("Failed to write to console");
+}
+
+/// Prints to the console.
+///
+/// # Safety
+/// This macro is unsafe because it uses a mutable static reference to the
+/// global console writer. The caller should ensure that the console writer is
+/// not accessed by other threads. We can remove the `unsafe` keyword after we
+/// implement threads and synchronization.
+///
+/// TODO: implement synchronization and protect the console writer.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::console::_print(format_args!($($arg)*)));
+}
+
+/// Prints to the console, with a newline.
+///
+/// # Safety
+/// This macro is unsafe because it uses a mutable static reference to the
+/// global console writer. The caller should ensure that the console writer is
+/// not accessed by other threads. We can remove the `unsafe` keyword after we
+/// implement threads and synchronization.
+///
+/// TODO: implement synchronization and protect the console writer.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
@@ -0,0 +1,83 @@
+use crate::threads::Mutex;
+
+use super::Sink;
+
+/// Circular buffer keeping the most recent console output in memory, so that
+/// it can be looked at after it scrolled off the screen.
+pub struct Ring {
+    buffer: [u8; Ring::SIZE],
+
+    /// Index of the next byte to write.
+    head: usize,
+
+    /// Number of bytes held.
+    len: usize,
+}
+
+impl Ring {
+    /// Capacity of the ring, in bytes.
+    pub const SIZE: usize = 16 * 1024;
+
+    /// Creates an empty [`Ring`].
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; Self::SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of bytes held.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if nothing was written since the ring was last cleared.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends `bytes`, overwriting the oldest ones once the ring is full.
+    pub fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.buffer[self.head] = byte;
+            self.head = (self.head + 1) % Self::SIZE;
+        }
+        self.len = (self.len + bytes.len()).min(Self::SIZE);
+    }
+
+    /// Returns the bytes held, oldest first. The first one may be in the
+    /// middle of a UTF-8 sequence, if older ones were overwritten.
+    pub fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        let tail = (self.head + Self::SIZE - self.len) % Self::SIZE;
+        (0..self.len).map(move |i| self.buffer[(tail + i) % Self::SIZE])
+    }
+
+    /// Removes all the bytes.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+impl Default for Ring {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Write for Ring {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl Sink for Mutex<Ring> {
+    fn write_str(&self, s: &str) {
+        self.lock().push(s.as_bytes());
+    }
+}
+
+/// Console output kept in memory, registered to the console as `"ring"`.
+pub static RING: Mutex<Ring> = Mutex::new(Ring::new());
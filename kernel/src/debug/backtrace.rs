@@ -0,0 +1,139 @@
+use crate::{
+    println,
+    threads::{interrupt::Frame, thread::Thread},
+};
+
+use super::symbols;
+
+/// A call stack, walked through the frame pointers.
+///
+/// The kernel is built with frame pointers forced on, so every function saves
+/// the caller's `rbp` right below its return address, and points `rbp` to it:
+///
+/// ```text
+///     |    return address     |  rbp + 8
+///     |  caller's saved rbp   |  <- rbp
+///     |   local variables     |
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Backtrace {
+    /// Address of the instruction the walk starts at, if known.
+    rip: Option<u64>,
+
+    /// Frame pointer of the function at `rip`.
+    rbp: u64,
+}
+
+impl Backtrace {
+    /// Maximum number of frames to walk.
+    const MAX_FRAMES: usize = 64;
+
+    /// Captures the call stack of the caller.
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let rbp: u64;
+        unsafe {
+            core::arch::asm!("mov {}, rbp", out(reg) rbp);
+        }
+
+        Self { rip: None, rbp }
+    }
+
+    /// Returns the call stack of the code interrupted at `frame`.
+    pub fn from_frame(frame: &Frame) -> Self {
+        Self {
+            rip: Some(frame.rip),
+            rbp: frame.rbp,
+        }
+    }
+
+    /// Returns the call stack of `thread`, which is not running.
+    ///
+    /// A thread which is not running is stopped in `switch_threads()`, which
+    /// saved its registers on its stack:
+    ///
+    /// ```text
+    ///     |    return address     |  stack + 32
+    ///     |          rbx          |  stack + 24
+    ///     |          rbp          |  stack + 16
+    ///     |          rsi          |  stack + 8
+    ///     |          rdi          |  <- stack
+    /// ```
+    pub fn from_thread(thread: &Thread) -> Self {
+        let stack = thread.stack as *const u64;
+        unsafe {
+            Self {
+                rip: Some(*stack.add(4)),
+                rbp: *stack.add(2),
+            }
+        }
+    }
+
+    /// Returns the addresses of the call stack, innermost first.
+    ///
+    /// Only frames on the stack `rbp` starts in are walked, since anything
+    /// outside it is surely not a saved frame pointer.
+    pub fn addresses(&self) -> impl Iterator<Item = u64> {
+        let stack_bottom = self.rbp & !Thread::STACK_MASK;
+        let stack_top = stack_bottom + Thread::STACK_SIZE as u64;
+
+        let mut rbp = self.rbp;
+        let return_addresses = core::iter::from_fn(move || {
+            // Frames are 8-byte aligned, and callers' frames are above their
+            // callees'.
+            if rbp % 8 != 0 || rbp < stack_bottom || rbp + 16 > stack_top {
+                return None;
+            }
+
+            let (saved_rbp, return_address) = unsafe {
+                let frame = rbp as *const u64;
+                (*frame, *frame.add(1))
+            };
+            if return_address == 0 {
+                return None;
+            }
+
+            rbp = if saved_rbp > rbp { saved_rbp } else { 0 };
+            Some(return_address)
+        });
+
+        self.rip
+            .into_iter()
+            .chain(return_addresses)
+            .take(Self::MAX_FRAMES)
+    }
+}
+
+impl core::fmt::Display for Backtrace {
+    /// Prints the call stack, one frame per line, like Pintos'
+    /// `debug_backtrace()` followed by its `backtrace` utility.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Call stack:")?;
+        for (depth, address) in self.addresses().enumerate() {
+            write!(f, "\n  #{depth:<2} {address:#018x}")?;
+
+            // Return addresses point right after the call instruction, which
+            // may be the last one of the caller, so look up the one before.
+            let call_site = if depth == 0 && self.rip.is_some() {
+                address
+            } else {
+                address - 1
+            };
+            if let Some(symbol) = symbols::lookup(call_site) {
+                write!(
+                    f,
+                    " {}+{:#x}",
+                    symbol.name,
+                    address - (call_site - symbol.offset)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prints the call stack of the caller.
+#[inline(always)]
+pub fn print_backtrace() {
+    println!("{}", Backtrace::capture());
+}
@@ -0,0 +1,9 @@
+mod backtrace;
+pub mod monitor;
+mod symbols;
+
+pub use self::backtrace::print_backtrace;
+pub use self::backtrace::Backtrace;
+
+pub use self::symbols::lookup;
+pub use self::symbols::Symbol;
@@ -0,0 +1,372 @@
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    devices::{
+        block, pci,
+        serial::{self, Serial},
+        shutdown,
+    },
+    logging,
+    threads::{
+        addr::{self, VirtAddr},
+        interrupt::{self, Frame},
+        thread::{self, Thread},
+        ALLOCATOR, PAGE_ALLOCATOR, SCHEDULER,
+    },
+};
+
+use super::backtrace::Backtrace;
+
+/// Keys entering the monitor from the serial port: Ctrl-B, then `m`.
+const BREAK_SEQUENCE: [u8; 2] = [0x02, b'm'];
+
+/// Maximum length of a command line.
+const LINE_LENGTH: usize = 80;
+
+/// Number of bytes `peek` dumps by default, and at most.
+const PEEK_DEFAULT: u64 = 64;
+const PEEK_MAX: u64 = 4096;
+
+/// Whether the monitor is enabled.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Interactive debug monitor, over the serial port.
+///
+/// The monitor stops the whole kernel while it runs: interrupts are disabled,
+/// and it talks to the serial port by polling. It does not take any locks
+/// either, since the code it interrupted may hold them, so what it shows may
+/// be in the middle of an update.
+pub struct Monitor {
+    /// Serial port, used directly rather than through the console, whose lock
+    /// may be held.
+    serial: Serial,
+
+    /// Number of bytes of [`BREAK_SEQUENCE`] received so far.
+    matched: usize,
+}
+
+impl Monitor {
+    /// Creates a new [`Monitor`].
+    pub const fn new() -> Self {
+        Self {
+            serial: Serial::attach(serial::Config::new()),
+            matched: 0,
+        }
+    }
+
+    /// Returns `true` if the received `byte` completes the
+    /// [`BREAK_SEQUENCE`].
+    fn is_break(&mut self, byte: u8) -> bool {
+        if byte == BREAK_SEQUENCE[self.matched] {
+            self.matched += 1;
+        } else {
+            self.matched = (byte == BREAK_SEQUENCE[0]) as usize;
+        }
+
+        if self.matched == BREAK_SEQUENCE.len() {
+            self.matched = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Runs commands until `continue`. `frame` is the interrupted code, if
+    /// the monitor was entered from an interrupt.
+    fn run(&mut self, frame: Option<&Frame>) {
+        assert!(interrupt::are_disabled());
+
+        // Get the queued console output out of the way.
+        serial::flush();
+
+        let _ = writeln!(
+            self,
+            "\nEntering monitor in thread '{}'. Type 'help' for commands.",
+            thread::running_thread().name()
+        );
+
+        loop {
+            let _ = write!(self, "monitor> ");
+
+            let mut buffer = [0; LINE_LENGTH];
+            let length = self.read_line(&mut buffer);
+            let line = core::str::from_utf8(&buffer[..length]).unwrap_or("");
+
+            if let Ok(false) = self.execute(line, frame) {
+                break;
+            }
+        }
+
+        let _ = writeln!(self, "Leaving monitor.");
+    }
+
+    /// Executes the command `line`. Returns `false` if the monitor should
+    /// return to the interrupted code.
+    fn execute(&mut self, line: &str, frame: Option<&Frame>) -> Result<bool, core::fmt::Error> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(true),
+        };
+
+        match command {
+            "help" => self.help()?,
+            "threads" => self.threads()?,
+            "mem" => unsafe {
+                PAGE_ALLOCATOR.write_stats(self)?;
+                ALLOCATOR.write_stats(self)?;
+            },
+            "irq" => unsafe { interrupt::REGISTRY.peek() }.write_stats(self)?,
+            "palloc" => unsafe { PAGE_ALLOCATOR.write_allocations(self)? },
+            "pci" => unsafe { pci::PCI.peek() }.write_devices(self)?,
+            "block" => unsafe { block::BLOCKS.peek() }.write_stats(self)?,
+            "dmesg" => logging::dmesg(self)?,
+            "peek" => match words.next().and_then(parse_number) {
+                Some(address) => {
+                    let length = words
+                        .next()
+                        .and_then(parse_number)
+                        .unwrap_or(PEEK_DEFAULT)
+                        .min(PEEK_MAX);
+                    self.peek(address, length)?;
+                }
+                None => writeln!(self, "Usage: peek <address> [length]")?,
+            },
+            "bt" => self.backtrace(words.next(), frame)?,
+            "continue" | "c" => return Ok(false),
+            "reboot" => {
+                writeln!(self, "Rebooting...")?;
+                shutdown::reboot();
+            }
+            _ => writeln!(
+                self,
+                "Unknown command '{command}'. Type 'help' for commands."
+            )?,
+        }
+        Ok(true)
+    }
+
+    fn help(&mut self) -> core::fmt::Result {
+        writeln!(self, "threads               list all threads")?;
+        writeln!(self, "mem                   show memory usage")?;
+        writeln!(self, "irq                   show interrupt statistics")?;
+        writeln!(self, "palloc                list allocated pages")?;
+        writeln!(self, "pci                   list PCI devices")?;
+        writeln!(self, "block                 list block devices")?;
+        writeln!(self, "dmesg                 show the kernel log")?;
+        writeln!(self, "peek <addr> [len]     dump memory at <addr>")?;
+        writeln!(
+            self,
+            "bt [thread]           show the call stack of a thread, by id or name"
+        )?;
+        writeln!(self, "continue              leave the monitor")?;
+        writeln!(self, "reboot                reboot the machine")
+    }
+
+    fn threads(&mut self) -> core::fmt::Result {
+        writeln!(self, "   id  status   priority  stack               name")?;
+        for thread in unsafe { SCHEDULER.peek() }.threads() {
+            writeln!(
+                self,
+                "{:>5}  {:<8} {:>8}  {:#018x}  {}",
+                thread.id.as_u32(),
+                status_name(thread.status),
+                thread.priority,
+                thread.stack as u64,
+                thread.name()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Dumps `length` bytes at `address`, like Pintos' `hex_dump()`.
+    fn peek(&mut self, address: u64, length: u64) -> core::fmt::Result {
+        const BYTES_PER_LINE: u64 = 16;
+
+        let end = match address.checked_add(length) {
+            Some(end) => end,
+            None => {
+                return writeln!(
+                    self,
+                    "{address:#x}: {length} bytes run past the end of memory"
+                )
+            }
+        };
+
+        let mut line = address - address % BYTES_PER_LINE;
+        while line < end {
+            let mapped = VirtAddr::try_new(line).is_ok_and(addr::is_mapped);
+            if !mapped {
+                return writeln!(self, "{line:016x}  (not mapped)");
+            }
+
+            let bytes =
+                unsafe { core::slice::from_raw_parts(line as *const u8, BYTES_PER_LINE as usize) };
+            let shown = |i: usize| (address..end).contains(&(line + i as u64));
+
+            write!(self, "{line:016x} ")?;
+            for (i, byte) in bytes.iter().enumerate() {
+                if shown(i) {
+                    write!(self, " {byte:02x}")?;
+                } else {
+                    write!(self, "   ")?;
+                }
+            }
+            write!(self, "  |")?;
+            for (i, &byte) in bytes.iter().enumerate() {
+                let c = if !shown(i) {
+                    ' '
+                } else if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                };
+                write!(self, "{c}")?;
+            }
+            writeln!(self, "|")?;
+
+            line = match line.checked_add(BYTES_PER_LINE) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        Ok(())
+    }
+
+    /// Prints the call stack of the thread with the id or name `thread`, or
+    /// of the running thread if `None`.
+    fn backtrace(&mut self, thread: Option<&str>, frame: Option<&Frame>) -> core::fmt::Result {
+        let thread = match thread {
+            Some(thread) => match find_thread(thread) {
+                Some(thread) => thread,
+                None => return writeln!(self, "No thread '{thread}'."),
+            },
+            None => &*thread::running_thread(),
+        };
+
+        let backtrace = if thread.status != thread::Status::Running {
+            Backtrace::from_thread(thread)
+        } else if let Some(frame) = frame {
+            Backtrace::from_frame(frame)
+        } else {
+            Backtrace::capture()
+        };
+
+        writeln!(self, "Thread '{}':", thread.name())?;
+        writeln!(self, "{backtrace}")
+    }
+
+    /// Reads a line from the serial port, echoing it back, into `buffer`.
+    /// Returns the length of the line.
+    fn read_line(&mut self, buffer: &mut [u8]) -> usize {
+        let mut length = 0;
+        loop {
+            let byte = match self.serial.receive_poll() {
+                Some(byte) => byte,
+                None => {
+                    core::hint::spin_loop();
+                    continue;
+                }
+            };
+
+            match byte {
+                b'\r' | b'\n' => {
+                    self.serial.send(b'\n');
+                    return length;
+                }
+                // Backspace or delete.
+                0x08 | 0x7f => {
+                    if length > 0 {
+                        length -= 1;
+                        let _ = write!(self, "\x08 \x08");
+                    }
+                }
+                _ if length < buffer.len() && (byte.is_ascii_graphic() || byte == b' ') => {
+                    buffer[length] = byte;
+                    length += 1;
+                    self.serial.send(byte);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Write for Monitor {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.serial.write_str(s)
+    }
+}
+
+/// Global debug monitor.
+pub static MONITOR: interrupt::Mutex<Monitor> = interrupt::Mutex::new(Monitor::new());
+
+/// Enables the monitor: it is entered by [`BREAK_SEQUENCE`] on the serial
+/// port configured by `config`, and by [`enter_on_panic()`]. The port must be
+/// initialized, e.g. by the console.
+pub fn init(config: serial::Config) {
+    MONITOR.lock().serial = Serial::attach(config);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Returns `true` if the monitor is enabled.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Watches the bytes received by the serial port for the
+/// [`BREAK_SEQUENCE`], and runs the monitor once it is complete. Called by the
+/// serial interrupt handler, with the interrupted `frame`.
+pub fn receive(byte: u8, frame: &Frame) {
+    if !is_enabled() {
+        return;
+    }
+
+    let mut monitor = MONITOR.lock();
+    if monitor.is_break(byte) {
+        monitor.run(Some(frame));
+    }
+}
+
+/// Runs the monitor, if enabled, after a panic. Returns on `continue`.
+pub fn enter_on_panic() {
+    if !is_enabled() {
+        return;
+    }
+
+    interrupt::disable();
+    MONITOR.lock().run(None);
+}
+
+/// Returns the thread whose id or name is `thread`.
+fn find_thread(thread: &str) -> Option<&'static Thread> {
+    let id = thread.parse::<u32>().ok();
+    let mut threads = unsafe { SCHEDULER.peek() }.threads();
+    threads.find(|t| Some(t.id.as_u32()) == id || t.name() == thread)
+}
+
+fn status_name(status: thread::Status) -> &'static str {
+    match status {
+        thread::Status::Running => "running",
+        thread::Status::Ready => "ready",
+        thread::Status::Blocked => "blocked",
+        thread::Status::Dying => "dying",
+    }
+}
+
+/// Parses a decimal number, or a hexadecimal one with a `0x` prefix.
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
@@ -0,0 +1,132 @@
+use core::convert::TryInto;
+
+/// Size of the space reserved for the symbol table.
+const CAPACITY: usize = 512 * 1024;
+
+/// Marks the start of the symbol table.
+const MAGIC: [u8; 8] = *b"KSYMTAB\0";
+
+/// Size of the header: the magic, the link-time address of the table, the
+/// number of symbols, and the offset of the string table.
+const HEADER_SIZE: usize = 24;
+
+/// Size of a symbol entry: its link-time address, its size, and the offset of
+/// its name in the string table.
+const ENTRY_SIZE: usize = 16;
+
+/// Symbol table of the kernel image.
+///
+/// The space is reserved here, and filled in after linking, by
+/// `kernel_symbols::embed()` on the host, with the function symbols of the
+/// kernel ELF:
+///
+/// ```text
+///     +-------------------------------------------+
+///     | magic: "KSYMTAB\0"                        |
+///     | address of this table at link time: u64   |
+///     | number of symbols: u32                    |
+///     | offset of the string table: u32           |
+///     +-------------------------------------------+
+///     | address: u64 | size: u32 | name: u32      |  sorted by address
+///     |                    ...                    |
+///     +-------------------------------------------+
+///     | NUL-terminated names                      |
+///     +-------------------------------------------+
+/// ```
+///
+/// All numbers are little-endian. The table holds only the magic if no
+/// symbols were embedded.
+///
+/// It must not be all zeros, or it would be placed in a `NOBITS` section,
+/// which takes no space in the image to fill in.
+#[used]
+#[link_section = ".ksymtab"]
+static mut SYMBOL_TABLE: [u8; CAPACITY] = {
+    let mut table = [0; CAPACITY];
+    let mut i = 0;
+    while i < MAGIC.len() {
+        table[i] = MAGIC[i];
+        i += 1;
+    }
+    table
+};
+
+/// A function symbol, and where an address is within it.
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol {
+    /// Name of the function.
+    pub name: &'static str,
+
+    /// Offset of the address from the start of the function.
+    pub offset: u64,
+}
+
+/// Returns the function containing `address`, if the symbol table has it.
+pub fn lookup(address: u64) -> Option<Symbol> {
+    let table = unsafe {
+        core::slice::from_raw_parts(core::ptr::addr_of!(SYMBOL_TABLE).cast::<u8>(), CAPACITY)
+    };
+
+    if table[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+
+    let link_address = read_u64(table, 8);
+    let count = read_u32(table, 16) as usize;
+    let strings = read_u32(table, 20) as usize;
+    if count == 0 || HEADER_SIZE + count * ENTRY_SIZE > strings || strings > CAPACITY {
+        return None;
+    }
+
+    // The bootloader loads the kernel at an offset from its link-time
+    // addresses.
+    let load_offset = (table.as_ptr() as u64).wrapping_sub(link_address);
+    let address = address.wrapping_sub(load_offset);
+
+    let entry = |index: usize| &table[HEADER_SIZE + index * ENTRY_SIZE..][..ENTRY_SIZE];
+
+    // Find the last symbol starting at or before `address`.
+    let index = match binary_search(count, |index| read_u64(entry(index), 0) <= address) {
+        0 => return None,
+        index => index - 1,
+    };
+
+    let entry = entry(index);
+    let start = read_u64(entry, 0);
+    let size = read_u32(entry, 8) as u64;
+    if size != 0 && address - start >= size {
+        return None;
+    }
+
+    let name = &table[strings..][read_u32(entry, 12) as usize..];
+    let end = name.iter().position(|&b| b == 0)?;
+    let name = core::str::from_utf8(&name[..end]).ok()?;
+
+    Some(Symbol {
+        name,
+        offset: address - start,
+    })
+}
+
+/// Returns the number of leading indices in `0..count` for which `is_before`
+/// holds, given that it holds for a prefix of them.
+fn binary_search(count: usize, is_before: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if is_before(mid) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
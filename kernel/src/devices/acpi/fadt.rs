@@ -0,0 +1,201 @@
+use core::convert::TryInto;
+
+use super::SdtHeader;
+
+/// Fixed ACPI Description Table (FADT).
+///
+/// Describes the fixed hardware of ACPI: the power management registers, the
+/// reset register, and where the DSDT is.
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    header: &'static SdtHeader,
+}
+
+/// Address space of a [`GenericAddress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    Memory,
+    Io,
+    PciConfiguration,
+    Other(u8),
+}
+
+/// Generic Address Structure: where a register is, in any address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub space: AddressSpace,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    /// Size of the structure in the tables.
+    const LENGTH: usize = 12;
+
+    fn parse(bytes: &[u8]) -> Self {
+        Self {
+            space: match bytes[0] {
+                0 => AddressSpace::Memory,
+                1 => AddressSpace::Io,
+                2 => AddressSpace::PciConfiguration,
+                space => AddressSpace::Other(space),
+            },
+            bit_width: bytes[1],
+            bit_offset: bytes[2],
+            address: u64::from_le_bytes(bytes[4..12].try_into().unwrap()),
+        }
+    }
+}
+
+impl Fadt {
+    /// Signature of the table.
+    pub const SIGNATURE: &'static [u8; 4] = b"FACP";
+
+    /// Flag set if the reset register is supported.
+    const RESET_REG_SUP: u32 = 1 << 10;
+
+    // Offsets of the fields in the table, including the header.
+    const DSDT: usize = 40;
+    const SMI_CMD: usize = 48;
+    const ACPI_ENABLE: usize = 52;
+    const PM1A_CNT_BLK: usize = 64;
+    const PM1B_CNT_BLK: usize = 68;
+    const FLAGS: usize = 112;
+    const RESET_REG: usize = 116;
+    const RESET_VALUE: usize = 128;
+    const X_DSDT: usize = 140;
+
+    pub(super) fn new(header: &'static SdtHeader) -> Self {
+        Self { header }
+    }
+
+    /// Returns the Differentiated System Description Table, the AML code
+    /// describing the rest of the hardware.
+    pub fn dsdt(&self) -> Option<&'static SdtHeader> {
+        let address = self
+            .read_u64(Self::X_DSDT)
+            .filter(|&address| address != 0)
+            .or_else(|| self.read_u32(Self::DSDT).map(u64::from))
+            .filter(|&address| address != 0)?;
+
+        let header = unsafe { super::table_at(address) };
+        if header.is_valid() {
+            Some(header)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the port to write [`Fadt::acpi_enable()`] to, to hand the
+    /// power management registers over from the firmware to us, or `None`
+    /// if they are always ours.
+    pub fn smi_command_port(&self) -> Option<u16> {
+        self.read_u32(Self::SMI_CMD)
+            .filter(|&port| port != 0)
+            .map(|port| port as u16)
+    }
+
+    /// Returns the value to write to [`Fadt::smi_command_port()`] to enter
+    /// ACPI mode.
+    pub fn acpi_enable(&self) -> u8 {
+        self.read_u8(Self::ACPI_ENABLE).unwrap_or(0)
+    }
+
+    /// Returns the ports of the PM1a and PM1b control registers. PM1b is
+    /// optional.
+    pub fn pm1_control_ports(&self) -> (Option<u16>, Option<u16>) {
+        let port = |offset| {
+            self.read_u32(offset)
+                .filter(|&port| port != 0)
+                .map(|port| port as u16)
+        };
+        (port(Self::PM1A_CNT_BLK), port(Self::PM1B_CNT_BLK))
+    }
+
+    /// Returns the register to write to reset the machine, and the value to
+    /// write, if it is supported.
+    pub fn reset_register(&self) -> Option<(GenericAddress, u8)> {
+        let flags = self.read_u32(Self::FLAGS)?;
+        if flags & Self::RESET_REG_SUP == 0 {
+            return None;
+        }
+
+        let bytes = self.bytes();
+        let register = bytes.get(Self::RESET_REG..Self::RESET_REG + GenericAddress::LENGTH)?;
+        let value = *bytes.get(Self::RESET_VALUE)?;
+        Some((GenericAddress::parse(register), value))
+    }
+
+    /// Returns the values of `SLP_TYPa` and `SLP_TYPb` which put the machine
+    /// in the S5 (soft off) state.
+    ///
+    /// They are defined by the `\_S5` package in the DSDT. Without an AML
+    /// interpreter, we look for its name, and read the package right after.
+    pub fn s5_sleep_types(&self) -> Option<(u8, u8)> {
+        const NAME_OP: u8 = 0x08;
+        const PACKAGE_OP: u8 = 0x12;
+        const BYTE_PREFIX: u8 = 0x0a;
+
+        let aml = self.dsdt()?.body();
+        let position = (0..aml.len().saturating_sub(4)).find(|&i| {
+            &aml[i..i + 4] == b"_S5_"
+                && (i >= 1 && aml[i - 1] == NAME_OP
+                    || i >= 2 && aml[i - 2] == NAME_OP && aml[i - 1] == b'\\')
+        })?;
+
+        let mut package = aml.get(position + 4..)?;
+        if *package.first()? != PACKAGE_OP {
+            return None;
+        }
+
+        // Skip the package length, whose top two bits count the bytes
+        // following the first, and the number of elements.
+        let length_bytes = (*package.get(1)? >> 6) as usize + 1;
+        package = package.get(1 + length_bytes + 1..)?;
+
+        let mut element = || {
+            let value = match *package.first()? {
+                BYTE_PREFIX => {
+                    let value = *package.get(1)?;
+                    package = &package[2..];
+                    value
+                }
+                value => {
+                    package = &package[1..];
+                    value
+                }
+            };
+            Some(value)
+        };
+
+        let slp_typa = element()?;
+        let slp_typb = element()?;
+        Some((slp_typa, slp_typb))
+    }
+
+    /// Returns the whole table, including the header.
+    fn bytes(&self) -> &'static [u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                (self.header as *const SdtHeader).cast::<u8>(),
+                self.header.length(),
+            )
+        }
+    }
+
+    fn read_u8(&self, offset: usize) -> Option<u8> {
+        self.bytes().get(offset).copied()
+    }
+
+    fn read_u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.bytes().get(offset..offset + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Reads a field of ACPI 2.0, which older tables are too short to have.
+    fn read_u64(&self, offset: usize) -> Option<u64> {
+        let bytes = self.bytes().get(offset..offset + 8)?;
+        Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+}
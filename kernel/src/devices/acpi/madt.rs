@@ -0,0 +1,119 @@
+use core::convert::TryInto;
+
+use crate::threads::addr::PhysAddr;
+
+use super::SdtHeader;
+
+/// Multiple APIC Description Table (MADT).
+///
+/// Describes the local APICs of the processors, I/O APICs, and how the legacy
+/// ISA interrupts are wired to the I/O APIC inputs.
+#[derive(Debug, Clone, Copy)]
+pub struct Madt {
+    header: &'static SdtHeader,
+}
+
+/// An entry of the [`Madt`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MadtEntry {
+    /// A processor and its local APIC.
+    LocalApic {
+        processor_id: u8,
+        apic_id: u8,
+        flags: u32,
+    },
+
+    /// An I/O APIC, whose inputs start at global system interrupt `gsi_base`.
+    IoApic { id: u8, address: u32, gsi_base: u32 },
+
+    /// ISA interrupt `source` is delivered to global system interrupt `gsi`,
+    /// not to the identically numbered one.
+    InterruptSourceOverride {
+        bus: u8,
+        source: u8,
+        gsi: u32,
+        flags: u16,
+    },
+
+    /// 64-bit address of the local APIC, overriding the 32-bit one.
+    LocalApicAddressOverride { address: u64 },
+
+    /// An entry we do not care about.
+    Unknown { kind: u8 },
+}
+
+impl Madt {
+    /// Signature of the table.
+    pub const SIGNATURE: &'static [u8; 4] = b"APIC";
+
+    pub(super) fn new(header: &'static SdtHeader) -> Self {
+        Self { header }
+    }
+
+    /// Returns the physical address of the local APIC, or `None` if the
+    /// table is too short to tell.
+    pub fn local_apic_address(&self) -> Option<PhysAddr> {
+        let address = self
+            .entries()
+            .find_map(|entry| match entry {
+                MadtEntry::LocalApicAddressOverride { address } => Some(address),
+                _ => None,
+            })
+            .or_else(|| {
+                let bytes = self.header.body().get(0..4)?;
+                Some(read_u32(bytes, 0) as u64)
+            })?;
+
+        Some(PhysAddr::new(address))
+    }
+
+    /// Returns the entries of the table.
+    pub fn entries(&self) -> impl Iterator<Item = MadtEntry> {
+        // Entries follow the local APIC address and the flags.
+        let mut entries = self.header.body().get(8..).unwrap_or(&[]);
+
+        core::iter::from_fn(move || {
+            if entries.len() < 2 {
+                return None;
+            }
+
+            // Stop at an entry running past the end of the table: the rest
+            // cannot be found.
+            let (kind, length) = (entries[0], entries[1] as usize);
+            let (entry, rest) = match entries.get(..length) {
+                Some(entry) if length >= 2 => (entry, &entries[length..]),
+                _ => return None,
+            };
+            entries = rest;
+
+            // Entries of known kinds are read only if they have their length.
+            Some(match (kind, length) {
+                (0, 8) => MadtEntry::LocalApic {
+                    processor_id: entry[2],
+                    apic_id: entry[3],
+                    flags: read_u32(entry, 4),
+                },
+                (1, 12) => MadtEntry::IoApic {
+                    id: entry[2],
+                    address: read_u32(entry, 4),
+                    gsi_base: read_u32(entry, 8),
+                },
+                (2, 10) => MadtEntry::InterruptSourceOverride {
+                    bus: entry[2],
+                    source: entry[3],
+                    gsi: read_u32(entry, 4),
+                    flags: u16::from_le_bytes([entry[8], entry[9]]),
+                },
+                (5, 12) => MadtEntry::LocalApicAddressOverride {
+                    address: u64::from_le_bytes(entry[4..12].try_into().unwrap()),
+                },
+                (kind, _) => MadtEntry::Unknown { kind },
+            })
+        })
+    }
+}
+
+/// Reads a little-endian `u32` at `offset` of `bytes`.
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
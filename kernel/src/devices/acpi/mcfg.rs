@@ -0,0 +1,53 @@
+use core::convert::TryInto;
+
+use crate::threads::addr::PhysAddr;
+
+use super::SdtHeader;
+
+/// PCI Express Memory-mapped Configuration table (MCFG).
+///
+/// Tells where the configuration space of the PCI buses is mapped in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Mcfg {
+    header: &'static SdtHeader,
+}
+
+/// An entry of the [`Mcfg`]: the configuration space of buses `start_bus` to
+/// `end_bus` of PCI segment `segment` is mapped from `base`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct McfgEntry {
+    pub base: PhysAddr,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl Mcfg {
+    /// Signature of the table.
+    pub const SIGNATURE: &'static [u8; 4] = b"MCFG";
+
+    /// Size of an entry.
+    const ENTRY_LENGTH: usize = 16;
+
+    pub(super) fn new(header: &'static SdtHeader) -> Self {
+        Self { header }
+    }
+
+    /// Returns the entries of the table.
+    pub fn entries(&self) -> impl Iterator<Item = McfgEntry> {
+        // Entries follow 8 reserved bytes.
+        self.header
+            .body()
+            .get(8..)
+            .unwrap_or(&[])
+            .as_chunks::<{ Self::ENTRY_LENGTH }>()
+            .0
+            .iter()
+            .map(|entry| McfgEntry {
+                base: PhysAddr::new(u64::from_le_bytes(entry[0..8].try_into().unwrap())),
+                segment: u16::from_le_bytes([entry[8], entry[9]]),
+                start_bus: entry[10],
+                end_bus: entry[11],
+            })
+    }
+}
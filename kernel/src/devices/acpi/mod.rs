@@ -0,0 +1,316 @@
+mod fadt;
+mod madt;
+mod mcfg;
+
+pub use self::{
+    fadt::{AddressSpace, Fadt, GenericAddress},
+    madt::{Madt, MadtEntry},
+    mcfg::{Mcfg, McfgEntry},
+};
+
+use crate::threads::{
+    addr::{is_mapped, ptov, PhysAddr},
+    interrupt,
+};
+
+/// Number of times to poll for the firmware to enter ACPI mode.
+const POLL_LIMIT: usize = 100_000;
+
+bitflags::bitflags! {
+    struct Pm1Control: u16 {
+        /// Power management events raise SCIs: ACPI mode is on.
+        const SCI_EN = 1 << 0;
+
+        /// Enters the sleep state given by `SLP_TYP`, bits 10 to 12.
+        const SLP_EN = 1 << 13;
+    }
+}
+
+/// Bit offset of `SLP_TYP` in the PM1 control registers.
+const SLP_TYP_SHIFT: u16 = 10;
+
+/// Root System Description Pointer.
+///
+/// The bootloader finds it for us in the BIOS memory area, and hands its
+/// physical address over in `BootInfo`.
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+
+    // Fields below are only present from ACPI 2.0 (`revision >= 2`).
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+impl Rsdp {
+    const SIGNATURE: &'static [u8; 8] = b"RSD PTR ";
+
+    /// Size of the ACPI 1.0 part of the structure.
+    const V1_LENGTH: usize = 20;
+}
+
+/// System Description Table header, which precedes every ACPI table.
+#[derive(Debug)]
+#[repr(C, packed)]
+pub struct SdtHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: u32,
+    creator_revision: u32,
+}
+
+impl SdtHeader {
+    /// Returns the 4-byte signature of the table, e.g. `APIC` for MADT.
+    pub fn signature(&self) -> [u8; 4] {
+        self.signature
+    }
+
+    /// Returns the length of the table in bytes, including the header.
+    pub fn length(&self) -> usize {
+        self.length as usize
+    }
+
+    /// Returns the table contents following the header.
+    pub fn body(&self) -> &'static [u8] {
+        let header_length = core::mem::size_of::<Self>();
+        unsafe {
+            core::slice::from_raw_parts(
+                (self as *const Self).cast::<u8>().add(header_length),
+                self.length() - header_length,
+            )
+        }
+    }
+
+    /// Returns `true` if the bytes of the table sum up to zero.
+    fn is_valid(&self) -> bool {
+        let bytes = unsafe {
+            core::slice::from_raw_parts((self as *const Self).cast::<u8>(), self.length())
+        };
+        checksum(bytes)
+    }
+}
+
+/// Root table which points to the other tables: RSDT holds 32-bit
+/// pointers, and XSDT (ACPI 2.0) holds 64-bit pointers.
+#[derive(Debug, Clone, Copy)]
+enum RootTable {
+    Rsdt(&'static SdtHeader),
+    Xsdt(&'static SdtHeader),
+}
+
+/// ACPI tables provided by the firmware.
+///
+/// We only read the static tables, so there is no AML interpreter here.
+#[derive(Debug)]
+pub struct Acpi {
+    root: Option<RootTable>,
+}
+
+impl Acpi {
+    /// Creates an empty set of tables.
+    pub const fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Locates the root table from the RSDP at physical address `rsdp_addr`.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that
+    /// `rsdp_addr` points to a valid RSDP.
+    pub unsafe fn init(&mut self, rsdp_addr: u64) {
+        let rsdp = &*ptov(PhysAddr::new(rsdp_addr)).as_ptr::<Rsdp>();
+
+        let v1 = core::slice::from_raw_parts((rsdp as *const Rsdp).cast::<u8>(), Rsdp::V1_LENGTH);
+        if &rsdp.signature != Rsdp::SIGNATURE || !checksum(v1) {
+            return;
+        }
+
+        let root = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+            RootTable::Xsdt(table_at(rsdp.xsdt_address))
+        } else {
+            RootTable::Rsdt(table_at(rsdp.rsdt_address as u64))
+        };
+
+        let (RootTable::Rsdt(header) | RootTable::Xsdt(header)) = root;
+        if header.is_valid() {
+            self.root = Some(root);
+        }
+    }
+
+    /// Returns `true` if the tables were found.
+    pub fn is_present(&self) -> bool {
+        self.root.is_some()
+    }
+
+    /// Returns all valid tables pointed by the root table.
+    pub fn tables(&self) -> impl Iterator<Item = &'static SdtHeader> {
+        let (body, entry_size) = match self.root {
+            Some(RootTable::Rsdt(header)) => (header.body(), 4),
+            Some(RootTable::Xsdt(header)) => (header.body(), 8),
+            None => (&[][..], 4),
+        };
+
+        body.chunks_exact(entry_size)
+            .map(|entry| {
+                let mut address = [0; 8];
+                address[..entry.len()].copy_from_slice(entry);
+                unsafe { table_at(u64::from_le_bytes(address)) }
+            })
+            .filter(|header| header.is_valid())
+    }
+
+    /// Returns the first valid table with `signature`.
+    pub fn find_table(&self, signature: &[u8; 4]) -> Option<&'static SdtHeader> {
+        self.tables()
+            .find(|header| &header.signature() == signature)
+    }
+
+    /// Returns the Fixed ACPI Description Table, describing the power
+    /// management hardware.
+    pub fn fadt(&self) -> Option<Fadt> {
+        self.find_table(Fadt::SIGNATURE).map(Fadt::new)
+    }
+
+    /// Returns the Multiple APIC Description Table, describing the interrupt
+    /// controllers.
+    pub fn madt(&self) -> Option<Madt> {
+        self.find_table(Madt::SIGNATURE).map(Madt::new)
+    }
+
+    /// Returns the PCI Express Memory-mapped Configuration table, if the PCI
+    /// configuration space is mapped in memory.
+    pub fn mcfg(&self) -> Option<Mcfg> {
+        self.find_table(Mcfg::SIGNATURE).map(Mcfg::new)
+    }
+}
+
+impl Default for Acpi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Global ACPI tables.
+pub static ACPI: interrupt::Mutex<Acpi> = interrupt::Mutex::new(Acpi::new());
+
+/// Locates the ACPI tables handed over by the bootloader, if any.
+pub fn init(boot_info: &bootloader_api::BootInfo) {
+    if let Some(&rsdp_addr) = boot_info.rsdp_addr.as_ref() {
+        unsafe {
+            ACPI.lock().init(rsdp_addr);
+        }
+    }
+}
+
+/// Powers off the machine, by putting it in the S5 sleep state through the
+/// PM1 control registers.
+///
+/// Returns if the tables do not tell how, or the machine did not go off.
+pub fn power_off() {
+    let fadt = match ACPI.lock().fadt() {
+        Some(fadt) => fadt,
+        None => return,
+    };
+    let (slp_typa, slp_typb) = match fadt.s5_sleep_types() {
+        Some(sleep_types) => sleep_types,
+        None => return,
+    };
+    let (pm1a, pm1b) = match fadt.pm1_control_ports() {
+        (Some(pm1a), pm1b) => (pm1a, pm1b),
+        (None, _) => return,
+    };
+
+    enable(&fadt, pm1a);
+
+    for (port, slp_typ) in [(Some(pm1a), slp_typa), (pm1b, slp_typb)] {
+        if let Some(port) = port {
+            let mut port = x86_64::instructions::port::Port::<u16>::new(port);
+            unsafe {
+                let control = port.read() & !(0b111 << SLP_TYP_SHIFT);
+                let slp_typ = (slp_typ as u16 & 0b111) << SLP_TYP_SHIFT;
+                port.write(control | slp_typ | Pm1Control::SLP_EN.bits());
+            }
+        }
+    }
+}
+
+/// Resets the machine through the reset register.
+///
+/// Returns if the tables do not have one we can write, or the machine did not
+/// reset.
+pub fn reset() {
+    let (register, value) = match ACPI.lock().fadt().and_then(|fadt| fadt.reset_register()) {
+        Some(reset) => reset,
+        None => return,
+    };
+
+    match register.space {
+        AddressSpace::Io => {
+            let mut port = x86_64::instructions::port::Port::<u8>::new(register.address as u16);
+            unsafe { port.write(value) }
+        }
+        AddressSpace::Memory => {
+            let vaddr = ptov(PhysAddr::new(register.address));
+            if is_mapped(vaddr) {
+                unsafe { core::ptr::write_volatile(vaddr.as_mut_ptr::<u8>(), value) }
+            }
+        }
+        AddressSpace::PciConfiguration => {
+            // The address holds the device, function and offset, on bus 0.
+            let device = (register.address >> 32) as u8;
+            let function = (register.address >> 16) as u8;
+            let offset = register.address as u16;
+            crate::devices::pci::Address::new(0, device, function).write_u8(offset, value);
+        }
+        AddressSpace::Other(_) => {}
+    }
+}
+
+/// Hands the power management registers over from the firmware to us, if
+/// they are not already, and waits for the firmware to let go.
+fn enable(fadt: &Fadt, pm1a: u16) {
+    let mut port = x86_64::instructions::port::Port::<u16>::new(pm1a);
+    let is_enabled = |port: &mut x86_64::instructions::port::Port<u16>| {
+        Pm1Control::from_bits_truncate(unsafe { port.read() }).contains(Pm1Control::SCI_EN)
+    };
+
+    if is_enabled(&mut port) {
+        return;
+    }
+    if let (Some(smi_command_port), acpi_enable) = (fadt.smi_command_port(), fadt.acpi_enable()) {
+        if acpi_enable != 0 {
+            let mut smi_command = x86_64::instructions::port::Port::<u8>::new(smi_command_port);
+            unsafe { smi_command.write(acpi_enable) }
+        }
+    }
+    for _ in 0..POLL_LIMIT {
+        if is_enabled(&mut port) {
+            break;
+        }
+    }
+}
+
+/// Returns the table header at physical address `addr`.
+///
+/// # Safety
+/// This function is unsafe because the caller must ensure that `addr` points
+/// to a table.
+unsafe fn table_at(addr: u64) -> &'static SdtHeader {
+    &*ptov(PhysAddr::new(addr)).as_ptr::<SdtHeader>()
+}
+
+/// Returns `true` if `bytes` sum up to zero, modulo 256.
+fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
@@ -0,0 +1,314 @@
+extern crate alloc;
+
+mod partition;
+
+pub use self::partition::scan as scan_partitions;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{console, threads::interrupt};
+
+/// Size of a sector, in bytes.
+pub const SECTOR_SIZE: usize = 512;
+
+/// Maximum number of block devices, disks and partitions together.
+const MAX_BLOCKS: usize = 32;
+
+/// Maximum length of the name of a block device.
+const NAME_LENGTH: usize = 16;
+
+/// A device made of sectors, read and written whole, like a disk.
+pub trait BlockDevice: Sync {
+    /// Returns the size of a sector, in bytes.
+    fn sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    /// Returns the number of sectors of the device.
+    fn sector_count(&self) -> u32;
+
+    /// Reads sector `sector` into `buffer`. Panics if the device fails.
+    ///
+    /// It may sleep until the device is done, so it must not be called in an
+    /// interrupt handler.
+    fn read(&self, sector: u32, buffer: &mut [u8; SECTOR_SIZE]);
+
+    /// Writes `buffer` to sector `sector`. Panics if the device fails.
+    ///
+    /// It may sleep until the device is done, so it must not be called in an
+    /// interrupt handler.
+    fn write(&self, sector: u32, buffer: &[u8; SECTOR_SIZE]);
+}
+
+/// Kinds of block devices, like Pintos' `enum block_type`.
+///
+/// The first four are also the roles the kernel gives block devices: which
+/// one holds the file system, and so on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// The kernel image, for the bootloader.
+    Kernel,
+
+    /// The file system.
+    Filesys,
+
+    /// Scratch space, to copy files in and out of the file system.
+    Scratch,
+
+    /// Swap space, for virtual memory.
+    Swap,
+
+    /// A whole disk, with or without partitions.
+    Raw,
+
+    /// A partition of another operating system.
+    Foreign,
+}
+
+impl Kind {
+    /// Kinds which are roles.
+    pub const ROLES: [Kind; 4] = [Kind::Kernel, Kind::Filesys, Kind::Scratch, Kind::Swap];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Kernel => "kernel",
+            Kind::Filesys => "filesys",
+            Kind::Scratch => "scratch",
+            Kind::Swap => "swap",
+            Kind::Raw => "raw",
+            Kind::Foreign => "foreign",
+        }
+    }
+
+    fn role_index(&self) -> Option<usize> {
+        Self::ROLES.iter().position(|role| role == self)
+    }
+}
+
+/// A registered block device: a disk or a partition, with its name and
+/// kind, and statistics of the sectors read and written.
+pub struct Block {
+    name: [u8; NAME_LENGTH],
+    kind: Kind,
+    device: alloc::boxed::Box<dyn BlockDevice>,
+
+    read_cnt: AtomicU64,
+    write_cnt: AtomicU64,
+}
+
+impl Block {
+    pub fn name(&self) -> &str {
+        let end = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(NAME_LENGTH);
+        core::str::from_utf8(&self.name[..end]).unwrap_or("")
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// Returns the number of sectors read so far.
+    pub fn read_count(&self) -> u64 {
+        self.read_cnt.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of sectors written so far.
+    pub fn write_count(&self) -> u64 {
+        self.write_cnt.load(Ordering::Relaxed)
+    }
+
+    /// Panics if `sector` is past the end of the device.
+    fn check_sector(&self, sector: u32) {
+        if sector >= self.sector_count() {
+            panic!(
+                "Access past end of device {} (sector={}, size={})",
+                self.name(),
+                sector,
+                self.sector_count()
+            );
+        }
+    }
+}
+
+impl BlockDevice for Block {
+    fn sector_size(&self) -> usize {
+        self.device.sector_size()
+    }
+
+    fn sector_count(&self) -> u32 {
+        self.device.sector_count()
+    }
+
+    fn read(&self, sector: u32, buffer: &mut [u8; SECTOR_SIZE]) {
+        self.check_sector(sector);
+        self.device.read(sector, buffer);
+        self.read_cnt.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn write(&self, sector: u32, buffer: &[u8; SECTOR_SIZE]) {
+        self.check_sector(sector);
+        assert!(
+            self.kind != Kind::Foreign,
+            "{}: writing a foreign partition",
+            self.name()
+        );
+        self.device.write(sector, buffer);
+        self.write_cnt.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl core::fmt::Debug for Block {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Block")
+            .field("name", &self.name())
+            .field("kind", &self.kind)
+            .field("sector_count", &self.sector_count())
+            .finish()
+    }
+}
+
+/// A size in bytes, displayed in the largest unit it has at least one of,
+/// like `16 MiB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl core::fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+        let mut size = self.0;
+        let mut unit = 0;
+        while size >= 1024 && unit + 1 < UNITS.len() {
+            size /= 1024;
+            unit += 1;
+        }
+        write!(f, "{} {}", size, UNITS[unit])
+    }
+}
+
+/// The registered block devices, and the roles given to them.
+pub struct Blocks {
+    blocks: [Option<&'static Block>; MAX_BLOCKS],
+    roles: [Option<&'static Block>; Kind::ROLES.len()],
+}
+
+impl Blocks {
+    pub const fn new() -> Self {
+        Self {
+            blocks: [None; MAX_BLOCKS],
+            roles: [None; Kind::ROLES.len()],
+        }
+    }
+
+    /// Returns the block devices, in the order they were registered.
+    pub fn iter(&self) -> impl Iterator<Item = &'static Block> + '_ {
+        self.blocks.iter().flatten().copied()
+    }
+
+    /// Prints the number of sectors read and written on each block device,
+    /// like Pintos' `block_print_stats()`.
+    pub fn print_stats(&self) {
+        console::with_console(|console| self.write_stats(console))
+            .expect("Failed to write to console");
+    }
+
+    /// Writes the statistics printed by [`Self::print_stats()`] to `out`.
+    pub fn write_stats(&self, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+        for block in self.iter() {
+            writeln!(
+                out,
+                "{} ({}): {} reads, {} writes",
+                block.name(),
+                block.kind.name(),
+                block.read_count(),
+                block.write_count()
+            )?;
+        }
+        Ok(())
+    }
+
+    fn role(&self, role: Kind) -> Option<&'static Block> {
+        self.roles[role.role_index()?]
+    }
+}
+
+impl Default for Blocks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Global block devices.
+pub static BLOCKS: interrupt::Mutex<Blocks> = interrupt::Mutex::new(Blocks::new());
+
+/// Registers `device` as a block device named `name`, of `kind`. Disk
+/// drivers then look for partitions on it, with [`scan_partitions()`].
+///
+/// Panics if too many block devices are registered.
+pub fn register<D: BlockDevice + 'static>(name: &str, kind: Kind, device: D) -> &'static Block {
+    assert!(name.len() < NAME_LENGTH);
+
+    let mut block = Block {
+        name: [0; NAME_LENGTH],
+        kind,
+        device: alloc::boxed::Box::new(device),
+        read_cnt: AtomicU64::new(0),
+        write_cnt: AtomicU64::new(0),
+    };
+    block.name[..name.len()].copy_from_slice(name.as_bytes());
+    let block: &'static Block = alloc::boxed::Box::leak(alloc::boxed::Box::new(block));
+
+    let mut blocks = BLOCKS.lock();
+    let slot = blocks
+        .blocks
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .expect("too many block devices");
+    *slot = Some(block);
+    block
+}
+
+/// Returns the block device named `name`.
+pub fn find(name: &str) -> Option<&'static Block> {
+    let blocks = BLOCKS.lock();
+    let block = blocks.iter().find(|block| block.name() == name);
+    block
+}
+
+/// Returns the block device given `role`, if any.
+pub fn get_role(role: Kind) -> Option<&'static Block> {
+    BLOCKS.lock().role(role)
+}
+
+/// Gives `role` to `block`. Panics if `role` is not one of [`Kind::ROLES`].
+pub fn set_role(role: Kind, block: &'static Block) {
+    let index = role.role_index().expect("not a role");
+    BLOCKS.lock().roles[index] = Some(block);
+}
+
+/// Gives `role` to the block device named `name`, or, if there is no name,
+/// to the first block device of that kind, like Pintos'
+/// `locate_block_device()`.
+///
+/// Panics if there is no block device named `name`.
+pub fn locate(role: Kind, name: Option<&str>) {
+    let block = match name {
+        Some(name) => match find(name) {
+            Some(block) => Some(block),
+            None => panic!("{}: block device not found", name),
+        },
+        None => {
+            let blocks = BLOCKS.lock();
+            let block = blocks.iter().find(|block| block.kind == role);
+            block
+        }
+    };
+
+    if let Some(block) = block {
+        log::info!("{}: using {}.", role.name(), block.name());
+        set_role(role, block);
+    }
+}
@@ -0,0 +1,295 @@
+extern crate alloc;
+
+use core::convert::TryInto;
+
+use super::{Block, BlockDevice, ByteSize, Kind, SECTOR_SIZE};
+
+/// Maximum number of partition tables followed through extended
+/// partitions, in case they loop.
+const MAX_TABLES: usize = 64;
+
+/// Maximum number of GPT entries looked at.
+const MAX_GPT_ENTRIES: u32 = 128;
+
+/// MBR partition types of extended partitions, which hold more partition
+/// tables.
+const MBR_EXTENDED: [u8; 4] = [0x05, 0x0f, 0x85, 0xc5];
+
+/// MBR partition type of the protective partition of a GPT disk.
+const MBR_GPT_PROTECTIVE: u8 = 0xee;
+
+/// Signature of a GPT header.
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// GPT partition type of Linux swap space,
+/// `0657fd6d-a4ab-43c4-84e5-0933c84b4f4f`, in its mixed-endian on-disk
+/// form.
+const GPT_LINUX_SWAP: [u8; 16] = [
+    0x6d, 0xfd, 0x57, 0x06, 0xab, 0xa4, 0xc4, 0x43, 0x84, 0xe5, 0x09, 0x33, 0xc8, 0x4b, 0x4f, 0x4f,
+];
+
+/// A partition of a block device: `size` sectors from `start`.
+struct Partition {
+    block: &'static Block,
+    start: u32,
+    size: u32,
+}
+
+impl BlockDevice for Partition {
+    fn sector_count(&self) -> u32 {
+        self.size
+    }
+
+    fn read(&self, sector: u32, buffer: &mut [u8; SECTOR_SIZE]) {
+        self.block.read(self.start + sector, buffer);
+    }
+
+    fn write(&self, sector: u32, buffer: &[u8; SECTOR_SIZE]) {
+        self.block.write(self.start + sector, buffer);
+    }
+}
+
+/// Scans `block`, a whole disk, for partitions, and registers each as a
+/// block device of its own: `hda1`, `hda2` and so on.
+///
+/// Reads an MBR partition table, following extended partitions, or the GPT
+/// it protects.
+pub fn scan(block: &'static Block) {
+    let mut scanner = Scanner {
+        block,
+        part_nr: 0,
+        table_cnt: 0,
+    };
+    scanner.read_mbr(0, 0);
+
+    if scanner.part_nr == 0 {
+        log::info!("{}: Device contains no partitions.", block.name());
+    }
+}
+
+/// Partitions found so far on a block device.
+struct Scanner {
+    block: &'static Block,
+    part_nr: u32,
+    table_cnt: usize,
+}
+
+impl Scanner {
+    /// Reads the MBR partition table in `sector`. `primary_extended_sector`
+    /// is the sector of the extended partition of the primary table, which
+    /// the logical partitions are relative to, or 0 for the primary table.
+    fn read_mbr(&mut self, sector: u32, primary_extended_sector: u32) {
+        let name = self.block.name();
+
+        self.table_cnt += 1;
+        if self.table_cnt > MAX_TABLES {
+            log::warn!("{}: Too many partition tables.", name);
+            return;
+        }
+
+        if sector >= self.block.sector_count() {
+            log::warn!(
+                "{}: Partition table at sector {} past end of device.",
+                name,
+                sector
+            );
+            return;
+        }
+
+        let mut table = [0; SECTOR_SIZE];
+        self.block.read(sector, &mut table);
+        if table[510..512] != [0x55, 0xaa] {
+            if primary_extended_sector == 0 {
+                log::info!("{}: Invalid partition table signature.", name);
+            } else {
+                log::warn!(
+                    "{}: Invalid extended partition table in sector {}.",
+                    name,
+                    sector
+                );
+            }
+            return;
+        }
+
+        let entries = table[446..510].as_chunks::<16>().0;
+        if sector == 0 && entries.iter().any(|entry| entry[4] == MBR_GPT_PROTECTIVE) {
+            self.read_gpt();
+            return;
+        }
+
+        for entry in entries {
+            let kind = entry[4];
+            let offset = u32::from_le_bytes(entry[8..12].try_into().unwrap());
+            let size = u32::from_le_bytes(entry[12..16].try_into().unwrap());
+
+            if size == 0 || kind == 0 {
+                // Unused entry.
+            } else if MBR_EXTENDED.contains(&kind) {
+                log::debug!("{}: Extended partition in sector {}.", name, sector);
+
+                // The first extended partition is relative to the start of
+                // the disk, and the ones it chains to, to the first.
+                if sector == 0 {
+                    self.read_mbr(offset, offset);
+                } else {
+                    self.read_mbr(
+                        offset.wrapping_add(primary_extended_sector),
+                        primary_extended_sector,
+                    );
+                }
+            } else {
+                self.part_nr += 1;
+                self.found(
+                    mbr_kind(kind),
+                    sector as u64 + offset as u64,
+                    size as u64,
+                    &alloc::format!("{} ({:02x})", mbr_type_name(kind), kind),
+                );
+            }
+        }
+    }
+
+    /// Reads the GUID partition table, whose header is in sector 1.
+    fn read_gpt(&mut self) {
+        let name = self.block.name();
+
+        if self.block.sector_count() < 2 {
+            return;
+        }
+        let mut header = [0; SECTOR_SIZE];
+        self.block.read(1, &mut header);
+        if &header[0..8] != GPT_SIGNATURE {
+            log::warn!("{}: Invalid GPT header signature.", name);
+            return;
+        }
+
+        let entries_sector = u64::from_le_bytes(header[72..80].try_into().unwrap());
+        let entry_cnt = u32::from_le_bytes(header[80..84].try_into().unwrap());
+        let entry_size = u32::from_le_bytes(header[84..88].try_into().unwrap()) as usize;
+        if !(128..=SECTOR_SIZE).contains(&entry_size) || !SECTOR_SIZE.is_multiple_of(entry_size) {
+            log::warn!("{}: Unsupported GPT entry size {}.", name, entry_size);
+            return;
+        }
+
+        let entries_per_sector = SECTOR_SIZE / entry_size;
+        let mut sector = [0; SECTOR_SIZE];
+        for index in 0..entry_cnt.min(MAX_GPT_ENTRIES) as usize {
+            let sector_no = entries_sector + (index / entries_per_sector) as u64;
+            if sector_no >= self.block.sector_count() as u64 {
+                log::warn!("{}: GPT entries past end of device.", name);
+                return;
+            }
+            if index % entries_per_sector == 0 {
+                self.block.read(sector_no as u32, &mut sector);
+            }
+
+            let offset = index % entries_per_sector * entry_size;
+            let entry = &sector[offset..offset + entry_size];
+            let kind: [u8; 16] = entry[0..16].try_into().unwrap();
+            if kind == [0; 16] {
+                continue;
+            }
+
+            let first = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+            let last = u64::from_le_bytes(entry[40..48].try_into().unwrap());
+            if last < first {
+                log::warn!(
+                    "{}{}: Partition ends before it starts (sectors {} to {}).",
+                    name,
+                    index + 1,
+                    first,
+                    last
+                );
+                continue;
+            }
+            let (kind, description) = if kind == GPT_LINUX_SWAP {
+                (Kind::Swap, "Linux swap (GPT)")
+            } else {
+                (Kind::Foreign, "GPT")
+            };
+
+            // GPT partitions keep their numbers, unused entries included.
+            self.part_nr = index as u32 + 1;
+            self.found(kind, first, (last - first).saturating_add(1), description);
+        }
+    }
+
+    /// Registers partition `part_nr`, of `kind`, which is `size` sectors from
+    /// `start`. `description` tells its type, for the log.
+    fn found(&self, kind: Kind, start: u64, size: u64, description: &str) {
+        let block = self.block;
+        let end = block.sector_count() as u64;
+        if start >= end {
+            log::warn!(
+                "{}{}: Partition starts past end of device (sector {}).",
+                block.name(),
+                self.part_nr,
+                start
+            );
+            return;
+        }
+        if size == 0
+            || start
+                .checked_add(size)
+                .is_none_or(|part_end| part_end > end)
+        {
+            log::warn!(
+                "{}{}: Partition ends past end of device (sector {}).",
+                block.name(),
+                self.part_nr,
+                start.saturating_add(size)
+            );
+            return;
+        }
+
+        let name = alloc::format!("{}{}", block.name(), self.part_nr);
+        let partition = Partition {
+            block,
+            start: start as u32,
+            size: size as u32,
+        };
+        super::register(&name, kind, partition);
+
+        log::info!(
+            "{}: {} sectors ({}), {}, partition {} of {}.",
+            name,
+            size,
+            ByteSize(size * SECTOR_SIZE as u64),
+            description,
+            self.part_nr,
+            block.name()
+        );
+    }
+}
+
+/// Returns the kind of a partition of MBR partition type `mbr_type`. Pintos
+/// uses types 0x20 to 0x23.
+fn mbr_kind(mbr_type: u8) -> Kind {
+    match mbr_type {
+        0x20 => Kind::Kernel,
+        0x21 => Kind::Filesys,
+        0x22 => Kind::Scratch,
+        0x23 => Kind::Swap,
+        _ => Kind::Foreign,
+    }
+}
+
+/// Returns a name for MBR partition type `mbr_type`.
+fn mbr_type_name(mbr_type: u8) -> &'static str {
+    match mbr_type {
+        0x01 => "FAT12",
+        0x04 | 0x06 => "FAT16",
+        0x07 => "NTFS",
+        0x0b | 0x0c => "FAT32",
+        0x0e => "FAT16 (LBA)",
+        0x20 => "Pintos kernel",
+        0x21 => "Pintos file system",
+        0x22 => "Pintos scratch",
+        0x23 => "Pintos swap",
+        0x82 => "Linux swap",
+        0x83 => "Linux",
+        0xa5 => "FreeBSD",
+        0xef => "EFI system",
+        _ => "Unknown",
+    }
+}
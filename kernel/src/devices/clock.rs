@@ -0,0 +1,238 @@
+use core::{convert::TryFrom, time::Duration};
+
+use crate::{println, threads::interrupt};
+
+use super::{
+    pit::Pit,
+    timer::{self, TIMER},
+};
+
+/// Number of nanoseconds in a second.
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// A measurement of the monotonic clock, with nanosecond resolution.
+///
+/// Works like `std::time::Instant`, and pairs with [`Duration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    /// The latest instant, some 584 years after boot.
+    pub const MAX: Instant = Instant(u64::MAX);
+
+    /// Returns the current instant.
+    pub fn now() -> Self {
+        Self(CLOCK.lock().nanos())
+    }
+
+    /// Returns the time elapsed since the OS booted.
+    pub fn since_boot(&self) -> Duration {
+        Duration::from_nanos(self.0)
+    }
+
+    /// Returns the time elapsed from `earlier` to `self`, or zero if
+    /// `earlier` is later than `self`.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    /// Returns the time elapsed since `self`.
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// Returns `self + duration`, or `None` if it overflows.
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        u64::try_from(duration.as_nanos())
+            .ok()
+            .and_then(|nanos| self.0.checked_add(nanos))
+            .map(Self)
+    }
+}
+
+impl core::ops::Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        self.checked_add(rhs)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl core::ops::Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+/// Source of the monotonic clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    /// The PIT counter read back, on top of the timer ticks.
+    Pit,
+
+    /// The invariant time-stamp counter of the CPU, running at `frequency`
+    /// Hz. It reads `tsc_base` at `nanos_base`.
+    Tsc {
+        frequency: u64,
+        tsc_base: u64,
+        nanos_base: u64,
+    },
+}
+
+/// The monotonic clock.
+///
+/// It starts out reading the PIT counter, which is slow to access but needs
+/// no calibration. [`Clock::use_tsc()`] switches to the time-stamp counter
+/// if it ticks at a constant rate, which makes reading the clock as cheap as
+/// a single instruction.
+pub struct Clock {
+    source: Source,
+
+    /// The last reading, to keep the clock from going backwards.
+    last_nanos: u64,
+}
+
+impl Clock {
+    /// Creates a new [`Clock`], reading the PIT.
+    pub const fn new() -> Self {
+        Self {
+            source: Source::Pit,
+            last_nanos: 0,
+        }
+    }
+
+    /// Returns the number of nanoseconds elapsed since the OS booted.
+    pub fn nanos(&mut self) -> u64 {
+        let nanos = match self.source {
+            Source::Pit => {
+                let counts = TIMER.lock().counts();
+                (counts as u128 * NANOS_PER_SEC as u128 / Pit::INPUT_FREQUENCY as u128) as u64
+            }
+            Source::Tsc {
+                frequency,
+                tsc_base,
+                nanos_base,
+            } => {
+                let cycles = rdtsc().wrapping_sub(tsc_base);
+                nanos_base + (cycles as u128 * NANOS_PER_SEC as u128 / frequency as u128) as u64
+            }
+        };
+
+        self.last_nanos = self.last_nanos.max(nanos);
+        self.last_nanos
+    }
+
+    /// Returns `true` if the clock counts the timer ticks, which stop while
+    /// interrupts are off.
+    pub fn counts_ticks(&self) -> bool {
+        self.source == Source::Pit
+    }
+
+    /// Switches to the time-stamp counter, running at `frequency` Hz.
+    pub fn use_tsc(&mut self, frequency: u64) {
+        let nanos_base = self.nanos();
+        self.source = Source::Tsc {
+            frequency,
+            tsc_base: rdtsc(),
+            nanos_base,
+        };
+    }
+
+    /// Prints the clock source.
+    pub fn print_source(&self) {
+        match self.source {
+            Source::Pit => println!("Clock: 8254 PIT at {} Hz.", Pit::INPUT_FREQUENCY),
+            Source::Tsc { frequency, .. } => println!("Clock: TSC at {frequency} Hz."),
+        }
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Global monotonic clock.
+pub static CLOCK: interrupt::Mutex<Clock> = interrupt::Mutex::new(Clock::new());
+
+/// Calibrates the monotonic clock, like Pintos' `timer_calibrate()`: if the
+/// CPU reports the time-stamp counter to be invariant, its frequency is
+/// measured against the timer ticks, and the clock switches to it.
+///
+/// Interrupts must be turned on, since this function waits for the timer to
+/// tick.
+pub fn init() {
+    /// Number of timer ticks to measure the TSC frequency over.
+    const CALIBRATION_TICKS: usize = 10;
+
+    assert!(interrupt::are_enabled());
+
+    println!("Calibrating clock...");
+
+    if has_invariant_tsc() {
+        // Start measuring right after a timer tick.
+        let start = wait_for_next_tick();
+        let tsc_start = rdtsc();
+
+        while TIMER.lock().elapsed(start) < CALIBRATION_TICKS {
+            core::hint::spin_loop();
+        }
+        let tsc_end = rdtsc();
+
+        let frequency = (tsc_end - tsc_start) * timer::FREQUENCY as u64 / CALIBRATION_TICKS as u64;
+        CLOCK.lock().use_tsc(frequency);
+    }
+
+    CLOCK.lock().print_source();
+}
+
+/// Busy-waits until `deadline`.
+///
+/// Works with interrupts off too: if the clock counts the timer ticks, which
+/// then stop, the timer's counter is followed instead.
+pub fn delay_until(deadline: Instant) {
+    if interrupt::are_disabled() && CLOCK.lock().counts_ticks() {
+        let duration = deadline.duration_since(Instant::now());
+        TIMER.lock().delay(duration);
+        return;
+    }
+
+    while Instant::now() < deadline {
+        core::hint::spin_loop();
+    }
+}
+
+/// Busy-waits until the timer ticks, and returns the new tick.
+fn wait_for_next_tick() -> usize {
+    let start = TIMER.lock().ticks();
+    loop {
+        let ticks = TIMER.lock().ticks();
+        if ticks != start {
+            return ticks;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Returns `true` if the time-stamp counter runs at a constant rate, in all
+/// power states.
+fn has_invariant_tsc() -> bool {
+    const CPUID_TSC: u32 = 1 << 4;
+    const CPUID_INVARIANT_TSC: u32 = 1 << 8;
+
+    let has_tsc = core::arch::x86_64::__cpuid(1).edx & CPUID_TSC != 0;
+    let max_extended_leaf = core::arch::x86_64::__cpuid(0x8000_0000).eax;
+
+    has_tsc
+        && max_extended_leaf >= 0x8000_0007
+        && core::arch::x86_64::__cpuid(0x8000_0007).edx & CPUID_INVARIANT_TSC != 0
+}
+
+/// Reads the time-stamp counter.
+pub fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
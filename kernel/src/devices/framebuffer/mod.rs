@@ -0,0 +1,377 @@
+mod font;
+
+use crate::{console, threads::Mutex};
+
+/// An RGB color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+/// The 16 colors of ANSI escape sequences: black, red, green, yellow, blue,
+/// magenta, cyan and white, then their bright versions.
+const PALETTE: [Color; 16] = [
+    Color::new(0x00, 0x00, 0x00),
+    Color::new(0xaa, 0x00, 0x00),
+    Color::new(0x00, 0xaa, 0x00),
+    Color::new(0xaa, 0x55, 0x00),
+    Color::new(0x00, 0x00, 0xaa),
+    Color::new(0xaa, 0x00, 0xaa),
+    Color::new(0x00, 0xaa, 0xaa),
+    Color::new(0xaa, 0xaa, 0xaa),
+    Color::new(0x55, 0x55, 0x55),
+    Color::new(0xff, 0x55, 0x55),
+    Color::new(0x55, 0xff, 0x55),
+    Color::new(0xff, 0xff, 0x55),
+    Color::new(0x55, 0x55, 0xff),
+    Color::new(0xff, 0x55, 0xff),
+    Color::new(0x55, 0xff, 0xff),
+    Color::new(0xff, 0xff, 0xff),
+];
+
+const DEFAULT_FOREGROUND: usize = 7;
+const DEFAULT_BACKGROUND: usize = 0;
+
+/// State of the parser of ANSI escape sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    /// Not in an escape sequence.
+    None,
+
+    /// After `ESC`.
+    Start,
+
+    /// After `ESC [`, reading the parameters.
+    Csi,
+}
+
+/// Text console drawn on the framebuffer the bootloader set up.
+///
+/// Characters are drawn with an 8x8 bitmap font, scaled up to fit 80
+/// columns if the screen is wide enough. The screen scrolls up once the
+/// cursor goes past the last row.
+///
+/// Understands a few ANSI escape sequences:
+/// - `ESC [ n m`: sets colors, for `n` in 0 (reset), 1 (bright), 30-37, 39
+///   (foreground), 40-47, 49 (background), 90-97 and 100-107 (bright).
+/// - `ESC [ 2 J`: clears the screen.
+/// - `ESC [ row ; column H`: moves the cursor, 1-based.
+/// - `ESC [ K`: clears to the end of the line.
+pub struct TextConsole {
+    buffer: &'static mut [u8],
+    info: bootloader_api::info::FrameBufferInfo,
+
+    /// Number of screen pixels per font pixel.
+    scale: usize,
+
+    columns: usize,
+    rows: usize,
+
+    /// Position of the cursor, in characters.
+    column: usize,
+    row: usize,
+
+    foreground: usize,
+    background: usize,
+    bright: bool,
+
+    escape: Escape,
+    params: [u16; TextConsole::MAX_PARAMS],
+    param_count: usize,
+}
+
+impl TextConsole {
+    /// Maximum number of parameters of an escape sequence.
+    const MAX_PARAMS: usize = 4;
+
+    /// Creates a text console on `framebuffer`, and clears the screen.
+    pub fn new(framebuffer: bootloader_api::info::FrameBuffer) -> Self {
+        let info = framebuffer.info();
+        let scale = (info.width / (80 * font::WIDTH)).max(1);
+
+        let mut console = Self {
+            buffer: framebuffer.into_buffer(),
+            info,
+            scale,
+            columns: info.width / (font::WIDTH * scale),
+            rows: info.height / (font::HEIGHT * scale),
+            column: 0,
+            row: 0,
+            foreground: DEFAULT_FOREGROUND,
+            background: DEFAULT_BACKGROUND,
+            bright: false,
+            escape: Escape::None,
+            params: [0; Self::MAX_PARAMS],
+            param_count: 0,
+        };
+        console.clear();
+        console
+    }
+
+    /// Returns the size of the screen, in characters: columns, then rows.
+    pub fn size(&self) -> (usize, usize) {
+        (self.columns, self.rows)
+    }
+
+    /// Returns the position of the cursor: column, then row.
+    pub fn position(&self) -> (usize, usize) {
+        (self.column, self.row)
+    }
+
+    /// Writes `c`, interpreting control characters and escape sequences.
+    pub fn write_char(&mut self, c: char) {
+        self.draw_cursor(false);
+
+        match self.escape {
+            Escape::None => self.put(c),
+            Escape::Start => {
+                if c == '[' {
+                    self.escape = Escape::Csi;
+                    self.params = [0; Self::MAX_PARAMS];
+                    self.param_count = 0;
+                } else {
+                    self.escape = Escape::None;
+                }
+            }
+            Escape::Csi => self.csi(c),
+        }
+
+        self.draw_cursor(true);
+    }
+
+    /// Clears the screen, and moves the cursor home.
+    pub fn clear(&mut self) {
+        let background = PALETTE[self.background];
+        for y in 0..self.info.height {
+            for x in 0..self.info.width {
+                self.put_pixel(x, y, background);
+            }
+        }
+        self.column = 0;
+        self.row = 0;
+    }
+
+    /// Puts `c` at the cursor, outside of escape sequences.
+    fn put(&mut self, c: char) {
+        match c {
+            '\x1b' => self.escape = Escape::Start,
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            '\x08' => self.column = self.column.saturating_sub(1),
+            '\t' => {
+                for _ in 0..8 - self.column % 8 {
+                    self.put(' ');
+                }
+            }
+            _ => {
+                if self.column == self.columns {
+                    self.new_line();
+                }
+                self.draw_glyph(c);
+                self.column += 1;
+            }
+        }
+    }
+
+    /// Handles `c` in an escape sequence after `ESC [`.
+    fn csi(&mut self, c: char) {
+        match c {
+            '0'..='9' => {
+                let index = self.param_count.min(Self::MAX_PARAMS - 1);
+                let digit = c as u16 - '0' as u16;
+                self.params[index] = self.params[index].saturating_mul(10).saturating_add(digit);
+                return;
+            }
+            ';' => {
+                self.param_count += 1;
+                return;
+            }
+            _ => {}
+        }
+
+        let count = (self.param_count + 1).min(Self::MAX_PARAMS);
+        let params = self.params;
+        match c {
+            'm' => {
+                for &param in &params[..count] {
+                    self.select_graphic_rendition(param);
+                }
+            }
+            'J' if params[0] == 2 => self.clear(),
+            'H' => {
+                self.row = (params[0].max(1) as usize - 1).min(self.rows - 1);
+                self.column = (params[1].max(1) as usize - 1).min(self.columns - 1);
+            }
+            'K' => {
+                for column in self.column..self.columns {
+                    self.fill_cell(column, self.row, PALETTE[self.background]);
+                }
+            }
+            _ => {}
+        }
+        self.escape = Escape::None;
+    }
+
+    fn select_graphic_rendition(&mut self, param: u16) {
+        let param = param as usize;
+        match param {
+            0 => {
+                self.foreground = DEFAULT_FOREGROUND;
+                self.background = DEFAULT_BACKGROUND;
+                self.bright = false;
+            }
+            1 => self.bright = true,
+            22 => self.bright = false,
+            30..=37 => self.foreground = param - 30,
+            39 => self.foreground = DEFAULT_FOREGROUND,
+            40..=47 => self.background = param - 40,
+            49 => self.background = DEFAULT_BACKGROUND,
+            90..=97 => self.foreground = param - 90 + 8,
+            100..=107 => self.background = param - 100 + 8,
+            _ => {}
+        }
+    }
+
+    /// Moves the cursor to the start of the next line, scrolling the screen
+    /// up if it is on the last one.
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+
+        let line_bytes = font::HEIGHT * self.scale * self.info.stride * self.info.bytes_per_pixel;
+        let screen_bytes = self.rows * line_bytes;
+        self.buffer.copy_within(line_bytes..screen_bytes, 0);
+        for column in 0..self.columns {
+            self.fill_cell(column, self.row, PALETTE[self.background]);
+        }
+    }
+
+    fn foreground_color(&self) -> Color {
+        if self.bright && self.foreground < 8 {
+            PALETTE[self.foreground + 8]
+        } else {
+            PALETTE[self.foreground]
+        }
+    }
+
+    /// Draws `c` at the cursor.
+    fn draw_glyph(&mut self, c: char) {
+        let glyph = font::glyph(c);
+        let (foreground, background) = (self.foreground_color(), PALETTE[self.background]);
+        let (left, top) = self.cell_origin(self.column, self.row);
+
+        for (y, row) in glyph.iter().enumerate() {
+            for x in 0..font::WIDTH {
+                let color = if row & (1 << x) != 0 {
+                    foreground
+                } else {
+                    background
+                };
+                self.fill_font_pixel(left + x * self.scale, top + y * self.scale, color);
+            }
+        }
+    }
+
+    /// Draws the cursor as an underline, or erases it.
+    fn draw_cursor(&mut self, visible: bool) {
+        if self.escape != Escape::None || self.column == self.columns {
+            return;
+        }
+
+        let color = if visible {
+            self.foreground_color()
+        } else {
+            PALETTE[self.background]
+        };
+        let (left, top) = self.cell_origin(self.column, self.row);
+        for x in 0..font::WIDTH {
+            self.fill_font_pixel(
+                left + x * self.scale,
+                top + (font::HEIGHT - 1) * self.scale,
+                color,
+            );
+        }
+    }
+
+    fn fill_cell(&mut self, column: usize, row: usize, color: Color) {
+        let (left, top) = self.cell_origin(column, row);
+        for y in 0..font::HEIGHT {
+            for x in 0..font::WIDTH {
+                self.fill_font_pixel(left + x * self.scale, top + y * self.scale, color);
+            }
+        }
+    }
+
+    /// Returns the top-left pixel of the character cell at `column`, `row`.
+    fn cell_origin(&self, column: usize, row: usize) -> (usize, usize) {
+        (
+            column * font::WIDTH * self.scale,
+            row * font::HEIGHT * self.scale,
+        )
+    }
+
+    /// Fills the square of screen pixels making up one font pixel.
+    fn fill_font_pixel(&mut self, x: usize, y: usize, color: Color) {
+        for dy in 0..self.scale {
+            for dx in 0..self.scale {
+                self.put_pixel(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Color) {
+        let offset = (y * self.info.stride + x) * self.info.bytes_per_pixel;
+        let pixel = &mut self.buffer[offset..offset + self.info.bytes_per_pixel];
+
+        match self.info.pixel_format {
+            bootloader_api::info::PixelFormat::Rgb => {
+                pixel[..3].copy_from_slice(&[color.r, color.g, color.b])
+            }
+            bootloader_api::info::PixelFormat::Bgr => {
+                pixel[..3].copy_from_slice(&[color.b, color.g, color.r])
+            }
+            _ => {
+                let gray = ((color.r as u16 + color.g as u16 + color.b as u16) / 3) as u8;
+                pixel.fill(gray);
+            }
+        }
+    }
+}
+
+impl console::Sink for Mutex<Option<TextConsole>> {
+    fn write_str(&self, s: &str) {
+        if let Some(console) = self.lock().as_mut() {
+            for c in s.chars() {
+                console.write_char(c);
+            }
+        }
+    }
+}
+
+/// Text console on the framebuffer, if there is one.
+pub static FRAMEBUFFER: Mutex<Option<TextConsole>> = Mutex::new(None);
+
+/// Sets up a text console on `framebuffer`, and adds it to the console's
+/// sinks as `"framebuffer"`.
+pub fn init(framebuffer: bootloader_api::info::FrameBuffer) {
+    let console = TextConsole::new(framebuffer);
+    let (columns, rows) = console.size();
+
+    *FRAMEBUFFER.lock() = Some(console);
+    console::CONSOLE
+        .lock()
+        .register("framebuffer", &FRAMEBUFFER);
+
+    log::info!("Framebuffer console: {columns}x{rows} characters.");
+}
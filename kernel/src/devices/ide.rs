@@ -0,0 +1,491 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::threads::{interrupt, Mutex, Semaphore};
+
+use super::{
+    block::{self, BlockDevice, ByteSize, SECTOR_SIZE},
+    timer,
+};
+
+/// Number of sectors addressable with 28-bit LBA.
+const LBA28_SECTORS: u32 = 1 << 28;
+
+/// Maximum number of disks: two on each channel.
+const MAX_DISKS: usize = 4;
+
+/// Offsets of the command block registers, from the base port of a channel.
+const REG_DATA: u16 = 0;
+const REG_ERROR: u16 = 1;
+const REG_NSECT: u16 = 2;
+const REG_LBAL: u16 = 3;
+const REG_LBAM: u16 = 4;
+const REG_LBAH: u16 = 5;
+const REG_DEVICE: u16 = 6;
+const REG_STATUS: u16 = 7;
+const REG_COMMAND: u16 = 7;
+
+/// Offset of the control block register, from the base port of a channel:
+/// alternate status when read, which does not acknowledge interrupts, and
+/// device control when written.
+const REG_CONTROL: u16 = 0x206;
+
+/// Commands.
+const CMD_IDENTIFY_DEVICE: u8 = 0xec;
+const CMD_READ_SECTOR_RETRY: u8 = 0x20;
+const CMD_WRITE_SECTOR_RETRY: u8 = 0x30;
+
+bitflags::bitflags! {
+    struct Status: u8 {
+        /// The device is busy.
+        const BSY = 0x80;
+
+        /// The device is ready.
+        const DRDY = 0x40;
+
+        /// The device wants data to be transferred.
+        const DRQ = 0x08;
+    }
+
+    struct DeviceControl: u8 {
+        /// Software reset of both devices of the channel.
+        const SRST = 0x04;
+    }
+
+    struct DeviceSelect: u8 {
+        /// Bits which must be set.
+        const MBS = 0xa0;
+
+        /// Addresses sectors by LBA, rather than CHS.
+        const LBA = 0x40;
+
+        /// Selects the slave device.
+        const DEV = 0x10;
+    }
+}
+
+/// An ATA channel, to which up to two devices, the master and the slave, are
+/// attached.
+struct Channel {
+    name: &'static str,
+
+    /// Base port of the command block registers.
+    base: u16,
+
+    irq: u8,
+
+    /// Held for the duration of a command, since only one may run at a time.
+    lock: Mutex<()>,
+
+    /// Whether an interrupt is expected.
+    expecting_interrupt: AtomicBool,
+
+    /// Upped by the interrupt handler on completion of a command.
+    completion: Semaphore,
+}
+
+/// The two channels of a PC, at their legacy ports and IRQs.
+static CHANNELS: [Channel; 2] = [
+    Channel::new("ide0", 0x1f0, 14),
+    Channel::new("ide1", 0x170, 15),
+];
+
+impl Channel {
+    const fn new(name: &'static str, base: u16, irq: u8) -> Self {
+        Self {
+            name,
+            base,
+            irq,
+            lock: Mutex::new(()),
+            expecting_interrupt: AtomicBool::new(false),
+            completion: Semaphore::new(0),
+        }
+    }
+
+    /// Resets the channel, and returns which devices seem present.
+    ///
+    /// The reset sequence depends on which devices are present, so we start
+    /// by detecting them: a register of a present device keeps what we write
+    /// to it.
+    fn reset(&self) -> [bool; 2] {
+        let mut present = [false; 2];
+        for (device, present) in present.iter_mut().enumerate() {
+            self.select_device(device as u8);
+
+            self.write(REG_NSECT, 0x55);
+            self.write(REG_LBAL, 0xaa);
+
+            self.write(REG_NSECT, 0xaa);
+            self.write(REG_LBAL, 0x55);
+
+            self.write(REG_NSECT, 0x55);
+            self.write(REG_LBAL, 0xaa);
+
+            *present = self.read(REG_NSECT) == 0x55 && self.read(REG_LBAL) == 0xaa;
+        }
+
+        // Issue the soft reset sequence, which selects the master as a side
+        // effect, and enables interrupts.
+        self.write(REG_CONTROL, 0);
+        timer::usleep(10);
+        self.write(REG_CONTROL, DeviceControl::SRST.bits());
+        timer::usleep(10);
+        self.write(REG_CONTROL, 0);
+
+        timer::msleep(150);
+
+        if present[0] {
+            self.select_device(0);
+            self.wait_while_busy();
+        }
+
+        if present[1] {
+            // The slave signals the end of the reset by setting its sector
+            // count and LBA low registers to 1.
+            self.select_device(1);
+            for _ in 0..3000 {
+                if self.read(REG_NSECT) == 1 && self.read(REG_LBAL) == 1 {
+                    break;
+                }
+                timer::msleep(10);
+            }
+            self.wait_while_busy();
+        }
+
+        present
+    }
+
+    /// Checks whether `device` is an ATA disk, from the signature it leaves
+    /// in the registers after a reset. Returns it, and whether the slave may
+    /// be present, if `device` is the master.
+    fn check_device_type(&self, device: u8) -> (bool, bool) {
+        self.select_device(device);
+
+        let error = self.read(REG_ERROR);
+        let lbam = self.read(REG_LBAM);
+        let lbah = self.read(REG_LBAH);
+        let status = Status::from_bits_truncate(self.read(REG_STATUS));
+
+        // An error of 0x81 from the master means that the slave failed its
+        // diagnostics, or is missing.
+        if (error != 1 && (error != 0x81 || device == 1))
+            || !status.contains(Status::DRDY)
+            || status.contains(Status::BSY)
+        {
+            (false, error != 0x81)
+        } else {
+            let is_ata = (lbam == 0 && lbah == 0) || (lbam == 0x3c && lbah == 0xc3);
+            (is_ata, true)
+        }
+    }
+
+    /// Sends IDENTIFY DEVICE to `device`, the disk `name` on channel
+    /// `channel_no`, and returns the disk it describes, or `None` if it does
+    /// not answer.
+    fn identify(&self, channel_no: usize, device: u8, name: &'static str) -> Option<Disk> {
+        let mut id = [0; SECTOR_SIZE];
+        {
+            let _lock = self.lock.lock();
+
+            self.select_device_wait(device);
+            self.issue_pio_command(CMD_IDENTIFY_DEVICE);
+            self.completion.down();
+
+            if !self.wait_while_busy() {
+                return None;
+            }
+            self.input_sector(&mut id);
+        }
+
+        let mut disk = Disk {
+            name,
+            channel: channel_no,
+            device,
+            sector_cnt: u32::from_le_bytes([id[120], id[121], id[122], id[123]]),
+            model: [0; 40],
+            serial: [0; 20],
+        };
+        format_string(&id[54..94], &mut disk.model);
+        format_string(&id[20..40], &mut disk.serial);
+        Some(disk)
+    }
+
+    /// Reads sector `sector` of `device` into `buffer`.
+    fn read_sector(&self, device: u8, name: &str, sector: u32, buffer: &mut [u8; SECTOR_SIZE]) {
+        let _lock = self.lock.lock();
+
+        self.select_sector(device, sector);
+        self.issue_pio_command(CMD_READ_SECTOR_RETRY);
+        self.completion.down();
+        if !self.wait_while_busy() {
+            panic!("{}: disk read failed, sector={}", name, sector);
+        }
+        self.input_sector(buffer);
+    }
+
+    /// Writes `buffer` to sector `sector` of `device`.
+    fn write_sector(&self, device: u8, name: &str, sector: u32, buffer: &[u8; SECTOR_SIZE]) {
+        let _lock = self.lock.lock();
+
+        self.select_sector(device, sector);
+        self.issue_pio_command(CMD_WRITE_SECTOR_RETRY);
+        if !self.wait_while_busy() {
+            panic!("{}: disk write failed, sector={}", name, sector);
+        }
+        self.output_sector(buffer);
+        self.completion.down();
+    }
+
+    /// Selects `device`, and `sector` on it, for the next command.
+    fn select_sector(&self, device: u8, sector: u32) {
+        assert!(sector < LBA28_SECTORS);
+
+        self.select_device_wait(device);
+        self.write(REG_NSECT, 1);
+        self.write(REG_LBAL, sector as u8);
+        self.write(REG_LBAM, (sector >> 8) as u8);
+        self.write(REG_LBAH, (sector >> 16) as u8);
+
+        let select = device_select(device) | DeviceSelect::LBA;
+        self.write(REG_DEVICE, select.bits() | (sector >> 24) as u8);
+    }
+
+    /// Writes `command`, whose completion interrupts. Interrupts must be on.
+    fn issue_pio_command(&self, command: u8) {
+        assert!(interrupt::are_enabled());
+
+        self.expecting_interrupt.store(true, Ordering::SeqCst);
+        self.write(REG_COMMAND, command);
+    }
+
+    /// Reads a sector from the data register into `buffer`.
+    fn input_sector(&self, buffer: &mut [u8; SECTOR_SIZE]) {
+        let mut port = x86_64::instructions::port::Port::<u16>::new(self.base + REG_DATA);
+        for word in buffer.as_chunks_mut::<2>().0 {
+            *word = unsafe { port.read() }.to_le_bytes();
+        }
+    }
+
+    /// Writes `buffer` to the data register.
+    fn output_sector(&self, buffer: &[u8; SECTOR_SIZE]) {
+        let mut port = x86_64::instructions::port::Port::<u16>::new(self.base + REG_DATA);
+        for &word in buffer.as_chunks::<2>().0 {
+            unsafe { port.write(u16::from_le_bytes(word)) };
+        }
+    }
+
+    /// Waits up to 10 ms for the controller to become idle, that is, for the
+    /// BSY and DRQ bits to clear.
+    fn wait_until_idle(&self) {
+        for _ in 0..1000 {
+            if !self.alt_status().intersects(Status::BSY | Status::DRQ) {
+                return;
+            }
+            timer::usleep(10);
+        }
+
+        log::warn!("{}: idle timeout", self.name);
+    }
+
+    /// Waits up to 30 seconds for the selected device to clear BSY. Returns
+    /// `true` if it did, and wants data to be transferred.
+    fn wait_while_busy(&self) -> bool {
+        for i in 0..3000 {
+            if i == 700 {
+                log::info!("{}: busy, waiting...", self.name);
+            }
+
+            let status = self.alt_status();
+            if !status.contains(Status::BSY) {
+                return status.contains(Status::DRQ);
+            }
+            timer::msleep(10);
+        }
+
+        log::warn!("{}: busy timeout", self.name);
+        false
+    }
+
+    /// Selects `device` for the following commands.
+    fn select_device(&self, device: u8) {
+        self.write(REG_DEVICE, device_select(device).bits());
+
+        // Give it 400 ns to respond.
+        self.alt_status();
+        timer::nsleep(400);
+    }
+
+    /// Selects `device`, waiting for the controller to be idle before and
+    /// after.
+    fn select_device_wait(&self, device: u8) {
+        self.wait_until_idle();
+        self.select_device(device);
+        self.wait_until_idle();
+    }
+
+    fn alt_status(&self) -> Status {
+        Status::from_bits_truncate(self.read(REG_CONTROL))
+    }
+
+    fn read(&self, register: u16) -> u8 {
+        let mut port = x86_64::instructions::port::Port::<u8>::new(self.base + register);
+        unsafe { port.read() }
+    }
+
+    fn write(&self, register: u16, value: u8) {
+        let mut port = x86_64::instructions::port::Port::<u8>::new(self.base + register);
+        unsafe { port.write(value) }
+    }
+}
+
+fn device_select(device: u8) -> DeviceSelect {
+    if device == 1 {
+        DeviceSelect::MBS | DeviceSelect::DEV
+    } else {
+        DeviceSelect::MBS
+    }
+}
+
+/// Copies the ATA string `string`, whose bytes are swapped in pairs, to
+/// `out`, padded with spaces.
+fn format_string(string: &[u8], out: &mut [u8]) {
+    for (&[first, second], out) in string
+        .as_chunks::<2>()
+        .0
+        .iter()
+        .zip(out.as_chunks_mut::<2>().0)
+    {
+        *out = [second, first];
+    }
+}
+
+/// An ATA disk, like Pintos' `ide.c`: read and written one sector at a time,
+/// through programmed I/O.
+#[derive(Debug, Clone, Copy)]
+pub struct Disk {
+    /// `hda` to `hdd`.
+    name: &'static str,
+
+    /// Index of the channel in [`CHANNELS`].
+    channel: usize,
+
+    /// 0 for the master, 1 for the slave.
+    device: u8,
+
+    sector_cnt: u32,
+
+    model: [u8; 40],
+    serial: [u8; 20],
+}
+
+impl Disk {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn model(&self) -> &str {
+        core::str::from_utf8(&self.model).unwrap_or("").trim_end()
+    }
+
+    pub fn serial(&self) -> &str {
+        core::str::from_utf8(&self.serial).unwrap_or("").trim_end()
+    }
+}
+
+impl BlockDevice for Disk {
+    fn sector_count(&self) -> u32 {
+        self.sector_cnt
+    }
+
+    fn read(&self, sector: u32, buffer: &mut [u8; SECTOR_SIZE]) {
+        assert!(sector < self.sector_cnt);
+        CHANNELS[self.channel].read_sector(self.device, self.name, sector, buffer);
+    }
+
+    fn write(&self, sector: u32, buffer: &[u8; SECTOR_SIZE]) {
+        assert!(sector < self.sector_cnt);
+        CHANNELS[self.channel].write_sector(self.device, self.name, sector, buffer);
+    }
+}
+
+/// Disks found by [`init()`].
+static DISKS: interrupt::Mutex<[Option<Disk>; MAX_DISKS]> =
+    interrupt::Mutex::new([None; MAX_DISKS]);
+
+/// Names of the disks, by channel and device.
+const DISK_NAMES: [[&str; 2]; 2] = [["hda", "hdb"], ["hdc", "hdd"]];
+
+/// Resets both channels, and identifies the ATA disks attached.
+///
+/// Interrupts must be on, since the disks interrupt on completion, and the
+/// resets take a while to sleep through.
+pub fn init() {
+    assert!(interrupt::are_enabled());
+
+    for (channel_no, channel) in CHANNELS.iter().enumerate() {
+        interrupt::REGISTRY.lock().register(
+            0x20 + channel.irq as usize,
+            ide_interrupt,
+            channel.name,
+        );
+        interrupt::enable_irq(channel.irq);
+
+        let present = channel.reset();
+        if !present.contains(&true) {
+            continue;
+        }
+
+        let (master_is_ata, probe_slave) = channel.check_device_type(0);
+        let slave_is_ata = probe_slave && channel.check_device_type(1).0;
+
+        for (device, is_ata) in [(0, master_is_ata), (1, slave_is_ata)] {
+            if !is_ata {
+                continue;
+            }
+
+            let name = DISK_NAMES[channel_no][device as usize];
+            let disk = match channel.identify(channel_no, device, name) {
+                Some(disk) => disk,
+                None => continue,
+            };
+
+            log::info!(
+                "{}: {} sectors ({}), model \"{}\", serial \"{}\".",
+                name,
+                disk.sector_cnt,
+                ByteSize(disk.sector_cnt as u64 * SECTOR_SIZE as u64),
+                disk.model(),
+                disk.serial()
+            );
+
+            DISKS.lock()[channel_no * 2 + device as usize] = Some(disk);
+            block::scan_partitions(block::register(name, block::Kind::Raw, disk));
+        }
+    }
+}
+
+/// Returns the disks found.
+pub fn disks() -> impl Iterator<Item = Disk> {
+    let disks = *DISKS.lock();
+    IntoIterator::into_iter(disks).flatten()
+}
+
+/// Returns the disk named `name`, like `hda`.
+pub fn find(name: &str) -> Option<Disk> {
+    disks().find(|disk| disk.name == name)
+}
+
+/// ATA interrupt handler: acknowledges the interrupt, and wakes up the
+/// thread waiting for the command to complete.
+fn ide_interrupt(frame: &mut interrupt::Frame) {
+    let irq = frame.vector() - 0x20;
+    for channel in CHANNELS.iter().filter(|channel| channel.irq == irq) {
+        // Reading the status register acknowledges the interrupt.
+        channel.read(REG_STATUS);
+
+        if channel.expecting_interrupt.swap(false, Ordering::SeqCst) {
+            channel.completion.up();
+        } else {
+            log::warn!("{}: unexpected interrupt", channel.name);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    print,
+    threads::{interrupt, Mutex},
+};
+
+use super::{intq::IntQueue, serial};
+
+/// Bytes received from the serial port and the keyboard, waiting to be read.
+static BUFFER: interrupt::Mutex<IntQueue> = interrupt::Mutex::new(IntQueue::new());
+
+/// Whether [`getc()`] returns edited lines rather than raw bytes.
+static COOKED: AtomicBool = AtomicBool::new(false);
+
+/// Line being read in cooked mode.
+static LINE: Mutex<Line> = Mutex::new(Line::new());
+
+/// A line read in cooked mode, and how much of it was returned so far.
+struct Line {
+    buffer: [u8; Line::CAPACITY],
+    length: usize,
+    position: usize,
+}
+
+impl Line {
+    /// Maximum length of a line, including the newline.
+    const CAPACITY: usize = 128;
+
+    const fn new() -> Self {
+        Self {
+            buffer: [0; Self::CAPACITY],
+            length: 0,
+            position: 0,
+        }
+    }
+
+    /// Returns the next byte of the line, reading a new line first if the
+    /// last one was all returned.
+    fn getc(&mut self) -> u8 {
+        if self.position == self.length {
+            self.read();
+        }
+
+        let byte = self.buffer[self.position];
+        self.position += 1;
+        byte
+    }
+
+    /// Reads a line, echoing it back and handling backspace, up to and
+    /// including the newline.
+    fn read(&mut self) {
+        self.length = 0;
+        self.position = 0;
+
+        loop {
+            match getc_raw() {
+                b'\r' | b'\n' => {
+                    print!("\n");
+                    self.buffer[self.length] = b'\n';
+                    self.length += 1;
+                    return;
+                }
+                // Backspace or delete.
+                0x08 | 0x7f => {
+                    if self.length > 0 {
+                        self.length -= 1;
+                        print!("\x08 \x08");
+                    }
+                }
+                // Leave room for the newline.
+                byte if self.length < Self::CAPACITY - 1 => {
+                    self.buffer[self.length] = byte;
+                    self.length += 1;
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        print!("{}", byte as char);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Adds a byte received from the serial port or typed on the keyboard to the
+/// input buffer. Called by their interrupt handlers, which check that the
+/// buffer is not full.
+pub fn putc(byte: u8) {
+    assert!(interrupt::are_disabled());
+    assert!(!BUFFER.lock().is_full());
+
+    BUFFER.lock().putc(byte);
+}
+
+/// Retrieves a byte from the input buffer, sleeping until one is available.
+///
+/// In cooked mode, a whole line is read, echoed and edited first, and then
+/// returned a byte at a time, ending with a newline.
+pub fn getc() -> u8 {
+    if COOKED.load(Ordering::Relaxed) {
+        LINE.lock().getc()
+    } else {
+        getc_raw()
+    }
+}
+
+/// Reads a line into `buffer`, without the newline, and returns its length.
+/// The line is cut short if it does not fit.
+pub fn read_line(buffer: &mut [u8]) -> usize {
+    let mut length = 0;
+    loop {
+        let byte = getc();
+        if byte == b'\n' || byte == b'\r' {
+            return length;
+        }
+
+        if length < buffer.len() {
+            buffer[length] = byte;
+            length += 1;
+        }
+    }
+}
+
+/// Returns `true` if the input buffer is full. Interrupts must be off.
+pub fn is_full() -> bool {
+    assert!(interrupt::are_disabled());
+    BUFFER.lock().is_full()
+}
+
+/// Returns `true` if the input buffer is empty. Interrupts must be off.
+pub fn is_empty() -> bool {
+    assert!(interrupt::are_disabled());
+    BUFFER.lock().is_empty()
+}
+
+/// Switches between cooked mode, where [`getc()`] returns lines edited with
+/// echo and backspace, and raw mode, where it returns bytes as received.
+///
+/// Raw mode is the default.
+pub fn set_cooked(cooked: bool) {
+    COOKED.store(cooked, Ordering::Relaxed);
+}
+
+/// Retrieves a byte as received, sleeping until one is available.
+fn getc_raw() -> u8 {
+    let byte = crate::without_interrupts!(BUFFER.lock().getc());
+
+    // There is room in the buffer now.
+    serial::notify();
+    byte
+}
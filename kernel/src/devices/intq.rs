@@ -0,0 +1,113 @@
+use core::ptr::NonNull;
+
+use crate::threads::{
+    interrupt,
+    thread::{self, Thread},
+    SCHEDULER,
+};
+
+/// "Interrupt queue", a circular buffer of bytes shared between kernel
+/// threads and external interrupt handlers, like Pintos' `intq`.
+///
+/// It must be accessed with interrupts off, so it is usually kept in an
+/// [`interrupt::Mutex`]. An interrupt handler must check that the queue is
+/// not empty before reading, or not full before writing, since it cannot
+/// sleep. A kernel thread sleeps until it can go on.
+///
+/// At most one thread may wait on each end of the queue at a time.
+pub struct IntQueue {
+    buffer: [u8; Self::SIZE],
+
+    /// Index of the next byte to write.
+    head: usize,
+
+    /// Index of the next byte to read.
+    tail: usize,
+
+    /// Thread waiting for the queue to become not full.
+    not_full: Option<NonNull<Thread>>,
+
+    /// Thread waiting for the queue to become not empty.
+    not_empty: Option<NonNull<Thread>>,
+}
+
+impl IntQueue {
+    /// Capacity of the queue, plus one.
+    pub const SIZE: usize = 64;
+
+    /// Creates an empty [`IntQueue`].
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; Self::SIZE],
+            head: 0,
+            tail: 0,
+            not_full: None,
+            not_empty: None,
+        }
+    }
+
+    /// Returns `true` if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        assert!(interrupt::are_disabled());
+        self.head == self.tail
+    }
+
+    /// Returns `true` if the queue is full.
+    pub fn is_full(&self) -> bool {
+        assert!(interrupt::are_disabled());
+        next(self.head) == self.tail
+    }
+
+    /// Removes a byte from the queue and returns it. If the queue is empty,
+    /// sleeps until a byte is added.
+    pub fn getc(&mut self) -> u8 {
+        while self.is_empty() {
+            assert!(!interrupt::is_external_handler_context());
+            Self::wait(&mut self.not_empty);
+        }
+
+        let byte = self.buffer[self.tail];
+        self.tail = next(self.tail);
+        Self::signal(&mut self.not_full);
+        byte
+    }
+
+    /// Adds `byte` to the end of the queue. If the queue is full, sleeps
+    /// until a byte is removed.
+    pub fn putc(&mut self, byte: u8) {
+        while self.is_full() {
+            assert!(!interrupt::is_external_handler_context());
+            Self::wait(&mut self.not_full);
+        }
+
+        self.buffer[self.head] = byte;
+        self.head = next(self.head);
+        Self::signal(&mut self.not_empty);
+    }
+
+    /// Puts the current thread to sleep, as the `waiter` of the queue.
+    fn wait(waiter: &mut Option<NonNull<Thread>>) {
+        assert!(waiter.is_none());
+
+        *waiter = Some(NonNull::from(&mut *thread::current_thread()));
+        SCHEDULER.lock().block_current_thread();
+    }
+
+    /// Wakes up the `waiter` of the queue, if any.
+    fn signal(waiter: &mut Option<NonNull<Thread>>) {
+        if let Some(thread) = waiter.take() {
+            SCHEDULER.lock().unblock(unsafe { &mut *thread.as_ptr() });
+        }
+    }
+}
+
+impl Default for IntQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the position after `pos` in the buffer.
+fn next(pos: usize) -> usize {
+    (pos + 1) % IntQueue::SIZE
+}
@@ -0,0 +1,380 @@
+use crate::{println, threads::interrupt};
+
+use super::input;
+
+/// Data port of the 8042 keyboard controller.
+const DATA_PORT: u16 = 0x60;
+
+/// Status register, when read, and command register, when written.
+const STATUS_PORT: u16 = 0x64;
+
+/// IRQ line of the keyboard.
+const IRQ: u8 = 1;
+
+/// Number of times to poll the controller before giving up on it.
+const POLL_LIMIT: usize = 100_000;
+
+/// Prefix of the scan codes of extended keys.
+const EXTENDED: u8 = 0xe0;
+
+/// Bit set in the scan code of a key release.
+const RELEASE: u8 = 0x80;
+
+bitflags::bitflags! {
+    struct Status: u8 {
+        /// A byte is waiting to be read from the data port.
+        const OUTPUT_FULL = 0b0000_0001;
+
+        /// The controller has not taken the last byte written yet.
+        const INPUT_FULL = 0b0000_0010;
+    }
+
+    struct Configuration: u8 {
+        /// Interrupts on IRQ 1 when a byte is received from the keyboard.
+        const KEYBOARD_INTERRUPT = 0b0000_0001;
+
+        /// Stops the keyboard clock, disabling the keyboard.
+        const KEYBOARD_DISABLED = 0b0001_0000;
+
+        /// Translates the keyboard's scan codes to scan code set 1.
+        const TRANSLATION = 0b0100_0000;
+    }
+}
+
+/// Commands of the 8042 controller.
+const READ_CONFIGURATION: u8 = 0x20;
+const WRITE_CONFIGURATION: u8 = 0x60;
+
+/// A key, as translated from its scan code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A key producing a character, as modified by shift, ctrl and caps lock.
+    Char(u8),
+
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+
+    /// F1 to F12.
+    Function(u8),
+
+    Shift,
+    Ctrl,
+    Alt,
+    CapsLock,
+
+    /// Any other key, by scan code, with 0xe0 in the high byte if it is an
+    /// extended key.
+    Other(u16),
+}
+
+/// A key pressed or released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub pressed: bool,
+}
+
+/// Keys which produce the same character whether shifted or not. Letters are
+/// lowercased unless shift or caps lock is on, but not both.
+const INVARIANT_KEYMAP: &[(u8, &[u8])] = &[
+    (0x01, b"\x1b"),
+    (0x0e, b"\x08"),
+    (0x0f, b"\tQWERTYUIOP"),
+    (0x1c, b"\r"),
+    (0x1e, b"ASDFGHJKL"),
+    (0x2c, b"ZXCVBNM"),
+    (0x37, b"*"),
+    (0x39, b" "),
+];
+
+/// Characters of the keys when shift is not held.
+const UNSHIFTED_KEYMAP: &[(u8, &[u8])] = &[
+    (0x02, b"1234567890-="),
+    (0x1a, b"[]"),
+    (0x27, b";'`"),
+    (0x2b, b"\\"),
+    (0x33, b",./"),
+];
+
+/// Characters of the keys when shift is held.
+const SHIFTED_KEYMAP: &[(u8, &[u8])] = &[
+    (0x02, b"!@#$%^&*()_+"),
+    (0x1a, b"{}"),
+    (0x27, b":\"~"),
+    (0x2b, b"|"),
+    (0x33, b"<>?"),
+];
+
+/// Looks up the character of scan code `code` in `keymap`.
+fn map_key(keymap: &[(u8, &[u8])], code: u8) -> Option<u8> {
+    keymap.iter().find_map(|&(first, chars)| {
+        let index = code.checked_sub(first)? as usize;
+        chars.get(index).copied()
+    })
+}
+
+/// PS/2 keyboard, like Pintos' `kbd.c`.
+///
+/// It translates scan code set 1, which the 8042 controller translates the
+/// keyboard's scan codes to, into [`KeyEvent`]s.
+pub struct Keyboard {
+    left_shift: bool,
+    right_shift: bool,
+    left_ctrl: bool,
+    right_ctrl: bool,
+    left_alt: bool,
+    right_alt: bool,
+    caps_lock: bool,
+
+    /// Whether the last byte was [`EXTENDED`].
+    extended: bool,
+
+    /// Number of keys pressed.
+    key_cnt: usize,
+}
+
+impl Keyboard {
+    /// Creates a new [`Keyboard`], with no keys held.
+    pub const fn new() -> Self {
+        Self {
+            left_shift: false,
+            right_shift: false,
+            left_ctrl: false,
+            right_ctrl: false,
+            left_alt: false,
+            right_alt: false,
+            caps_lock: false,
+            extended: false,
+            key_cnt: 0,
+        }
+    }
+
+    /// Handles a byte received from the keyboard. Returns the key event, if
+    /// the byte completes one.
+    pub fn receive(&mut self, byte: u8) -> Option<KeyEvent> {
+        if byte == EXTENDED {
+            self.extended = true;
+            return None;
+        }
+
+        let extended = core::mem::replace(&mut self.extended, false);
+        let pressed = byte & RELEASE == 0;
+        let code = byte & !RELEASE;
+
+        let key = if extended {
+            self.extended_key(code, pressed)
+        } else {
+            self.key(code, pressed)
+        };
+
+        if pressed {
+            self.key_cnt += 1;
+        }
+        Some(KeyEvent { key, pressed })
+    }
+
+    /// Translates the scan code `code` of a non-extended key.
+    fn key(&mut self, code: u8, pressed: bool) -> Key {
+        match code {
+            0x2a => {
+                self.left_shift = pressed;
+                return Key::Shift;
+            }
+            0x36 => {
+                self.right_shift = pressed;
+                return Key::Shift;
+            }
+            0x1d => {
+                self.left_ctrl = pressed;
+                return Key::Ctrl;
+            }
+            0x38 => {
+                self.left_alt = pressed;
+                return Key::Alt;
+            }
+            0x3a => {
+                if pressed {
+                    self.caps_lock = !self.caps_lock;
+                }
+                return Key::CapsLock;
+            }
+            0x3b..=0x44 => return Key::Function(code - 0x3b + 1),
+            0x57 | 0x58 => return Key::Function(code - 0x57 + 11),
+            _ => {}
+        }
+
+        let shift = self.left_shift || self.right_shift;
+        let ctrl = self.left_ctrl || self.right_ctrl;
+
+        if let Some(c) = map_key(INVARIANT_KEYMAP, code) {
+            let c = if ctrl && c.is_ascii_uppercase() {
+                // Ctrl-A is 0x01, and so on.
+                c - b'@'
+            } else if c.is_ascii_uppercase() && shift == self.caps_lock {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            };
+            return Key::Char(c);
+        }
+
+        let keymap = if shift {
+            SHIFTED_KEYMAP
+        } else {
+            UNSHIFTED_KEYMAP
+        };
+        match map_key(keymap, code) {
+            Some(c) => Key::Char(c),
+            None => Key::Other(code as u16),
+        }
+    }
+
+    /// Translates the scan code `code` of an extended key.
+    fn extended_key(&mut self, code: u8, pressed: bool) -> Key {
+        match code {
+            0x1d => {
+                self.right_ctrl = pressed;
+                Key::Ctrl
+            }
+            0x38 => {
+                self.right_alt = pressed;
+                Key::Alt
+            }
+            0x1c => Key::Char(b'\r'),
+            0x35 => Key::Char(b'/'),
+            0x53 => Key::Char(0x7f),
+            0x48 => Key::Up,
+            0x50 => Key::Down,
+            0x4b => Key::Left,
+            0x4d => Key::Right,
+            0x47 => Key::Home,
+            0x4f => Key::End,
+            0x49 => Key::PageUp,
+            0x51 => Key::PageDown,
+            0x52 => Key::Insert,
+            _ => Key::Other(((EXTENDED as u16) << 8) | code as u16),
+        }
+    }
+
+    /// Prints keyboard statistics.
+    pub fn print_stats(&self) {
+        println!("Keyboard: {} keys pressed", self.key_cnt);
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Global keyboard state.
+pub static KEYBOARD: interrupt::Mutex<Keyboard> = interrupt::Mutex::new(Keyboard::new());
+
+/// Sets up the 8042 controller to interrupt on key strokes, translated to
+/// scan code set 1, and registers the keyboard interrupt.
+pub fn init() {
+    crate::without_interrupts!({
+        // Throw away the key strokes from before we were ready.
+        while status().contains(Status::OUTPUT_FULL) {
+            read_data();
+        }
+
+        if let Some(configuration) = send_command_with_reply(READ_CONFIGURATION) {
+            let mut configuration = Configuration::from_bits_truncate(configuration);
+            configuration.insert(Configuration::KEYBOARD_INTERRUPT | Configuration::TRANSLATION);
+            configuration.remove(Configuration::KEYBOARD_DISABLED);
+
+            send_command(WRITE_CONFIGURATION);
+            write_data(configuration.bits());
+        } else {
+            log::warn!("8042 keyboard controller does not answer.");
+        }
+    });
+
+    interrupt::REGISTRY
+        .lock()
+        .register(0x20 + IRQ as usize, keyboard_interrupt, "8042 Keyboard");
+    interrupt::enable_irq(IRQ);
+}
+
+/// Keyboard interrupt handler: translates the byte received, and adds the
+/// characters typed to the input buffer. The cursor keys are added as the
+/// VT100 escape sequences a serial terminal sends for them.
+fn keyboard_interrupt(_frame: &mut interrupt::Frame) {
+    let event = KEYBOARD.lock().receive(read_data());
+    let key = match event {
+        Some(KeyEvent { key, pressed: true }) => key,
+        _ => return,
+    };
+
+    let sequence: &[u8] = match key {
+        Key::Char(c) => return putc(c),
+        Key::Up => b"\x1b[A",
+        Key::Down => b"\x1b[B",
+        Key::Right => b"\x1b[C",
+        Key::Left => b"\x1b[D",
+        Key::Home => b"\x1b[H",
+        Key::End => b"\x1b[F",
+        _ => b"",
+    };
+    for &byte in sequence {
+        putc(byte);
+    }
+}
+
+/// Adds `byte` to the input buffer, dropping it if the buffer is full.
+fn putc(byte: u8) {
+    if !input::is_full() {
+        input::putc(byte);
+    }
+}
+
+fn status() -> Status {
+    let mut port = x86_64::instructions::port::PortReadOnly::<u8>::new(STATUS_PORT);
+    Status::from_bits_truncate(unsafe { port.read() })
+}
+
+fn read_data() -> u8 {
+    let mut port = x86_64::instructions::port::PortReadOnly::<u8>::new(DATA_PORT);
+    unsafe { port.read() }
+}
+
+/// Waits for the controller to take the last byte written.
+fn wait_input_empty() {
+    for _ in 0..POLL_LIMIT {
+        if !status().contains(Status::INPUT_FULL) {
+            break;
+        }
+    }
+}
+
+fn write_data(data: u8) {
+    wait_input_empty();
+    let mut port = x86_64::instructions::port::PortWriteOnly::<u8>::new(DATA_PORT);
+    unsafe { port.write(data) }
+}
+
+fn send_command(command: u8) {
+    wait_input_empty();
+    let mut port = x86_64::instructions::port::PortWriteOnly::<u8>::new(STATUS_PORT);
+    unsafe { port.write(command) }
+}
+
+/// Sends `command`, and returns the controller's reply, if it comes.
+fn send_command_with_reply(command: u8) -> Option<u8> {
+    send_command(command);
+    for _ in 0..POLL_LIMIT {
+        if status().contains(Status::OUTPUT_FULL) {
+            return Some(read_data());
+        }
+    }
+    None
+}
@@ -1,4 +1,17 @@
+pub mod acpi;
+pub mod block;
+pub mod clock;
+pub mod framebuffer;
+pub mod ide;
+pub mod input;
+pub mod intq;
+pub mod keyboard;
+pub mod pci;
 pub mod pit;
+pub mod rng;
+pub mod rtc;
 pub mod serial;
 pub mod shutdown;
+pub mod speaker;
 pub mod timer;
+pub mod virtio;
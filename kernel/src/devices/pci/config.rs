@@ -0,0 +1,158 @@
+use crate::threads::{
+    addr::{is_mapped, ptov, VirtAddr},
+    interrupt,
+};
+
+use super::Address;
+
+/// Address port of configuration mechanism #1: selects the register which
+/// the data port accesses.
+const CONFIG_ADDRESS_PORT: u16 = 0xcf8;
+
+/// Data port of configuration mechanism #1.
+const CONFIG_DATA_PORT: u16 = 0xcfc;
+
+/// Bit of the address port enabling the access.
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+/// How the configuration space is accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mechanism {
+    /// Configuration mechanism #1: through the address and data ports. Only
+    /// the first 256 bytes of each function are reachable.
+    Port,
+
+    /// Enhanced Configuration Access Mechanism: mapped in memory, from
+    /// `base`, for buses `start_bus` to `end_bus`.
+    Ecam {
+        base: VirtAddr,
+        start_bus: u8,
+        end_bus: u8,
+    },
+}
+
+/// Mechanism in use, chosen by [`init()`].
+static MECHANISM: interrupt::Mutex<Mechanism> = interrupt::Mutex::new(Mechanism::Port);
+
+/// Uses ECAM if the ACPI tables map the configuration space of segment 0 in
+/// memory, and falls back to configuration mechanism #1 otherwise.
+pub(super) fn init() -> Mechanism {
+    let entry = crate::devices::acpi::ACPI.lock().mcfg().and_then(|mcfg| {
+        mcfg.entries()
+            .find(|entry| entry.segment == 0 && entry.start_bus == 0)
+    });
+
+    let mechanism = match entry {
+        Some(entry) if is_mapped(ptov(entry.base)) => Mechanism::Ecam {
+            base: ptov(entry.base),
+            start_bus: entry.start_bus,
+            end_bus: entry.end_bus,
+        },
+        _ => Mechanism::Port,
+    };
+
+    *MECHANISM.lock() = mechanism;
+    mechanism
+}
+
+impl Address {
+    /// Reads the byte at `offset` in the function's configuration space.
+    pub fn read_u8(&self, offset: u16) -> u8 {
+        let shift = (offset & 0b11) * 8;
+        (self.read_u32(offset & !0b11) >> shift) as u8
+    }
+
+    /// Reads the 16-bit register at `offset`.
+    pub fn read_u16(&self, offset: u16) -> u16 {
+        let shift = (offset & 0b10) * 8;
+        (self.read_u32(offset & !0b11) >> shift) as u16
+    }
+
+    /// Reads the register at `offset` in the function's configuration space.
+    pub fn read_u32(&self, offset: u16) -> u32 {
+        let mechanism = *MECHANISM.lock();
+        match self.ecam_register(mechanism, offset) {
+            Some(register) => unsafe { core::ptr::read_volatile(register.as_ptr::<u32>()) },
+            None => crate::without_interrupts!(unsafe {
+                self.select(offset);
+                x86_64::instructions::port::Port::<u32>::new(CONFIG_DATA_PORT).read()
+            }),
+        }
+    }
+
+    /// Writes the byte at `offset`, leaving the rest of its dword alone.
+    pub fn write_u8(&self, offset: u16, value: u8) {
+        let mechanism = *MECHANISM.lock();
+        match self.ecam_register(mechanism, offset) {
+            Some(register) => unsafe {
+                core::ptr::write_volatile(register.as_mut_ptr::<u8>(), value)
+            },
+            None => crate::without_interrupts!(unsafe {
+                self.select(offset);
+                x86_64::instructions::port::Port::<u8>::new(CONFIG_DATA_PORT + (offset & 0b11))
+                    .write(value)
+            }),
+        }
+    }
+
+    /// Writes the 16-bit register at `offset`, leaving the other half of its
+    /// dword alone, since writing back some registers, e.g. the status
+    /// register, clears them.
+    pub fn write_u16(&self, offset: u16, value: u16) {
+        let mechanism = *MECHANISM.lock();
+        match self.ecam_register(mechanism, offset) {
+            Some(register) => unsafe {
+                core::ptr::write_volatile(register.as_mut_ptr::<u16>(), value)
+            },
+            None => crate::without_interrupts!(unsafe {
+                self.select(offset);
+                x86_64::instructions::port::Port::<u16>::new(CONFIG_DATA_PORT + (offset & 0b10))
+                    .write(value)
+            }),
+        }
+    }
+
+    /// Writes the register at `offset` in the function's configuration
+    /// space.
+    pub fn write_u32(&self, offset: u16, value: u32) {
+        let mechanism = *MECHANISM.lock();
+        match self.ecam_register(mechanism, offset) {
+            Some(register) => unsafe {
+                core::ptr::write_volatile(register.as_mut_ptr::<u32>(), value)
+            },
+            None => crate::without_interrupts!(unsafe {
+                self.select(offset);
+                x86_64::instructions::port::Port::<u32>::new(CONFIG_DATA_PORT).write(value)
+            }),
+        }
+    }
+
+    /// Returns where the register at `offset` is mapped, if the bus is
+    /// reachable through ECAM.
+    fn ecam_register(&self, mechanism: Mechanism, offset: u16) -> Option<VirtAddr> {
+        match mechanism {
+            Mechanism::Ecam {
+                base,
+                start_bus,
+                end_bus,
+            } if (start_bus..=end_bus).contains(&self.bus) => {
+                let function = ((self.bus - start_bus) as u64) << 20
+                    | (self.device as u64) << 15
+                    | (self.function as u64) << 12;
+                Some(base + function + (offset & 0xfff) as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Selects the dword at `offset` for the data port. Interrupts must be
+    /// off until the data port is accessed.
+    unsafe fn select(&self, offset: u16) {
+        let address = CONFIG_ENABLE
+            | (self.bus as u32) << 16
+            | (self.device as u32) << 11
+            | (self.function as u32) << 8
+            | (offset & 0xfc) as u32;
+        x86_64::instructions::port::Port::<u32>::new(CONFIG_ADDRESS_PORT).write(address);
+    }
+}
@@ -0,0 +1,530 @@
+mod config;
+
+pub use self::config::Mechanism;
+
+use crate::threads::interrupt;
+
+/// Offsets of the registers in the configuration space header.
+const VENDOR_ID: u16 = 0x00;
+const DEVICE_ID: u16 = 0x02;
+const COMMAND: u16 = 0x04;
+const STATUS: u16 = 0x06;
+const REVISION: u16 = 0x08;
+const PROG_IF: u16 = 0x09;
+const SUBCLASS: u16 = 0x0a;
+const CLASS: u16 = 0x0b;
+const HEADER_TYPE: u16 = 0x0e;
+const BAR0: u16 = 0x10;
+const SECONDARY_BUS: u16 = 0x19;
+const CAPABILITIES_POINTER: u16 = 0x34;
+const INTERRUPT_LINE: u16 = 0x3c;
+const INTERRUPT_PIN: u16 = 0x3d;
+
+/// Vendor ID read from a function which does not exist.
+const NO_VENDOR: u16 = 0xffff;
+
+/// Bit of the header type set if the device has more than one function.
+const MULTI_FUNCTION: u8 = 0x80;
+
+/// Header type of PCI-to-PCI bridges.
+const HEADER_TYPE_BRIDGE: u8 = 0x01;
+
+/// Class and subclass of PCI-to-PCI bridges.
+const CLASS_BRIDGE: u8 = 0x06;
+const SUBCLASS_PCI_BRIDGE: u8 = 0x04;
+
+/// Maximum number of functions we keep track of.
+const MAX_DEVICES: usize = 64;
+
+/// Maximum number of drivers.
+const MAX_DRIVERS: usize = 16;
+
+/// Maximum number of capabilities followed in a list, in case it loops.
+const MAX_CAPABILITIES: usize = 48;
+
+bitflags::bitflags! {
+    pub struct Command: u16 {
+        /// Responds to accesses to its I/O BARs.
+        const IO_SPACE = 1 << 0;
+
+        /// Responds to accesses to its memory BARs.
+        const MEMORY_SPACE = 1 << 1;
+
+        /// May master the bus, e.g. for DMA.
+        const BUS_MASTER = 1 << 2;
+
+        /// Does not assert its INTx# interrupt line.
+        const INTERRUPT_DISABLE = 1 << 10;
+    }
+}
+
+/// Bit of the status register set if the function has a capabilities list.
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+
+/// Location of a function: bus, device and function number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Address {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl Address {
+    pub const fn new(bus: u8, device: u8, function: u8) -> Self {
+        Self {
+            bus,
+            device,
+            function,
+        }
+    }
+}
+
+impl core::fmt::Display for Address {
+    /// Formats the address like `00:1f.2`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
+/// A Base Address Register, decoded and sized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bar {
+    /// Memory-mapped registers, at physical address `address`.
+    Memory {
+        address: u64,
+        size: u64,
+        prefetchable: bool,
+        is_64bit: bool,
+    },
+
+    /// Registers in I/O space, from `port`.
+    Io { port: u16, size: u16 },
+}
+
+/// An entry of the capabilities list of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capability {
+    pub id: u8,
+
+    /// Offset of the capability in the configuration space.
+    pub offset: u16,
+}
+
+impl Capability {
+    pub const POWER_MANAGEMENT: u8 = 0x01;
+    pub const MSI: u8 = 0x05;
+    pub const VENDOR_SPECIFIC: u8 = 0x09;
+    pub const PCI_EXPRESS: u8 = 0x10;
+    pub const MSI_X: u8 = 0x11;
+}
+
+/// A PCI function, as found while scanning the buses.
+#[derive(Debug, Clone, Copy)]
+pub struct Device {
+    pub address: Address,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+
+    /// Base Address Registers. The upper half of a 64-bit BAR is `None`.
+    pub bars: [Option<Bar>; 6],
+
+    /// IRQ line the firmware routed the function's interrupt pin to.
+    pub interrupt_line: Option<u8>,
+
+    /// Interrupt pin the function uses: 1 for INTA# to 4 for INTD#.
+    pub interrupt_pin: Option<u8>,
+
+    /// Name of the driver which took the device.
+    driver: Option<&'static str>,
+}
+
+impl Device {
+    /// Reads the function at `address`, sizing its BARs. Returns `None` if
+    /// there is no such function.
+    fn read(address: Address) -> Option<Self> {
+        let vendor_id = address.read_u16(VENDOR_ID);
+        if vendor_id == NO_VENDOR {
+            return None;
+        }
+
+        let header_type = address.read_u8(HEADER_TYPE) & !MULTI_FUNCTION;
+        let bar_count = match header_type {
+            0x00 => 6,
+            HEADER_TYPE_BRIDGE => 2,
+            _ => 0,
+        };
+
+        let mut bars = [None; 6];
+        let mut index = 0;
+        while index < bar_count {
+            let bar = read_bar(address, index, bar_count);
+            bars[index] = bar;
+            index += match bar {
+                Some(Bar::Memory { is_64bit: true, .. }) => 2,
+                _ => 1,
+            };
+        }
+
+        let interrupt_line = address.read_u8(INTERRUPT_LINE);
+        let interrupt_pin = address.read_u8(INTERRUPT_PIN);
+
+        Some(Self {
+            address,
+            vendor_id,
+            device_id: address.read_u16(DEVICE_ID),
+            class: address.read_u8(CLASS),
+            subclass: address.read_u8(SUBCLASS),
+            prog_if: address.read_u8(PROG_IF),
+            revision: address.read_u8(REVISION),
+            bars,
+            interrupt_line: Some(interrupt_line).filter(|&line| line != 0xff),
+            interrupt_pin: Some(interrupt_pin).filter(|pin| (1..=4).contains(pin)),
+            driver: None,
+        })
+    }
+
+    /// Returns the name of the driver which took the device, if any.
+    pub fn driver(&self) -> Option<&'static str> {
+        self.driver
+    }
+
+    /// Returns the capabilities of the function.
+    pub fn capabilities(&self) -> impl Iterator<Item = Capability> {
+        let address = self.address;
+        let mut offset = if address.read_u16(STATUS) & STATUS_CAPABILITIES != 0 {
+            address.read_u8(CAPABILITIES_POINTER) & !0b11
+        } else {
+            0
+        };
+
+        core::iter::from_fn(move || {
+            if offset == 0 {
+                return None;
+            }
+
+            let capability = Capability {
+                id: address.read_u8(offset as u16),
+                offset: offset as u16,
+            };
+            offset = address.read_u8(offset as u16 + 1) & !0b11;
+            Some(capability)
+        })
+        .take(MAX_CAPABILITIES)
+    }
+
+    /// Returns the first capability with `id`.
+    pub fn find_capability(&self, id: u8) -> Option<Capability> {
+        self.capabilities().find(|capability| capability.id == id)
+    }
+
+    /// Returns the command register.
+    pub fn command(&self) -> Command {
+        Command::from_bits_truncate(self.address.read_u16(COMMAND))
+    }
+
+    /// Turns on the bits of `command` in the command register, e.g. to let
+    /// the device master the bus.
+    pub fn enable(&self, command: Command) {
+        let value = self.address.read_u16(COMMAND) | command.bits();
+        self.address.write_u16(COMMAND, value);
+    }
+}
+
+/// Reads and sizes BAR `index` of the `bar_count` BARs of the function at
+/// `address`.
+///
+/// The size is found by writing all ones, and seeing which bits stick. The
+/// device stops decoding its BARs meanwhile, so that it does not respond at
+/// the bogus addresses, and nothing else may run, which could access them.
+fn read_bar(address: Address, index: usize, bar_count: usize) -> Option<Bar> {
+    crate::without_interrupts!(size_bar(address, index, bar_count))
+}
+
+fn size_bar(address: Address, index: usize, bar_count: usize) -> Option<Bar> {
+    const IO_SPACE: u32 = 0b1;
+    const TYPE_64BIT: u32 = 0b100;
+    const PREFETCHABLE: u32 = 0b1000;
+
+    let offset = BAR0 + index as u16 * 4;
+    let command = address.read_u16(COMMAND);
+    let decode = (Command::IO_SPACE | Command::MEMORY_SPACE).bits();
+    address.write_u16(COMMAND, command & !decode);
+
+    let size = |offset: u16| {
+        let value = address.read_u32(offset);
+        address.write_u32(offset, u32::MAX);
+        let mask = address.read_u32(offset);
+        address.write_u32(offset, value);
+        (value, mask)
+    };
+
+    let (value, mask) = size(offset);
+    let (bar, mask) = if value & IO_SPACE != 0 {
+        let mask = mask & !0b11 & 0xffff;
+        let bar = Bar::Io {
+            port: (value & !0b11) as u16,
+            size: (!mask).wrapping_add(1) as u16,
+        };
+        (bar, mask as u64)
+    } else if value & TYPE_64BIT != 0 && index + 1 < bar_count {
+        let (high, high_mask) = size(offset + 4);
+        let mask = (high_mask as u64) << 32 | (mask & !0b1111) as u64;
+        let bar = Bar::Memory {
+            address: (high as u64) << 32 | (value & !0b1111) as u64,
+            size: (!mask).wrapping_add(1),
+            prefetchable: value & PREFETCHABLE != 0,
+            is_64bit: true,
+        };
+        (bar, mask)
+    } else {
+        let mask = mask & !0b1111;
+        let bar = Bar::Memory {
+            address: (value & !0b1111) as u64,
+            size: (!mask).wrapping_add(1) as u64,
+            prefetchable: value & PREFETCHABLE != 0,
+            is_64bit: false,
+        };
+        (bar, mask as u64)
+    };
+
+    address.write_u16(COMMAND, command);
+
+    // No bits stick in BARs which are not implemented.
+    if mask != 0 {
+        Some(bar)
+    } else {
+        None
+    }
+}
+
+/// Which devices a [`Driver`] drives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Match {
+    /// Devices with this vendor and device ID.
+    Id { vendor_id: u16, device_id: u16 },
+
+    /// Devices of this class and subclass.
+    Class { class: u8, subclass: u8 },
+}
+
+impl Match {
+    fn matches(&self, device: &Device) -> bool {
+        match *self {
+            Match::Id {
+                vendor_id,
+                device_id,
+            } => device.vendor_id == vendor_id && device.device_id == device_id,
+            Match::Class { class, subclass } => {
+                device.class == class && device.subclass == subclass
+            }
+        }
+    }
+}
+
+/// A driver for PCI devices.
+///
+/// Once registered, its `probe` function is called for each device it
+/// matches which no other driver took, whether found before or after.
+#[derive(Debug)]
+pub struct Driver {
+    pub name: &'static str,
+    pub matches: &'static [Match],
+
+    /// Sets up `device`. Returns `true` if the driver takes the device.
+    pub probe: fn(&Device) -> bool,
+}
+
+/// The PCI functions found, and the drivers.
+pub struct Pci {
+    devices: [Option<Device>; MAX_DEVICES],
+    device_cnt: usize,
+    drivers: [Option<&'static Driver>; MAX_DRIVERS],
+}
+
+impl Pci {
+    /// Creates an empty [`Pci`], with no devices nor drivers.
+    pub const fn new() -> Self {
+        Self {
+            devices: [None; MAX_DEVICES],
+            device_cnt: 0,
+            drivers: [None; MAX_DRIVERS],
+        }
+    }
+
+    /// Returns the devices found.
+    pub fn devices(&self) -> impl Iterator<Item = &Device> {
+        self.devices[..self.device_cnt].iter().flatten()
+    }
+
+    /// Returns the device at `address`, if there is one.
+    pub fn find(&self, address: Address) -> Option<&Device> {
+        self.devices().find(|device| device.address == address)
+    }
+
+    /// Writes the devices found, like `lspci`.
+    pub fn write_devices(&self, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+        writeln!(out, "address  id         class     irq  driver")?;
+        for device in self.devices() {
+            write!(
+                out,
+                "{}  {:04x}:{:04x}  {:02x}.{:02x}.{:02x}  ",
+                device.address,
+                device.vendor_id,
+                device.device_id,
+                device.class,
+                device.subclass,
+                device.prog_if
+            )?;
+            match device.interrupt_line {
+                Some(line) => write!(out, "{line:>3}")?,
+                None => write!(out, "  -")?,
+            }
+            writeln!(out, "  {}", device.driver.unwrap_or("-"))?;
+        }
+        Ok(())
+    }
+
+    /// Adds `device`. Returns `false` if there is no room for it.
+    fn add_device(&mut self, device: Device) -> bool {
+        if self.device_cnt == MAX_DEVICES {
+            return false;
+        }
+        self.devices[self.device_cnt] = Some(device);
+        self.device_cnt += 1;
+        true
+    }
+
+    fn add_driver(&mut self, driver: &'static Driver) {
+        let slot = self
+            .drivers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .expect("too many PCI drivers");
+        *slot = Some(driver);
+    }
+}
+
+impl Default for Pci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Global PCI devices and drivers.
+pub static PCI: interrupt::Mutex<Pci> = interrupt::Mutex::new(Pci::new());
+
+/// Scans the PCI buses, and probes the drivers registered so far for the
+/// devices found.
+///
+/// The ACPI tables must be read, to find the memory-mapped configuration
+/// space, and the interrupts set up, for the drivers to use.
+pub fn init() {
+    let mechanism = config::init();
+
+    // A multi-function host bridge has one function per host controller,
+    // each with its bus.
+    let host = Address::new(0, 0, 0);
+    if host.read_u8(HEADER_TYPE) & MULTI_FUNCTION == 0 {
+        scan_bus(0);
+    } else {
+        for function in 0..8 {
+            if Address::new(0, 0, function).read_u16(VENDOR_ID) != NO_VENDOR {
+                scan_bus(function);
+            }
+        }
+    }
+
+    let device_cnt = PCI.lock().device_cnt;
+    match mechanism {
+        Mechanism::Port => log::info!("PCI: {device_cnt} devices, through I/O ports."),
+        Mechanism::Ecam { base, .. } => {
+            log::info!("PCI: {device_cnt} devices, through ECAM at {base:#x}.")
+        }
+    }
+
+    probe();
+}
+
+/// Scans bus `bus`, and the buses behind its bridges.
+fn scan_bus(bus: u8) {
+    for device in 0..32 {
+        let address = Address::new(bus, device, 0);
+        if address.read_u16(VENDOR_ID) == NO_VENDOR {
+            continue;
+        }
+
+        let functions = if address.read_u8(HEADER_TYPE) & MULTI_FUNCTION != 0 {
+            8
+        } else {
+            1
+        };
+        for function in 0..functions {
+            scan_function(Address::new(bus, device, function));
+        }
+    }
+}
+
+fn scan_function(address: Address) {
+    let device = match Device::read(address) {
+        Some(device) => device,
+        None => return,
+    };
+
+    log::debug!(
+        "{}: {:04x}:{:04x}, class {:02x}.{:02x}.",
+        address,
+        device.vendor_id,
+        device.device_id,
+        device.class,
+        device.subclass
+    );
+    if !PCI.lock().add_device(device) {
+        log::warn!("Too many PCI devices, ignoring {address}.");
+        return;
+    }
+
+    if device.class == CLASS_BRIDGE && device.subclass == SUBCLASS_PCI_BRIDGE {
+        let secondary_bus = address.read_u8(SECONDARY_BUS);
+        if secondary_bus > address.bus {
+            scan_bus(secondary_bus);
+        }
+    }
+}
+
+/// Registers `driver`, and probes it for the devices found so far.
+pub fn register_driver(driver: &'static Driver) {
+    PCI.lock().add_driver(driver);
+    probe();
+}
+
+/// Offers the devices no driver took yet to the drivers which match them.
+///
+/// The drivers are probed without [`PCI`] locked, so that they may look at
+/// the other devices.
+fn probe() {
+    for index in 0..MAX_DEVICES {
+        let (device, drivers) = {
+            let pci = PCI.lock();
+            match pci.devices[index] {
+                Some(device) if device.driver.is_none() => (device, pci.drivers),
+                Some(_) => continue,
+                None => break,
+            }
+        };
+
+        let driver = drivers.iter().flatten().find(|driver| {
+            driver.matches.iter().any(|m| m.matches(&device)) && (driver.probe)(&device)
+        });
+
+        if let Some(driver) = driver {
+            log::info!("{}: driven by {}.", device.address, driver.name);
+            if let Some(device) = PCI.lock().devices[index].as_mut() {
+                device.driver = Some(driver.name);
+            }
+        }
+    }
+}
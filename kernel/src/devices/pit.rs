@@ -19,6 +19,10 @@ pub enum Channel {
 
 /// Represents the form of output:
 ///
+/// - Mode 0 is an one-shot interrupt: the channel's output goes to 1 once the
+///   counter reaches zero, and stays there until the channel is reprogrammed.
+///   This is useful for waking up the CPU once, after a given delay.
+///
 /// - Mode 2 is a periodic pulse: the channel's output is 1 for most of the
 ///   period, but drops to 0 briefly towards the end of the period. This is
 ///   useful for hooking up to an interrupt controller to generate a periodic
@@ -29,10 +33,25 @@ pub enum Channel {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Mode {
+    InterruptOnTerminalCount = 0,
     RateGenerator = 2,
     SquareWave = 3,
 }
 
+/// State of a channel, read back from the PIT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    /// Level of the channel's output.
+    pub output: bool,
+
+    /// Whether the count loaded last is yet to be moved into the counter.
+    pub null_count: bool,
+
+    /// Mode the channel is configured in, or `None` if it is one we do not
+    /// use.
+    pub mode: Option<Mode>,
+}
+
 /// Interface to 8254 Programmable Interrupt Timer (PIT).
 #[derive(Debug)]
 pub struct Pit {
@@ -59,6 +78,12 @@ impl Pit {
     /// would interpret as 65536.
     const MAX_FREQUENCY: usize = 1193180;
 
+    /// Frequency of the oscillator driving the counters, in Hz.
+    pub const INPUT_FREQUENCY: usize = Self::MAX_FREQUENCY;
+
+    /// Largest count we can load to a counter.
+    pub const MAX_COUNT: u16 = u16::MAX;
+
     /// Creates a new interface for [`Pit`].
     pub const fn new() -> Self {
         Self {
@@ -68,6 +93,7 @@ impl Pit {
         }
     }
 
+    /// Configures `channel` to output in `mode` at `frequency` Hz.
     pub fn configure(&mut self, channel: Channel, mode: Mode, frequency: usize) {
         let count = {
             if frequency < Self::MIN_FREQUENCY {
@@ -85,22 +111,77 @@ impl Pit {
             }
         } as u16;
 
+        self.load(channel, mode, count);
+    }
+
+    /// Configures `channel` to output in `mode`, loading `count` directly to
+    /// its counter.
+    pub fn load(&mut self, channel: Channel, mode: Mode, count: u16) {
         // Configure the PIT mode and load its counters.
         unsafe {
             self.control
                 .write(((channel as u8) << 6) | 0x30 | ((mode as u8) << 1));
 
-            let Self { out0, out2, .. } = self;
-            let out = match channel {
-                Channel::OUT0 => out0,
-                Channel::OUT2 => out2,
-            };
+            let out = self.counter(channel);
 
-            let [low, high] = [(count >> 8) as u8, count as u8];
+            let [low, high] = count.to_le_bytes();
             out.write(low);
             out.write(high);
         }
     }
+
+    /// Latches the counter of `channel` and reads back its current count.
+    pub fn read_count(&mut self, channel: Channel) -> u16 {
+        unsafe {
+            // Counter latch command: access mode 0 freezes the current count
+            // until it has been read.
+            self.control.write((channel as u8) << 6);
+
+            let out = self.counter(channel);
+
+            let low = out.read();
+            let high = out.read();
+            u16::from_le_bytes([low, high])
+        }
+    }
+
+    /// Reads back the status of `channel`, and the count it latches along
+    /// with it.
+    pub fn read_status(&mut self, channel: Channel) -> (Status, u16) {
+        unsafe {
+            // Read-back command: latches both the status and the count of the
+            // channels selected by bits 1 to 3.
+            self.control.write(0xc0 | (1 << (channel as u8 + 1)));
+
+            let out = self.counter(channel);
+
+            let status = out.read();
+            let low = out.read();
+            let high = out.read();
+
+            // Modes 2 and 3 may read back with bit 2 set.
+            let mode = match (status >> 1) & 0x07 {
+                0 => Some(Mode::InterruptOnTerminalCount),
+                2 | 6 => Some(Mode::RateGenerator),
+                3 | 7 => Some(Mode::SquareWave),
+                _ => None,
+            };
+
+            let status = Status {
+                output: status & 0x80 != 0,
+                null_count: status & 0x40 != 0,
+                mode,
+            };
+            (status, u16::from_le_bytes([low, high]))
+        }
+    }
+
+    fn counter(&mut self, channel: Channel) -> &mut x86_64::instructions::port::Port<u8> {
+        match channel {
+            Channel::OUT0 => &mut self.out0,
+            Channel::OUT2 => &mut self.out2,
+        }
+    }
 }
 
 /// Global [`Pit`].
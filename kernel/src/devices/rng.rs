@@ -0,0 +1,194 @@
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::{threads::interrupt, utils::random::ChaChaRng};
+
+use super::{clock::rdtsc, rtc};
+
+/// CPUID feature bits: RDRAND in ECX of leaf 1, RDSEED in EBX of leaf 7.
+const CPUID_RDRAND: u32 = 1 << 30;
+const CPUID_RDSEED: u32 = 1 << 18;
+
+/// Number of times RDRAND or RDSEED is retried when it has no number ready.
+const HARDWARE_RETRIES: usize = 10;
+
+/// Number of TSC samples timed for jitter, at boot.
+const JITTER_SAMPLES: usize = 256;
+
+/// Number of requests served between reseeds.
+const RESEED_INTERVAL: u32 = 64;
+
+static HAS_RDRAND: AtomicBool = AtomicBool::new(false);
+static HAS_RDSEED: AtomicBool = AtomicBool::new(false);
+
+/// Entropy from the timing of interrupts, which the interrupt handlers fold
+/// in, and the number of interrupts folded in.
+static INTERRUPT_POOL: AtomicU64 = AtomicU64::new(0);
+static INTERRUPT_CNT: AtomicU64 = AtomicU64::new(0);
+
+/// The kernel's generator, and the number of requests since it was last
+/// reseeded.
+struct Rng {
+    chacha: ChaChaRng,
+    requests: u32,
+}
+
+static RNG: interrupt::Mutex<Option<Rng>> = interrupt::Mutex::new(None);
+
+/// Looks for RDRAND and RDSEED, and seeds the kernel's generator from them,
+/// the jitter of the TSC, and the time.
+///
+/// Numbers asked for before are drawn from a generator seeded at that time,
+/// without the jitter.
+pub fn init() {
+    let has_rdrand = core::arch::x86_64::__cpuid(1).ecx & CPUID_RDRAND != 0;
+    let max_leaf = core::arch::x86_64::__cpuid(0).eax;
+    let has_rdseed = max_leaf >= 7 && core::arch::x86_64::__cpuid(7).ebx & CPUID_RDSEED != 0;
+    HAS_RDRAND.store(has_rdrand, Ordering::Relaxed);
+    HAS_RDSEED.store(has_rdseed, Ordering::Relaxed);
+
+    let mut chacha = ChaChaRng::new(fresh_entropy());
+    let mut samples = [0; 4];
+    for _ in 0..JITTER_SAMPLES / samples.len() {
+        for sample in samples.iter_mut() {
+            *sample = jitter_sample();
+        }
+        chacha.reseed(words_to_seed(samples));
+    }
+    chacha.reseed(words_to_seed([rtc::now(), rdtsc(), 0, 0]));
+
+    *RNG.lock() = Some(Rng {
+        chacha,
+        requests: 0,
+    });
+
+    log::info!(
+        "rng: seeded from {}TSC jitter and interrupt timing.",
+        match (has_rdseed, has_rdrand) {
+            (true, _) => "RDSEED, ",
+            (false, true) => "RDRAND, ",
+            (false, false) => "",
+        }
+    );
+}
+
+/// Returns whether the CPU has RDRAND.
+pub fn has_rdrand() -> bool {
+    HAS_RDRAND.load(Ordering::Relaxed)
+}
+
+/// Returns whether the CPU has RDSEED.
+pub fn has_rdseed() -> bool {
+    HAS_RDSEED.load(Ordering::Relaxed)
+}
+
+/// Returns a number from the CPU's generator: from RDSEED, which is straight
+/// from its entropy source, or else RDRAND. Returns `None` if the CPU has
+/// neither, or they keep failing.
+pub fn hardware_u64() -> Option<u64> {
+    let mut value = 0;
+    for _ in 0..HARDWARE_RETRIES {
+        if has_rdseed() && unsafe { rdseed(&mut value) } {
+            return Some(value);
+        }
+        if has_rdrand() && unsafe { rdrand(&mut value) } {
+            return Some(value);
+        }
+        if !has_rdseed() && !has_rdrand() {
+            return None;
+        }
+        core::hint::spin_loop();
+    }
+    None
+}
+
+#[target_feature(enable = "rdrand")]
+unsafe fn rdrand(value: &mut u64) -> bool {
+    core::arch::x86_64::_rdrand64_step(value) == 1
+}
+
+#[target_feature(enable = "rdseed")]
+unsafe fn rdseed(value: &mut u64) -> bool {
+    core::arch::x86_64::_rdseed64_step(value) == 1
+}
+
+/// Folds the time of an interrupt on `vector` into the interrupt pool.
+///
+/// Called by the interrupt handlers, so it only touches atomics.
+pub fn add_interrupt_entropy(vector: u8) {
+    let sample = rdtsc() ^ (vector as u64) << 56;
+    let pool = INTERRUPT_POOL.load(Ordering::Relaxed);
+    INTERRUPT_POOL.store(
+        pool.rotate_left(7) ^ sample.wrapping_mul(0x9e37_79b9_7f4a_7c15),
+        Ordering::Relaxed,
+    );
+    INTERRUPT_CNT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the number of interrupts folded into the interrupt pool.
+pub fn interrupt_count() -> u64 {
+    INTERRUPT_CNT.load(Ordering::Relaxed)
+}
+
+/// Fills `buffer` with cryptographically secure random bytes.
+pub fn fill_bytes(buffer: &mut [u8]) {
+    let mut rng = RNG.lock();
+    let rng = rng.get_or_insert_with(|| Rng {
+        chacha: ChaChaRng::new(fresh_entropy()),
+        requests: 0,
+    });
+
+    rng.requests += 1;
+    if rng.requests >= RESEED_INTERVAL {
+        rng.chacha.reseed(fresh_entropy());
+        rng.requests = 0;
+    }
+    rng.chacha.fill_bytes(buffer);
+}
+
+pub fn next_u64() -> u64 {
+    let mut bytes = [0; 8];
+    fill_bytes(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
+
+pub fn next_u32() -> u32 {
+    let mut bytes = [0; 4];
+    fill_bytes(&mut bytes);
+    u32::from_le_bytes(bytes)
+}
+
+/// Returns a seed from the entropy at hand, cheaply: the CPU's generator,
+/// the interrupt pool, and the TSC.
+fn fresh_entropy() -> [u8; 32] {
+    let hardware = [
+        hardware_u64().unwrap_or(0),
+        hardware_u64().unwrap_or(0),
+        hardware_u64().unwrap_or(0),
+        hardware_u64().unwrap_or(0),
+    ];
+    words_to_seed([
+        hardware[0] ^ INTERRUPT_POOL.load(Ordering::Relaxed),
+        hardware[1] ^ rdtsc(),
+        hardware[2] ^ INTERRUPT_CNT.load(Ordering::Relaxed),
+        hardware[3],
+    ])
+}
+
+/// Times a short computation with the TSC. How long it takes varies with
+/// the caches, the pipeline and the interrupts, in the low bits.
+fn jitter_sample() -> u64 {
+    let start = rdtsc();
+    let mut x = start;
+    for i in 0..64 {
+        x = core::hint::black_box(x.rotate_left(5) ^ i);
+    }
+    rdtsc().wrapping_sub(start) ^ x
+}
+
+fn words_to_seed(words: [u64; 4]) -> [u8; 32] {
+    let mut seed = [0; 32];
+    for (bytes, word) in seed.as_chunks_mut::<8>().0.iter_mut().zip(words) {
+        *bytes = word.to_le_bytes();
+    }
+    seed
+}
@@ -0,0 +1,235 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::clock::Instant;
+
+/// CMOS registers, selected through the index port and accessed through the
+/// data port.
+const CMOS_INDEX_PORT: u16 = 0x70;
+const CMOS_DATA_PORT: u16 = 0x71;
+
+/// Indexes of the CMOS registers of the real-time clock.
+const RTC_SECONDS: u8 = 0x00;
+const RTC_MINUTES: u8 = 0x02;
+const RTC_HOURS: u8 = 0x04;
+const RTC_DAY: u8 = 0x07;
+const RTC_MONTH: u8 = 0x08;
+const RTC_YEAR: u8 = 0x09;
+const RTC_STATUS_A: u8 = 0x0a;
+const RTC_STATUS_B: u8 = 0x0b;
+
+/// Number of seconds in a day.
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+bitflags::bitflags! {
+    struct StatusA: u8 {
+        /// Set while the clock is updating its registers, which may then be
+        /// inconsistent.
+        const UPDATE_IN_PROGRESS = 0b1000_0000;
+    }
+
+    struct StatusB: u8 {
+        /// Hours run from 0 to 23. Otherwise, from 1 to 12, with the top bit
+        /// of the hour set in the afternoon.
+        const HOURS_24 = 0b0000_0010;
+
+        /// The registers are in binary. Otherwise, they are in BCD.
+        const BINARY = 0b0000_0100;
+    }
+}
+
+/// Bit of the hour register set for PM, in 12-hour mode.
+const HOUR_PM: u8 = 0b1000_0000;
+
+/// A date and time of day, in UTC, like the real-time clock keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    /// From 1 to 12.
+    pub month: u8,
+    /// From 1 to 31.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Returns the date and time `time` seconds after the Unix epoch.
+    pub fn from_unix(time: u64) -> Self {
+        let days = time / SECS_PER_DAY;
+        let secs = time % SECS_PER_DAY;
+
+        // Count from 0000-03-01, so that leap days come at the end of the
+        // year, in eras of 400 years. See Howard Hinnant's
+        // `civil_from_days()`.
+        let days = days + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        };
+        let year = year_of_era + era * 400 + (month <= 2) as u64;
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+
+    /// Returns the number of seconds from the Unix epoch to `self`, which
+    /// must not be earlier.
+    pub fn to_unix(&self) -> u64 {
+        // The inverse of `from_unix()`: Howard Hinnant's
+        // `days_from_civil()`.
+        let month = self.month as u64;
+        let year = self.year as u64 - (month <= 2) as u64;
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let month_from_march = (month + 9) % 12;
+        let day_of_year = (153 * month_from_march + 2) / 5 + self.day as u64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        days * SECS_PER_DAY + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64
+    }
+
+    /// Returns the current date and time.
+    pub fn now() -> Self {
+        Self::from_unix(now())
+    }
+}
+
+impl core::fmt::Display for DateTime {
+    /// Formats the date and time like `2024-02-29 13:45:00`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Unix time the kernel booted at, or 0 before [`init()`].
+static BOOT_TIME: AtomicU64 = AtomicU64::new(0);
+
+/// Reads the real-time clock, and works out the Unix time the kernel booted
+/// at from it, like Pintos' `rtc_get_time()`.
+///
+/// The monotonic clock must be running, since the time since boot is taken
+/// away from the time read.
+pub fn init() {
+    let time = read().to_unix();
+    let since_boot = Instant::now().since_boot().as_secs();
+    BOOT_TIME.store(time - since_boot, Ordering::Relaxed);
+
+    log::info!("Real-time clock: {}.", DateTime::from_unix(time));
+}
+
+/// Returns the Unix time the kernel booted at, once [`init()`] read the
+/// real-time clock.
+pub fn boot_time() -> Option<u64> {
+    match BOOT_TIME.load(Ordering::Relaxed) {
+        0 => None,
+        time => Some(time),
+    }
+}
+
+/// Returns the current Unix time: the number of seconds since
+/// 1970-01-01 00:00:00 UTC.
+///
+/// It is counted by the monotonic clock from the time read at [`init()`],
+/// rather than read from the real-time clock, which is slow.
+pub fn now() -> u64 {
+    let boot_time = boot_time().expect("real-time clock not initialized");
+    boot_time + Instant::now().since_boot().as_secs()
+}
+
+/// Reads the date and time from the real-time clock.
+///
+/// The registers are read twice, until the two readings agree, so that they
+/// are not torn by an update of the clock in the middle.
+pub fn read() -> DateTime {
+    let mut last = read_registers();
+    loop {
+        let registers = read_registers();
+        if registers == last {
+            break;
+        }
+        last = registers;
+    }
+
+    let status_b = StatusB::from_bits_truncate(read_cmos(RTC_STATUS_B));
+    let decode = |value: u8| {
+        if status_b.contains(StatusB::BINARY) {
+            value
+        } else {
+            (value >> 4) * 10 + (value & 0x0f)
+        }
+    };
+
+    let [second, minute, hour, day, month, year] = last;
+    let mut hour_value = decode(hour & !HOUR_PM);
+    if !status_b.contains(StatusB::HOURS_24) {
+        // 12 AM is midnight, and 12 PM is noon.
+        hour_value %= 12;
+        if hour & HOUR_PM != 0 {
+            hour_value += 12;
+        }
+    }
+
+    // The clock keeps two digits of the year: take them to be within 1970
+    // to 2069.
+    let year = decode(year) as u16;
+    let year = if year < 70 { 2000 + year } else { 1900 + year };
+
+    DateTime {
+        year,
+        month: decode(month),
+        day: decode(day),
+        hour: hour_value,
+        minute: decode(minute),
+        second: decode(second),
+    }
+}
+
+/// Reads the raw date and time registers, once no update is in progress.
+fn read_registers() -> [u8; 6] {
+    while StatusA::from_bits_truncate(read_cmos(RTC_STATUS_A)).contains(StatusA::UPDATE_IN_PROGRESS)
+    {
+        core::hint::spin_loop();
+    }
+
+    [
+        RTC_SECONDS,
+        RTC_MINUTES,
+        RTC_HOURS,
+        RTC_DAY,
+        RTC_MONTH,
+        RTC_YEAR,
+    ]
+    .map(read_cmos)
+}
+
+/// Reads CMOS register `index`.
+fn read_cmos(index: u8) -> u8 {
+    let mut index_port = x86_64::instructions::port::PortWriteOnly::<u8>::new(CMOS_INDEX_PORT);
+    let mut data_port = x86_64::instructions::port::PortReadOnly::<u8>::new(CMOS_DATA_PORT);
+
+    // Nothing may select another register in between.
+    crate::without_interrupts!(unsafe {
+        index_port.write(index);
+        data_port.read()
+    })
+}
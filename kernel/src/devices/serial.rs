@@ -1,9 +1,100 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{debug, threads::interrupt};
+
+use super::{input, intq::IntQueue};
+
 /// Transmission mode.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum SerialMode {
     Uninitialized,
     Poll,
     Queue,
+
+    /// No UART answered at the port, so bytes sent are dropped.
+    Absent,
+}
+
+/// Serial ports of a PC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    Com1,
+    Com2,
+    Com3,
+    Com4,
+}
+
+impl Port {
+    /// Returns the base I/O port of the serial port.
+    pub const fn io_base(self) -> u16 {
+        match self {
+            Self::Com1 => 0x3F8,
+            Self::Com2 => 0x2F8,
+            Self::Com3 => 0x3E8,
+            Self::Com4 => 0x2E8,
+        }
+    }
+
+    /// Returns the IRQ line of the serial port. COM3 and COM4 share theirs
+    /// with COM1 and COM2.
+    pub const fn irq(self) -> u8 {
+        match self {
+            Self::Com1 | Self::Com3 => 4,
+            Self::Com2 | Self::Com4 => 3,
+        }
+    }
+}
+
+/// Number of bytes in the receive FIFO which raise an interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FifoTrigger {
+    Bytes1,
+    Bytes4,
+    Bytes8,
+    Bytes14,
+}
+
+impl FifoTrigger {
+    fn bits(self) -> FifoControl {
+        match self {
+            Self::Bytes1 => FifoControl::TRIGGER_1,
+            Self::Bytes4 => FifoControl::TRIGGER_4,
+            Self::Bytes8 => FifoControl::TRIGGER_8,
+            Self::Bytes14 => FifoControl::TRIGGER_14,
+        }
+    }
+}
+
+/// Configuration of a serial port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub port: Port,
+
+    /// Baud rate, from 300 to 115200 bps.
+    pub baud_rate: u32,
+
+    /// Trigger level of the receive FIFO, or `None` to leave the FIFOs off.
+    /// They are only turned on if the UART is a 16550A, since the FIFOs of
+    /// older ones are missing or broken.
+    pub fifo: Option<FifoTrigger>,
+}
+
+impl Config {
+    /// Creates the default configuration: COM1 at 9600 bps, interrupting on
+    /// every byte received.
+    pub const fn new() -> Self {
+        Self {
+            port: Port::Com1,
+            baud_rate: 9_600,
+            fifo: Some(FifoTrigger::Bytes1),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 bitflags::bitflags! {
@@ -15,7 +106,27 @@ bitflags::bitflags! {
         const TRANSMITTER_HOLDING_EMPTY = 0b0000_0010;
     }
 
-    struct FifoControl: u8 {}
+    struct FifoControl: u8 {
+        /// Enables the receive and transmit FIFOs.
+        const ENABLE = 0b0000_0001;
+
+        /// Clears the receive FIFO.
+        const CLEAR_RECEIVER = 0b0000_0010;
+
+        /// Clears the transmit FIFO.
+        const CLEAR_TRANSMITTER = 0b0000_0100;
+
+        /// Receive FIFO trigger levels.
+        const TRIGGER_1 = 0b0000_0000;
+        const TRIGGER_4 = 0b0100_0000;
+        const TRIGGER_8 = 0b1000_0000;
+        const TRIGGER_14 = 0b1100_0000;
+    }
+
+    struct InterruptIdentification: u8 {
+        /// Both set when the FIFOs are enabled and working, as on the 16550A.
+        const FIFOS_ENABLED = 0b1100_0000;
+    }
 
     struct LineControl: u8 {
         /// No parity, 1 stop bit, 8 data bits.
@@ -28,6 +139,9 @@ bitflags::bitflags! {
     struct ModemControl: u8 {
         /// Controls OUT2 signal, an auxiliary output pin.
         const OUT2 = 0b0000_1000;
+
+        /// Connects the transmitter to the receiver, for self-testing.
+        const LOOPBACK = 0b0001_0000;
     }
 
     struct LineStatus: u8 {
@@ -51,6 +165,7 @@ bitflags::bitflags! {
 /// for the full specification.
 pub struct Serial {
     mode: SerialMode,
+    config: Config,
 
     // DLAB = 0 registers.
     receiver_buffer: x86_64::instructions::port::PortReadOnly<u8>,
@@ -70,16 +185,27 @@ pub struct Serial {
 }
 
 impl Serial {
-    const BASE_BAUD_RATE: u32 = 1_843_200; // 1.8432 MHz
-    const BAUD_RATE: u32 = 9_600; // 9.6 kbps
+    const BASE_BAUD_RATE: u32 = 1_843_200 / 16; // 1.8432 MHz, divided by 16
+
+    /// Size of the transmit FIFO of the 16550A.
+    const FIFO_SIZE: usize = 16;
 
-    /// Creates a new serial port connected to 0x3F8 (COM1).
+    /// Byte sent through the loopback to test the UART.
+    const TEST_BYTE: u8 = 0xAE;
+
+    /// Number of times to poll the line status before giving up on the UART.
+    /// A byte takes about 33 ms to go through at 300 bps, which is well within
+    /// this many port reads.
+    const POLL_LIMIT: usize = 1_000_000;
+
+    /// Creates a new serial port, configured by `config`.
     #[must_use = "Serial port must be initialized before use"]
-    pub const fn new() -> Self {
-        let io_base = 0x3F8u16;
+    pub const fn new(config: Config) -> Self {
+        let io_base = config.port.io_base();
 
         Self {
             mode: SerialMode::Uninitialized,
+            config,
 
             receiver_buffer: x86_64::instructions::port::PortReadOnly::new(io_base),
             transmitter_holding: x86_64::instructions::port::PortWriteOnly::new(io_base),
@@ -96,11 +222,23 @@ impl Serial {
         }
     }
 
+    /// Creates a handle to a serial port which someone else, e.g. the
+    /// console, already initialized with `config`. It transmits by polling,
+    /// leaving the port as it is.
+    pub const fn attach(config: Config) -> Self {
+        let mut serial = Self::new(config);
+        serial.mode = SerialMode::Poll;
+        serial
+    }
+
     /// Initializes the serial port to a polling mode.
     ///
     /// Polling mode busy-waits for the serial port to become free before
     /// writing to it. It's slow, but until interrupts are enabled, it's all we
     /// can do.
+    ///
+    /// The UART is tested through its loopback first. If it does not answer,
+    /// the port is marked absent, and anything sent to it is dropped.
     pub fn init_poll(&mut self) {
         assert_eq!(self.mode, SerialMode::Uninitialized);
 
@@ -108,19 +246,93 @@ impl Serial {
             // Turn off all interrupts.
             self.interrupt_enable.write(InterruptEnable::empty().bits());
 
-            // Disable FIFO.
+            // Disable FIFO, until we know it works.
             self.fifo_control.write(FifoControl::empty().bits());
 
             // Set baud rate.
-            self.set_baud_rate(Self::BAUD_RATE);
+            self.set_baud_rate(self.config.baud_rate);
+        }
+
+        if !self.self_test() {
+            self.mode = SerialMode::Absent;
+            return;
+        }
 
+        unsafe {
             // Enable OUT2 (required for interrupts).
             self.modem_control.write(ModemControl::OUT2.bits());
         }
 
+        if let Some(trigger) = self.config.fifo {
+            if !self.enable_fifo(trigger) {
+                self.config.fifo = None;
+            }
+        }
+
         self.mode = SerialMode::Poll;
     }
 
+    /// Returns the configuration of the serial port. The FIFOs are off if
+    /// the UART turned out not to have working ones.
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Returns `true` unless the UART failed its self-test.
+    pub fn is_present(&self) -> bool {
+        self.mode != SerialMode::Absent
+    }
+
+    /// Returns `true` if the FIFOs are enabled.
+    pub fn has_fifo(&self) -> bool {
+        self.config.fifo.is_some()
+    }
+
+    /// Sends [`Self::TEST_BYTE`] to the UART in loopback mode, and returns
+    /// `true` if it comes back.
+    fn self_test(&mut self) -> bool {
+        unsafe {
+            self.modem_control.write(ModemControl::LOOPBACK.bits());
+
+            // Discard whatever was received before.
+            self.receiver_buffer.read();
+
+            self.transmitter_holding.write(Self::TEST_BYTE);
+            let received = (0..Self::POLL_LIMIT).any(|_| {
+                LineStatus::from_bits_truncate(self.line_status.read())
+                    .contains(LineStatus::DATA_READY)
+            });
+            let passed = received && self.receiver_buffer.read() == Self::TEST_BYTE;
+
+            self.modem_control.write(ModemControl::empty().bits());
+            passed
+        }
+    }
+
+    /// Enables the FIFOs with the receive `trigger` level, if the UART is a
+    /// 16550A. Returns `true` if it is.
+    fn enable_fifo(&mut self, trigger: FifoTrigger) -> bool {
+        unsafe {
+            self.fifo_control.write(FifoControl::ENABLE.bits());
+
+            let iir =
+                InterruptIdentification::from_bits_truncate(self.interrupt_identification.read());
+            if !iir.contains(InterruptIdentification::FIFOS_ENABLED) {
+                self.fifo_control.write(FifoControl::empty().bits());
+                return false;
+            }
+
+            self.fifo_control.write(
+                (FifoControl::ENABLE
+                    | FifoControl::CLEAR_RECEIVER
+                    | FifoControl::CLEAR_TRANSMITTER
+                    | trigger.bits())
+                .bits(),
+            );
+        }
+        true
+    }
+
     fn set_baud_rate(&mut self, baud_rate: u32) {
         use core::convert::TryFrom;
 
@@ -143,38 +355,127 @@ impl Serial {
         }
     }
 
+    /// Initializes the serial port to a queued, interrupt-driven mode.
+    ///
+    /// Bytes sent are put into a queue, which the serial interrupt drains as
+    /// the port becomes ready. Must be called after the interrupt system is
+    /// initialized. Only one serial port may be in queue mode.
+    pub fn init_queue(&mut self) {
+        if self.mode == SerialMode::Uninitialized {
+            self.init_poll();
+        }
+        if self.mode == SerialMode::Absent {
+            return;
+        }
+        assert_eq!(self.mode, SerialMode::Poll);
+
+        let irq = self.config.port.irq();
+        interrupt::REGISTRY
+            .lock()
+            .register(irq as usize + 0x20, serial_interrupt, "serial");
+        interrupt::enable_irq(irq);
+
+        self.mode = SerialMode::Queue;
+        crate::without_interrupts!({
+            let mut queued = QUEUED.lock();
+            assert!(queued.is_none());
+            *queued = Some(self.config);
+        });
+        notify();
+    }
+
     /// Sends a byte to the serial port.
+    ///
+    /// In queue mode, the byte is put into the transmit queue, sleeping while
+    /// it is full. But if interrupts are off, or the kernel is panicking, the
+    /// queue may never drain, so the queue is flushed and the byte sent by
+    /// polling instead.
     pub fn send(&mut self, data: u8) {
         match self.mode {
             SerialMode::Poll => self.send_poll(data),
-            SerialMode::Queue => todo!(),
+            SerialMode::Queue => {
+                if interrupt::are_disabled() || FORCE_POLL.load(Ordering::Relaxed) {
+                    flush();
+                    self.send_poll(data);
+                } else {
+                    crate::without_interrupts!({
+                        let mut txq = TX_QUEUE.lock();
+                        txq.putc(data);
+                        self.write_ier(&txq);
+                    });
+                }
+            }
             SerialMode::Uninitialized => {
                 self.init_poll();
-                self.send_poll(data);
+                self.send(data);
+            }
+            SerialMode::Absent => {}
+        }
+    }
+
+    /// Returns the byte received by the serial port, if any, without waiting.
+    ///
+    /// It reads the port directly, so it works even with interrupts disabled.
+    pub fn receive_poll(&mut self) -> Option<u8> {
+        if self.mode == SerialMode::Uninitialized {
+            self.init_poll();
+        }
+        if self.mode == SerialMode::Absent {
+            return None;
+        }
+
+        unsafe {
+            if LineStatus::from_bits_truncate(self.line_status.read())
+                .contains(LineStatus::DATA_READY)
+            {
+                Some(self.receiver_buffer.read())
+            } else {
+                None
             }
         }
     }
 
     /// Polls the serial port until it's ready, and then transmits the given
-    /// byte.
+    /// byte. If the port is not ready after [`Self::POLL_LIMIT`] polls, it
+    /// must have gone away, so the byte is sent anyway rather than waiting
+    /// forever.
     fn send_poll(&mut self, data: u8) {
-        assert_eq!(self.mode, SerialMode::Poll);
-
         unsafe {
             // Wait until the transmitter holding register is empty.
-            while !LineStatus::from_bits_truncate(self.line_status.read())
-                .intersects(LineStatus::TRANSMITTER_EMPTY)
-            {}
+            for _ in 0..Self::POLL_LIMIT {
+                if LineStatus::from_bits_truncate(self.line_status.read())
+                    .contains(LineStatus::TRANSMITTER_EMPTY)
+                {
+                    break;
+                }
+            }
 
             // Send the byte.
             self.transmitter_holding.write(data);
         }
     }
+
+    /// Enables the interrupts the serial port needs: transmitter holding
+    /// register empty, if there are bytes to transmit, and received data
+    /// available, if there is room to store them.
+    fn write_ier(&mut self, txq: &IntQueue) {
+        let mut ier = InterruptEnable::empty();
+        if !txq.is_empty() {
+            ier |= InterruptEnable::TRANSMITTER_HOLDING_EMPTY;
+        }
+        if !input::is_full() {
+            ier |= InterruptEnable::RECEIVED_DATA_AVAILABLE;
+        }
+
+        unsafe {
+            self.interrupt_enable.write(ier.bits());
+        }
+    }
 }
 
 impl core::default::Default for Serial {
     fn default() -> Self {
-        Self::new()
+        Self::new(Config::new())
     }
 }
 
@@ -186,3 +487,88 @@ impl core::fmt::Write for Serial {
         Ok(())
     }
 }
+
+/// Bytes waiting to be transmitted, in queue mode.
+static TX_QUEUE: interrupt::Mutex<IntQueue> = interrupt::Mutex::new(IntQueue::new());
+
+/// Configuration of the serial port in queue mode, if any.
+static QUEUED: interrupt::Mutex<Option<Config>> = interrupt::Mutex::new(None);
+
+/// Whether to transmit by polling, even in queue mode.
+static FORCE_POLL: AtomicBool = AtomicBool::new(false);
+
+/// Makes the serial port transmit by polling from now on, e.g. during a
+/// panic, when interrupts may never come back.
+pub fn force_poll() {
+    FORCE_POLL.store(true, Ordering::Relaxed);
+    flush();
+}
+
+/// Transmits all the bytes in the transmit queue, by polling.
+///
+/// Called before the machine goes down, so that no output is lost.
+pub fn flush() {
+    crate::without_interrupts!({
+        if let Some(config) = *QUEUED.lock() {
+            let mut serial = Serial::attach(config);
+            let mut txq = TX_QUEUE.lock();
+            while !txq.is_empty() {
+                serial.send_poll(txq.getc());
+            }
+        }
+    });
+}
+
+/// Updates the serial interrupts, in queue mode, after the transmit queue or
+/// the input buffer changed, like Pintos' `serial_notify()`.
+pub fn notify() {
+    crate::without_interrupts!({
+        if let Some(config) = *QUEUED.lock() {
+            Serial::attach(config).write_ier(&TX_QUEUE.lock());
+        }
+    });
+}
+
+/// Serial interrupt handler: stores the received bytes, as long as there is
+/// room in the input buffer, and transmits the queued bytes, as long as the
+/// transmitter holding register is empty, filling the transmit FIFO at once
+/// if there is one.
+fn serial_interrupt(frame: &mut interrupt::Frame) {
+    let config = (*QUEUED.lock()).expect("no serial port in queue mode");
+    let mut serial = Serial::attach(config);
+
+    unsafe {
+        // Acknowledge the interrupt.
+        serial.interrupt_identification.read();
+
+        while !input::is_full()
+            && LineStatus::from_bits_truncate(serial.line_status.read())
+                .contains(LineStatus::DATA_READY)
+        {
+            let byte = serial.receiver_buffer.read();
+            input::putc(byte);
+            debug::monitor::receive(byte, frame);
+        }
+    }
+
+    let mut txq = TX_QUEUE.lock();
+    unsafe {
+        if LineStatus::from_bits_truncate(serial.line_status.read())
+            .contains(LineStatus::TRANSMITTER_EMPTY)
+        {
+            let room = if serial.has_fifo() {
+                Serial::FIFO_SIZE
+            } else {
+                1
+            };
+            for _ in 0..room {
+                if txq.is_empty() {
+                    break;
+                }
+                serial.transmitter_holding.write(txq.getc());
+            }
+        }
+    }
+
+    serial.write_ier(&txq);
+}
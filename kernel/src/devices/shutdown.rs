@@ -1,9 +1,13 @@
-use crate::{console::CONSOLE, println, threads::SCHEDULER};
+use crate::{
+    console, println,
+    threads::{interrupt, SCHEDULER},
+};
 
-use super::timer::TIMER;
+use super::{acpi, block::BLOCKS, keyboard::KEYBOARD, serial, timer::TIMER};
 
-// We configured this by running QEMU with
-// `-device isa-debug-exit,iobase=0xf4,iosize=0x04`.
+// The test runner runs QEMU with
+// `-device isa-debug-exit,iobase=0xf4,iosize=0x04`, and we write to it with
+// the `isa-debug-exit` feature on.
 const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
 
 // We use 0x31 as the exit code.
@@ -12,37 +16,63 @@ const ISA_DEBUG_EXIT_CODE_SUCCESS: u8 = 0x31;
 // Exit code notifying the unexpected panic.
 const ISA_DEBUG_EXIT_CODE_FAILURE: u8 = 0x42;
 
-/// Powers down the machine we're running on,
-/// as long as we're running on QEMU.
+// Command port of the 8042 keyboard controller.
+const KEYBOARD_CONTROLLER_PORT: u16 = 0x64;
+
+// Command pulsing the CPU reset line.
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xfe;
+
+/// Powers down the machine we're running on, through ACPI.
+///
+/// With the `isa-debug-exit` feature, it exits QEMU instead, with the exit
+/// code the test runner expects from a test which passed.
 pub fn power_off() -> ! {
     print_stats();
 
     println!("Powering off...");
 
-    let mut port = x86_64::instructions::port::Port::new(ISA_DEBUG_EXIT_PORT);
-    unsafe {
-        port.write(ISA_DEBUG_EXIT_CODE_SUCCESS);
-    }
-
-    // If we're not running on QEMU, we'll just loop forever.
-    loop {
-        x86_64::instructions::hlt();
-    }
+    serial::flush();
+    exit(ISA_DEBUG_EXIT_CODE_SUCCESS)
 }
 
-/// Powers down the machine we're running on,
-/// but we are exiting to the host with an error code.
+/// Powers down the machine we're running on, like [`power_off()`], but
+/// exits QEMU with the exit code of a failed test.
 pub fn power_off_with_failure() -> ! {
     print_stats();
 
     println!("Powering off with failure...");
 
-    let mut port = x86_64::instructions::port::Port::new(ISA_DEBUG_EXIT_PORT);
+    serial::flush();
+    exit(ISA_DEBUG_EXIT_CODE_FAILURE)
+}
+
+/// Powers down the machine right away, exiting to the host with an error
+/// code.
+///
+/// Unlike [`power_off_with_failure()`], it neither prints nor flushes
+/// anything, so it works even when the console is what is broken, e.g. after
+/// a panic while panicking.
+pub fn abort() -> ! {
+    exit(ISA_DEBUG_EXIT_CODE_FAILURE)
+}
+
+/// Reboots the machine we're running on, through the ACPI reset register,
+/// or else by pulsing the CPU reset line through the keyboard controller.
+///
+/// It does not print anything, so it may be called even in an interrupt
+/// handler.
+pub fn reboot() -> ! {
+    serial::flush();
+
+    acpi::reset();
+
+    let mut port = x86_64::instructions::port::Port::new(KEYBOARD_CONTROLLER_PORT);
     unsafe {
-        port.write(ISA_DEBUG_EXIT_CODE_FAILURE);
+        port.write(KEYBOARD_CONTROLLER_RESET);
     }
 
-    // If we're not running on QEMU, we'll just loop forever.
+    // If the keyboard controller did not reset the machine, we'll just loop
+    // forever.
     loop {
         x86_64::instructions::hlt();
     }
@@ -52,5 +82,26 @@ pub fn power_off_with_failure() -> ! {
 fn print_stats() {
     TIMER.lock().print_stats();
     SCHEDULER.lock().print_stats();
-    CONSOLE.lock().print_stats();
+    interrupt::REGISTRY.lock().print_stats();
+    KEYBOARD.lock().print_stats();
+    BLOCKS.lock().print_stats();
+    console::with_console(|console| console.print_stats());
+}
+
+/// Exits QEMU with `code`, if the `isa-debug-exit` feature is on, or powers
+/// off the machine.
+fn exit(code: u8) -> ! {
+    if cfg!(feature = "isa-debug-exit") {
+        let mut port = x86_64::instructions::port::Port::new(ISA_DEBUG_EXIT_PORT);
+        unsafe {
+            port.write(code);
+        }
+    }
+
+    acpi::power_off();
+
+    // If neither worked, we'll just loop forever.
+    loop {
+        x86_64::instructions::hlt();
+    }
 }
@@ -0,0 +1,88 @@
+use core::time::Duration;
+
+use crate::threads::interrupt;
+
+use super::{
+    pit::{Channel, Mode, PIT},
+    timer,
+};
+
+/// PC speaker control port.
+const GATE_PORT: u16 = 0x61;
+
+/// Lowest and highest frequencies the speaker plays, in Hz.
+const MIN_FREQUENCY: usize = 20;
+const MAX_FREQUENCY: usize = 20_000;
+
+/// Frequency of [`beep()`], in Hz.
+pub const BEEP_FREQUENCY: usize = 440;
+
+bitflags::bitflags! {
+    struct Gate: u8 {
+        /// Lets PIT channel 2 count.
+        const TIMER2 = 0b0000_0001;
+
+        /// Connects PIT channel 2's output to the speaker.
+        const SPEAKER = 0b0000_0010;
+
+        /// Level of PIT channel 2's output, when read.
+        const OUT2 = 0b0010_0000;
+    }
+}
+
+/// Sets the PC speaker to emit a tone at the given `frequency`, in Hz, like
+/// Pintos' `speaker_on()`. Frequencies the speaker cannot play turn it off.
+pub fn on(frequency: usize) {
+    if !(MIN_FREQUENCY..=MAX_FREQUENCY).contains(&frequency) {
+        off();
+        return;
+    }
+
+    PIT.lock()
+        .configure(Channel::OUT2, Mode::SquareWave, frequency);
+    let enable = (Gate::TIMER2 | Gate::SPEAKER).bits();
+    crate::without_interrupts!(write_gate(read_gate() | enable));
+}
+
+/// Turns off the PC speaker, by disconnecting the timer channel's output
+/// from the speaker.
+pub fn off() {
+    let enable = (Gate::TIMER2 | Gate::SPEAKER).bits();
+    crate::without_interrupts!(write_gate(read_gate() & !enable));
+}
+
+/// Returns `true` if the PC speaker is on.
+pub fn is_on() -> bool {
+    Gate::from_bits_truncate(read_gate()).contains(Gate::TIMER2 | Gate::SPEAKER)
+}
+
+/// Returns the level of PIT channel 2's output, as the speaker sees it.
+pub fn output() -> bool {
+    Gate::from_bits_truncate(read_gate()).contains(Gate::OUT2)
+}
+
+/// Plays a tone at `frequency` Hz for `duration`, like Pintos'
+/// `speaker_beep()`.
+///
+/// It sleeps meanwhile, so nothing is played in an external interrupt
+/// handler, which cannot.
+pub fn beep(frequency: usize, duration: Duration) {
+    if interrupt::is_external_handler_context() {
+        return;
+    }
+
+    on(frequency);
+    timer::usleep(duration.as_micros() as u64);
+    off();
+}
+
+/// Reads the control port, whose other bits must be written back as read.
+fn read_gate() -> u8 {
+    let mut port = x86_64::instructions::port::Port::<u8>::new(GATE_PORT);
+    unsafe { port.read() }
+}
+
+fn write_gate(gate: u8) {
+    let mut port = x86_64::instructions::port::Port::<u8>::new(GATE_PORT);
+    unsafe { port.write(gate) }
+}
@@ -1,39 +1,176 @@
+use core::time::Duration;
+
 use crate::{
-    println,
-    threads::{interrupt, SCHEDULER},
+    get_list_element, println,
+    threads::{
+        interrupt::{self, LocalApic, TimerDivide, TimerMode},
+        thread, SCHEDULER,
+    },
+    utils::data_structures::linked_list::LinkedList,
 };
 
-use super::pit::{Channel, Mode, PIT};
+use super::{
+    clock::{self, Instant},
+    pit::{Channel, Mode, Pit, PIT},
+};
 
 /// Number of timer interrupts per second.
 pub const FREQUENCY: usize = 100;
 
+/// Number of nanoseconds in a second.
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Number of PIT counts in a timer tick.
+pub const COUNTS_PER_TICK: usize = (Pit::INPUT_FREQUENCY + FREQUENCY / 2) / FREQUENCY;
+
+/// Maximum number of ticks the PIT can wait for in one-shot mode, before its
+/// 16-bit counter overflows.
+///
+/// That is only 5 ticks at 100 Hz, so an idle CPU still wakes up about 20
+/// times per second on the PICs. The local APIC timer's 32-bit counter waits
+/// for more than a minute, so it is the one to stop the tick for long.
+const MAX_PIT_TICKLESS_TICKS: usize = Pit::MAX_COUNT as usize / COUNTS_PER_TICK;
+
+/// Interrupt vector of the timer: that of ISA interrupt 0, which the PIT is
+/// wired to. The local APIC timer interrupts to it too.
+const VECTOR: u8 = 0x20;
+
+/// Divisor of the bus clock for the local APIC timer.
+const LOCAL_APIC_DIVIDE: TimerDivide = TimerDivide::By16;
+
+/// Hardware interrupting at each timer tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    /// The PIT, on ISA interrupt line 0.
+    Pit,
+
+    /// The local APIC timer, counting down `counts_per_tick` in a tick. The
+    /// PIT is left stopped.
+    LocalApic { counts_per_tick: u32 },
+}
+
 /// Sets up the timer to interrupt [`FREQUENCY`] times per second, and
 /// registers the corresponding interrupt.
+///
+/// The local APIC timer drives the ticks if the APIC controller is active,
+/// after measuring its rate against the PIT. Otherwise, the PIT does.
 pub fn init() {
-    PIT.lock()
-        .configure(Channel::OUT0, Mode::RateGenerator, FREQUENCY);
+    let source = match interrupt::CONTROLLER.lock().apic() {
+        Some(apic) => match calibrate_local_apic(apic.local()) {
+            0 => Source::Pit,
+            counts_per_tick => Source::LocalApic { counts_per_tick },
+        },
+        None => Source::Pit,
+    };
 
+    let name = match source {
+        Source::Pit => "8254 Timer",
+        Source::LocalApic { counts_per_tick } => {
+            log::info!("timer: local APIC timer at {counts_per_tick} counts per tick.");
+            "Local APIC Timer"
+        }
+    };
     interrupt::REGISTRY
         .lock()
-        .register(0x20, interrupt, "8254 Timer");
+        .register(VECTOR as usize, interrupt, name);
+
+    TIMER.lock().start(source);
+    if source == Source::Pit {
+        interrupt::enable_irq(0);
+    }
+}
+
+/// Returns the number of counts the local APIC timer counts down in a timer
+/// tick, timed by the PIT counting down the same ticks in one-shot mode.
+///
+/// Leaves the PIT stopped, its count over.
+fn calibrate_local_apic(local: &mut LocalApic) -> u32 {
+    /// Number of timer ticks to measure over. The PIT's counter holds them.
+    const CALIBRATION_TICKS: usize = 5;
+
+    let mut pit = PIT.lock();
+
+    local.set_timer_divide(LOCAL_APIC_DIVIDE);
+    pit.load(
+        Channel::OUT0,
+        Mode::InterruptOnTerminalCount,
+        (CALIBRATION_TICKS * COUNTS_PER_TICK) as u16,
+    );
+    local.start_timer(VECTOR, TimerMode::OneShot, u32::MAX);
+
+    // The PIT's output goes up once its count is over.
+    while !pit.read_status(Channel::OUT0).0.output {
+        core::hint::spin_loop();
+    }
+    let counts = u32::MAX - local.timer_current_count();
+    local.stop_timer();
+
+    counts / CALIBRATION_TICKS as u32
+}
+
+/// Runs `f` on the local APIC, whose timer drives the ticks.
+fn with_local_apic<R>(f: impl FnOnce(&mut LocalApic) -> R) -> R {
+    let mut controller = interrupt::CONTROLLER.lock();
+    let apic = controller
+        .apic()
+        .expect("Local APIC timer needs the APIC controller");
+    f(apic.local())
 }
 
 /// Manages the ticks and calibration.
 pub struct Timer {
+    /// Hardware interrupting at each tick.
+    source: Source,
+
     /// Number of timer ticks since OS booted.
     ticks: usize,
+
+    /// Threads sleeping in [`sleep()`], ordered by the tick to wake up.
+    sleepers: LinkedList<thread::Thread>,
+
+    /// Number of ticks the timer is programmed to wait for in one-shot mode,
+    /// while the CPU is idle. `None` if it is interrupting periodically.
+    tickless: Option<usize>,
+
+    /// Number of timer interrupts skipped while the CPU was idle.
+    skipped_ticks: usize,
 }
 
 impl Timer {
     /// Creates a new [`Timer`].
     pub const fn new() -> Timer {
-        Self { ticks: 0 }
+        Self {
+            source: Source::Pit,
+            ticks: 0,
+            sleepers: LinkedList::new(),
+            tickless: None,
+            skipped_ticks: 0,
+        }
+    }
+
+    /// Starts interrupting periodically, from `source`.
+    fn start(&mut self, source: Source) {
+        self.source = source;
+        self.start_periodic();
     }
 
     /// Timer tick.
-    pub fn tick(&mut self) {
-        self.ticks += 1;
+    ///
+    /// Returns the number of ticks elapsed since the last timer interrupt,
+    /// which is more than one if the timer was in tickless mode.
+    pub fn tick(&mut self) -> usize {
+        let ticks = if let Some(ticks) = self.tickless.take() {
+            // The one-shot interrupt fired, so all of the ticks have elapsed,
+            // with one interrupt instead of one for each.
+            self.start_periodic();
+            self.skipped_ticks += ticks - 1;
+            ticks
+        } else {
+            1
+        };
+
+        self.advance(ticks);
+        ticks
     }
 
     /// Returns the number of timer ticks since the OS booted.
@@ -46,9 +183,211 @@ impl Timer {
         self.ticks() - then
     }
 
+    /// Returns the number of ticks which elapsed without a timer interrupt,
+    /// while the CPU was idle.
+    pub fn skipped_ticks(&mut self) -> usize {
+        self.skipped_ticks
+    }
+
+    /// Returns the number of PIT counts elapsed since the OS booted. This is
+    /// [`Timer::ticks()`] plus the counts elapsed in the current tick, so it is
+    /// much finer grained. The local APIC timer's counts are converted to
+    /// PIT counts.
+    ///
+    /// If the timer interrupt is pending, the current tick has wrapped around
+    /// but is not yet counted, so the result may go backwards by a tick.
+    pub fn counts(&mut self) -> u64 {
+        let period = self.tickless.unwrap_or(1) * COUNTS_PER_TICK;
+        let elapsed = self
+            .remaining_counts()
+            .map_or(period, |remaining| period.saturating_sub(remaining));
+
+        (self.ticks * COUNTS_PER_TICK + elapsed) as u64
+    }
+
+    /// Stops the periodic timer interrupt, if no sleeping thread is due soon.
+    /// The timer is programmed to interrupt once, at the next wake-up time of a
+    /// sleeping thread or as late as its counter allows.
+    ///
+    /// Called by the idle thread with interrupts off, right before halting.
+    pub fn enter_tickless(&mut self) {
+        assert!(interrupt::are_disabled());
+        assert!(self.tickless.is_none());
+
+        let max_ticks = self.max_tickless_ticks();
+        let ticks = self
+            .next_wakeup()
+            .map_or(max_ticks, |wakeup| wakeup.saturating_sub(self.ticks))
+            .min(max_ticks);
+
+        // The next periodic interrupt is due anyway.
+        if ticks <= 1 {
+            return;
+        }
+
+        self.start_one_shot(ticks);
+        self.tickless = Some(ticks);
+    }
+
+    /// Resumes the periodic timer interrupt, if the CPU was woken up by some
+    /// other interrupt before the one-shot timer interrupt fired.
+    ///
+    /// The ticks elapsed so far are read back from the timer's counter, and
+    /// returned. The partially elapsed tick is dropped. If the one-shot wait
+    /// is over, its interrupt is pending, and the handler counts the ticks
+    /// instead.
+    pub fn exit_tickless(&mut self) -> usize {
+        assert!(interrupt::are_disabled());
+
+        let ticks = match self.tickless {
+            Some(ticks) => ticks,
+            None => return 0,
+        };
+        let remaining = match self.remaining_counts() {
+            Some(remaining) => remaining,
+            None => return 0,
+        };
+
+        self.tickless = None;
+        self.start_periodic();
+
+        let counts = ticks * COUNTS_PER_TICK;
+        let elapsed = counts.saturating_sub(remaining) / COUNTS_PER_TICK;
+
+        // No timer interrupt fired for any of them.
+        self.skipped_ticks += elapsed;
+        self.advance(elapsed);
+        elapsed
+    }
+
+    /// Busy-waits for `duration`, following the timer's counter from tick to
+    /// tick rather than the ticks, so that it works with interrupts off.
+    ///
+    /// The counter must be read at least once per tick, so interrupt handlers
+    /// must not hold us up for that long meanwhile.
+    pub fn delay(&mut self, duration: Duration) {
+        assert!(self.tickless.is_none());
+
+        let mut counts = duration.as_nanos() * Pit::INPUT_FREQUENCY as u128 / NANOS_PER_SEC;
+        let mut last = self.remaining_counts().unwrap();
+        while counts > 0 {
+            let remaining = self.remaining_counts().unwrap();
+
+            // The counter counts down, and starts over at each tick.
+            let elapsed = (last + COUNTS_PER_TICK - remaining) % COUNTS_PER_TICK;
+            counts = counts.saturating_sub(elapsed as u128);
+            last = remaining;
+        }
+    }
+
     /// Prints timer statistics.
     pub fn print_stats(&mut self) {
-        println!("Timer: {} ticks", self.ticks());
+        println!(
+            "Timer: {} ticks ({} skipped while idle)",
+            self.ticks(),
+            self.skipped_ticks
+        );
+    }
+
+    /// Advances the clock by `ticks`, waking up the sleeping threads which
+    /// are due.
+    fn advance(&mut self, ticks: usize) {
+        self.ticks += ticks;
+
+        while let Some(node) = self.sleepers.front_mut() {
+            let thread = get_list_element!(node, thread::Thread, status_list_node);
+            if thread.wakeup_ticks > self.ticks {
+                break;
+            }
+
+            self.sleepers.pop_front();
+            SCHEDULER.lock().unblock(thread);
+        }
+    }
+
+    /// Adds `thread` to the sleepers, to be woken up at `wakeup_ticks`.
+    fn add_sleeper(&mut self, thread: &'static mut thread::Thread, wakeup_ticks: usize) {
+        thread.wakeup_ticks = wakeup_ticks;
+
+        // Keep the sleepers ordered by their wake-up time.
+        let mut cursor = self.sleepers.cursor_mut();
+        cursor.move_next();
+        while let Some(node) = cursor.current() {
+            if get_list_element!(node, thread::Thread, status_list_node).wakeup_ticks > wakeup_ticks
+            {
+                break;
+            }
+            cursor.move_next();
+        }
+        cursor.insert_before(&mut thread.status_list_node);
+    }
+
+    /// Returns the earliest tick at which a sleeping thread wakes up.
+    fn next_wakeup(&mut self) -> Option<usize> {
+        self.sleepers
+            .front_mut()
+            .map(|node| get_list_element!(node, thread::Thread, status_list_node).wakeup_ticks)
+    }
+
+    /// Programs the timer to interrupt at each tick.
+    fn start_periodic(&mut self) {
+        match self.source {
+            Source::Pit => PIT
+                .lock()
+                .configure(Channel::OUT0, Mode::RateGenerator, FREQUENCY),
+            Source::LocalApic { counts_per_tick } => with_local_apic(|local| {
+                local.start_timer(VECTOR, TimerMode::Periodic, counts_per_tick)
+            }),
+        }
+    }
+
+    /// Programs the timer to interrupt once, `ticks` from now.
+    fn start_one_shot(&mut self, ticks: usize) {
+        match self.source {
+            Source::Pit => PIT.lock().load(
+                Channel::OUT0,
+                Mode::InterruptOnTerminalCount,
+                (ticks * COUNTS_PER_TICK) as u16,
+            ),
+            Source::LocalApic { counts_per_tick } => with_local_apic(|local| {
+                local.start_timer(VECTOR, TimerMode::OneShot, ticks as u32 * counts_per_tick)
+            }),
+        }
+    }
+
+    /// Returns the maximum number of ticks the timer can wait for in one-shot
+    /// mode.
+    fn max_tickless_ticks(&self) -> usize {
+        match self.source {
+            Source::Pit => MAX_PIT_TICKLESS_TICKS,
+            Source::LocalApic { counts_per_tick } => (u32::MAX / counts_per_tick) as usize,
+        }
+    }
+
+    /// Returns the number of PIT counts left until the timer interrupts, or
+    /// `None` if the one-shot wait is over and its interrupt is pending.
+    fn remaining_counts(&mut self) -> Option<usize> {
+        match self.source {
+            Source::Pit => {
+                let mut pit = PIT.lock();
+                if self.tickless.is_none() {
+                    return Some(pit.read_count(Channel::OUT0) as usize);
+                }
+
+                // In one-shot mode, the output goes up once the count is
+                // over, and stays up, while the counter wraps around.
+                let (status, remaining) = pit.read_status(Channel::OUT0);
+                (!status.output).then_some(remaining as usize)
+            }
+            Source::LocalApic { counts_per_tick } => {
+                // In one-shot mode, the count stays at zero once over.
+                let remaining = with_local_apic(|local| local.timer_current_count());
+                if self.tickless.is_some() && remaining == 0 {
+                    return None;
+                }
+                Some((remaining as u64 * COUNTS_PER_TICK as u64 / counts_per_tick as u64) as usize)
+            }
+        }
     }
 }
 
@@ -60,14 +399,81 @@ pub static TIMER: interrupt::Mutex<Timer> = interrupt::Mutex::new(Timer::new());
 pub fn sleep(ticks: usize) {
     assert!(interrupt::are_enabled());
 
-    let start = TIMER.lock().ticks();
-    while TIMER.lock().elapsed(start) < ticks {
-        SCHEDULER.lock().yield_current_thread();
+    if ticks == 0 {
+        return;
+    }
+
+    crate::without_interrupts!({
+        let mut timer = TIMER.lock();
+        let wakeup_ticks = timer.ticks().saturating_add(ticks);
+        timer.add_sleeper(thread::current_thread(), wakeup_ticks);
+
+        SCHEDULER.lock().block_current_thread();
+    });
+}
+
+/// Sleeps for approximately `ms` milliseconds.
+/// Interrupts must be turned on, unless it is shorter than a timer tick.
+pub fn msleep(ms: u64) {
+    real_time_sleep(Duration::from_millis(ms));
+}
+
+/// Sleeps for approximately `us` microseconds.
+/// Interrupts must be turned on, unless it is shorter than a timer tick.
+pub fn usleep(us: u64) {
+    real_time_sleep(Duration::from_micros(us));
+}
+
+/// Sleeps for approximately `ns` nanoseconds.
+/// Interrupts must be turned on, unless it is shorter than a timer tick.
+pub fn nsleep(ns: u64) {
+    real_time_sleep(Duration::from_nanos(ns));
+}
+
+/// Sleeps for `duration`, like Pintos' `real_time_sleep()`: blocks for the
+/// whole timer ticks in it, and busy-waits for the remainder, which is too
+/// short to sleep for.
+///
+/// Durations shorter than a tick only busy-wait, so they need no interrupts.
+fn real_time_sleep(duration: Duration) {
+    // Deadlines past what the clock counts never come.
+    let deadline = Instant::now().checked_add(duration).unwrap_or(Instant::MAX);
+
+    let ticks =
+        (duration.as_nanos() * FREQUENCY as u128 / NANOS_PER_SEC).min(usize::MAX as u128) as usize;
+    if ticks > 0 {
+        sleep(ticks);
+    }
+
+    clock::delay_until(deadline);
+}
+
+/// Halts the CPU until the next interrupt, stopping the periodic timer
+/// interrupt meanwhile if no sleeping thread is due soon.
+///
+/// Called by the idle thread.
+pub fn idle() {
+    interrupt::disable();
+
+    TIMER.lock().enter_tickless();
+
+    // Re-enable interrupts and wait for the next one.
+    //
+    // `sti` disables interrupts until the completion of the next instruction,
+    // so these two instructions are executed atomically. This atomicity is
+    // important; otherwise, an interrupt could be handled between re-enabling
+    // interrupts and waiting for the next one to occur, wasting as much as one
+    // clock tick worth of time (or the whole tickless period).
+    x86_64::instructions::interrupts::enable_and_hlt();
+
+    let ticks = TIMER.lock().exit_tickless();
+    if ticks > 0 {
+        SCHEDULER.lock().tick(ticks);
     }
 }
 
 /// Timer interrupt handler.
-fn interrupt(_frame: x86_64::structures::idt::InterruptStackFrame) {
-    TIMER.lock().tick();
-    SCHEDULER.lock().tick();
+fn interrupt(_frame: &mut interrupt::Frame) {
+    let ticks = TIMER.lock().tick();
+    SCHEDULER.lock().tick(ticks);
 }
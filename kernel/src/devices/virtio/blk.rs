@@ -0,0 +1,424 @@
+use crate::{
+    devices::{
+        block::{self, BlockDevice, ByteSize, SECTOR_SIZE},
+        pci,
+    },
+    threads::{
+        addr::{vtop, VirtAddr, PAGE_SIZE},
+        interrupt, AllocateFlags, Semaphore, PAGE_ALLOCATOR,
+    },
+};
+
+use super::{
+    queue::{Buffer, Virtqueue},
+    Status, Transport, ISR_QUEUE, VENDOR_ID,
+};
+
+/// Device ID of transitional block devices, which have the legacy
+/// transport.
+const DEVICE_ID_TRANSITIONAL: u16 = 0x1001;
+
+/// Feature bit of read-only devices.
+const FEATURE_RO: u32 = 1 << 5;
+
+/// Offset of the capacity, in sectors, in the device configuration.
+const CONFIG_CAPACITY: u16 = 0x00;
+
+/// Request types.
+const REQUEST_IN: u32 = 0;
+const REQUEST_OUT: u32 = 1;
+
+/// Request status written by the device on success.
+const STATUS_OK: u8 = 0;
+
+/// Maximum number of disks.
+const MAX_DISKS: usize = 4;
+
+/// Maximum number of requests outstanding on each disk.
+const MAX_REQUESTS: usize = 8;
+
+/// Bytes of DMA memory for each request: its header at the start, its
+/// status after it, and the sector from the middle on.
+const REQUEST_SIZE: usize = 2 * SECTOR_SIZE;
+const REQUEST_STATUS: u64 = 16;
+const REQUEST_DATA: u64 = SECTOR_SIZE as u64;
+
+/// Pages of DMA memory for the requests of a disk.
+const REQUEST_PAGES: usize = MAX_REQUESTS * REQUEST_SIZE / PAGE_SIZE;
+
+/// Names of the disks, in the order they are found.
+const DISK_NAMES: [&str; MAX_DISKS] = ["vda", "vdb", "vdc", "vdd"];
+
+static DRIVER: pci::Driver = pci::Driver {
+    name: "virtio-blk",
+    matches: &[pci::Match::Id {
+        vendor_id: VENDOR_ID,
+        device_id: DEVICE_ID_TRANSITIONAL,
+    }],
+    probe,
+};
+
+/// Header of a request, read by the device.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RequestHeader {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// Where a request in flight is.
+#[derive(Debug, Clone, Copy)]
+enum Slot {
+    Free,
+
+    /// Handed to the device, as the chain starting at `head`. The thread
+    /// which made the request waits on `completion`, on its stack.
+    Pending {
+        head: u16,
+        completion: *const Semaphore,
+    },
+
+    /// Used by the device, but not yet looked at by the thread.
+    Done,
+}
+
+/// A disk which the driver took.
+struct State {
+    transport: Transport,
+    queue: Virtqueue,
+    irq: u8,
+
+    /// DMA memory of the requests, one [`REQUEST_SIZE`] for each slot.
+    requests: VirtAddr,
+    slots: [Slot; MAX_REQUESTS],
+}
+
+impl State {
+    fn request(&self, slot: usize) -> VirtAddr {
+        self.requests + (slot * REQUEST_SIZE) as u64
+    }
+}
+
+/// A slot for a disk, whether the driver took one or not.
+struct Device {
+    state: interrupt::Mutex<Option<State>>,
+
+    /// Number of free slots.
+    free_slots: Semaphore,
+}
+
+impl Device {
+    const fn new() -> Self {
+        Self {
+            state: interrupt::Mutex::new(None),
+            free_slots: Semaphore::new(0),
+        }
+    }
+}
+
+static DEVICES: [Device; MAX_DISKS] = [Device::new(), Device::new(), Device::new(), Device::new()];
+
+/// A virtio block device.
+///
+/// Unlike an ATA disk, it takes several requests at once: each thread
+/// making one sleeps until its own is done.
+#[derive(Debug, Clone, Copy)]
+pub struct Disk {
+    /// `vda` to `vdd`.
+    name: &'static str,
+
+    /// Index of the device in [`DEVICES`].
+    index: usize,
+
+    sector_cnt: u32,
+    read_only: bool,
+}
+
+impl Disk {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Hands a request of `kind` for `sector` to the device, and sleeps
+    /// until it is done. Returns the status the device wrote.
+    ///
+    /// The sector goes through the DMA memory of the request: `data` itself
+    /// may be anywhere, e.g. in the kernel image, whose physical address we
+    /// do not know.
+    fn request(&self, kind: u32, sector: u32, data: &mut [u8; SECTOR_SIZE]) -> u8 {
+        let device = &DEVICES[self.index];
+        let completion = Semaphore::new(0);
+
+        device.free_slots.down();
+        let slot = {
+            let mut state = device.state.lock();
+            let state = state.as_mut().unwrap();
+
+            let slot = state
+                .slots
+                .iter()
+                .position(|slot| matches!(slot, Slot::Free))
+                .unwrap();
+            let request = state.request(slot);
+
+            let header = RequestHeader {
+                kind,
+                reserved: 0,
+                sector: sector as u64,
+            };
+            unsafe {
+                core::ptr::write_volatile(request.as_mut_ptr::<RequestHeader>(), header);
+                core::ptr::write_volatile((request + REQUEST_STATUS).as_mut_ptr::<u8>(), 0xff);
+                if kind == REQUEST_OUT {
+                    core::ptr::copy_nonoverlapping(
+                        data.as_ptr(),
+                        (request + REQUEST_DATA).as_mut_ptr::<u8>(),
+                        SECTOR_SIZE,
+                    );
+                }
+            }
+
+            let buffers = [
+                Buffer {
+                    address: vtop(request),
+                    length: core::mem::size_of::<RequestHeader>() as u32,
+                    device_writes: false,
+                },
+                Buffer {
+                    address: vtop(request + REQUEST_DATA),
+                    length: SECTOR_SIZE as u32,
+                    device_writes: kind == REQUEST_IN,
+                },
+                Buffer {
+                    address: vtop(request + REQUEST_STATUS),
+                    length: 1,
+                    device_writes: true,
+                },
+            ];
+            let head = state
+                .queue
+                .add(&buffers)
+                .expect("virtqueue has a chain for each slot");
+            state.slots[slot] = Slot::Pending {
+                head,
+                completion: &completion,
+            };
+            state.transport.notify(state.queue.index());
+            slot
+        };
+
+        completion.down();
+
+        let status = {
+            let mut state = device.state.lock();
+            let state = state.as_mut().unwrap();
+            let request = state.request(slot);
+            state.slots[slot] = Slot::Free;
+
+            unsafe {
+                if kind == REQUEST_IN {
+                    core::ptr::copy_nonoverlapping(
+                        (request + REQUEST_DATA).as_ptr::<u8>(),
+                        data.as_mut_ptr(),
+                        SECTOR_SIZE,
+                    );
+                }
+                core::ptr::read_volatile((request + REQUEST_STATUS).as_ptr::<u8>())
+            }
+        };
+        device.free_slots.up();
+
+        status
+    }
+}
+
+impl BlockDevice for Disk {
+    /// Returns the number of sectors of the disk, which stops at what a
+    /// `u32` counts. Virtio block devices count in 512-byte sectors,
+    /// whatever their block size.
+    fn sector_count(&self) -> u32 {
+        self.sector_cnt
+    }
+
+    fn read(&self, sector: u32, buffer: &mut [u8; SECTOR_SIZE]) {
+        assert!(sector < self.sector_cnt);
+        if self.request(REQUEST_IN, sector, buffer) != STATUS_OK {
+            panic!("{}: disk read failed, sector={}", self.name, sector);
+        }
+    }
+
+    /// Panics if the disk is read-only, rather than letting the device fail
+    /// the request.
+    fn write(&self, sector: u32, buffer: &[u8; SECTOR_SIZE]) {
+        assert!(sector < self.sector_cnt);
+        assert!(!self.read_only, "{}: writing a read-only disk", self.name);
+        let mut data = *buffer;
+        if self.request(REQUEST_OUT, sector, &mut data) != STATUS_OK {
+            panic!("{}: disk write failed, sector={}", self.name, sector);
+        }
+    }
+}
+
+/// Disks found by [`probe()`].
+static DISKS: interrupt::Mutex<[Option<Disk>; MAX_DISKS]> =
+    interrupt::Mutex::new([None; MAX_DISKS]);
+
+/// Registers the driver, which probes the virtio block devices on the PCI
+/// buses.
+///
+/// Interrupts must be on, since the disks interrupt on completion.
+pub fn init() {
+    pci::register_driver(&DRIVER);
+}
+
+/// Returns the disks found.
+pub fn disks() -> impl Iterator<Item = Disk> {
+    let disks = *DISKS.lock();
+    IntoIterator::into_iter(disks).flatten()
+}
+
+/// Returns the disk named `name`, like `vda`.
+pub fn find(name: &str) -> Option<Disk> {
+    disks().find(|disk| disk.name == name)
+}
+
+/// Sets up the virtio block device `device`: its only queue, the DMA
+/// memory of its requests, and its interrupt.
+fn probe(device: &pci::Device) -> bool {
+    let index = match DEVICES
+        .iter()
+        .position(|device| device.state.lock().is_none())
+    {
+        Some(index) => index,
+        None => {
+            log::warn!("{}: too many virtio disks.", device.address);
+            return false;
+        }
+    };
+    let name = DISK_NAMES[index];
+
+    let transport = match Transport::new(device) {
+        Some(transport) => transport,
+        None => return false,
+    };
+    let irq = match device.interrupt_line {
+        Some(irq) if irq < 16 => irq,
+        _ => {
+            log::warn!("{}: no interrupt line.", device.address);
+            return false;
+        }
+    };
+
+    transport.reset();
+    let features = transport.negotiate(FEATURE_RO);
+
+    // Each request takes a chain of three descriptors.
+    if (transport.select_queue(0) as usize) < 3 * MAX_REQUESTS {
+        log::warn!("{}: virtqueue too small.", name);
+        transport.add_status(Status::FAILED);
+        return false;
+    }
+    let queue = Virtqueue::new(&transport, 0);
+    let requests = PAGE_ALLOCATOR.get_pages(REQUEST_PAGES, AllocateFlags::ZERO);
+    let (queue, requests) = match (queue, requests) {
+        (Some(queue), Some(requests)) => (queue, requests.start_address()),
+        _ => {
+            log::warn!("{}: out of memory.", name);
+            transport.add_status(Status::FAILED);
+            return false;
+        }
+    };
+
+    let sector_cnt = transport
+        .read_config_u64(CONFIG_CAPACITY)
+        .min(u32::MAX as u64) as u32;
+    let read_only = features & FEATURE_RO != 0;
+
+    // Disks on the same line share the handler, which looks at them all.
+    let line_is_taken = DEVICES
+        .iter()
+        .any(|device| matches!(&*device.state.lock(), Some(state) if state.irq == irq));
+    *DEVICES[index].state.lock() = Some(State {
+        transport,
+        queue,
+        irq,
+        requests,
+        slots: [Slot::Free; MAX_REQUESTS],
+    });
+    for _ in 0..MAX_REQUESTS {
+        DEVICES[index].free_slots.up();
+    }
+    if !line_is_taken {
+        interrupt::REGISTRY.lock().register_shared(
+            0x20 + irq as usize,
+            virtio_blk_interrupt,
+            "virtio-blk",
+        );
+        interrupt::enable_irq(irq);
+    }
+    transport.add_status(Status::DRIVER_OK);
+
+    log::info!(
+        "{}: {} sectors ({}){}.",
+        name,
+        sector_cnt,
+        ByteSize(sector_cnt as u64 * SECTOR_SIZE as u64),
+        if read_only { ", read-only" } else { "" }
+    );
+
+    let disk = Disk {
+        name,
+        index,
+        sector_cnt,
+        read_only,
+    };
+    DISKS.lock()[index] = Some(disk);
+    block::scan_partitions(block::register(name, block::Kind::Raw, disk));
+    true
+}
+
+/// Virtio block interrupt handler: acknowledges the interrupt of each disk
+/// on the line which raised it, and wakes up the threads whose requests are
+/// done.
+fn virtio_blk_interrupt(frame: &mut interrupt::Frame) -> bool {
+    let irq = frame.vector() - 0x20;
+    let mut handled = false;
+
+    for device in DEVICES.iter() {
+        let mut state = device.state.lock();
+        let state = match state.as_mut() {
+            Some(state) if state.irq == irq => state,
+            _ => continue,
+        };
+
+        // Reading the ISR status acknowledges the interrupt.
+        if state.transport.isr() & ISR_QUEUE == 0 {
+            continue;
+        }
+        handled = true;
+
+        while let Some((head, _)) = state.queue.pop_used() {
+            let slot = state.slots.iter_mut().find(
+                |slot| matches!(**slot, Slot::Pending { head: pending, .. } if pending == head),
+            );
+            match slot {
+                Some(slot) => {
+                    if let Slot::Pending { completion, .. } = *slot {
+                        *slot = Slot::Done;
+                        // The thread waits until it is upped, so the
+                        // semaphore is still on its stack.
+                        unsafe { (*completion).up() };
+                    }
+                }
+                None => log::warn!("virtio-blk: unexpected used chain {}", head),
+            }
+        }
+    }
+
+    handled
+}
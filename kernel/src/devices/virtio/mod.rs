@@ -0,0 +1,144 @@
+pub mod blk;
+mod queue;
+
+use crate::devices::pci;
+
+/// Vendor ID of virtio devices.
+const VENDOR_ID: u16 = 0x1af4;
+
+/// Offsets of the registers of the legacy transport, from the start of BAR 0.
+const REG_DEVICE_FEATURES: u16 = 0x00;
+const REG_DRIVER_FEATURES: u16 = 0x04;
+const REG_QUEUE_PFN: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0c;
+const REG_QUEUE_SELECT: u16 = 0x0e;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_STATUS: u16 = 0x12;
+const REG_ISR: u16 = 0x13;
+
+/// Offset of the device-specific configuration, while MSI-X is off.
+const REG_CONFIG: u16 = 0x14;
+
+/// Bit of the ISR status set if a queue was used.
+const ISR_QUEUE: u8 = 0b01;
+
+bitflags::bitflags! {
+    /// Device status: how far the driver got setting the device up.
+    struct Status: u8 {
+        /// The driver noticed the device.
+        const ACKNOWLEDGE = 1;
+
+        /// The driver knows how to drive the device.
+        const DRIVER = 2;
+
+        /// The driver is ready to drive the device.
+        const DRIVER_OK = 4;
+
+        /// The driver gave up on the device.
+        const FAILED = 128;
+    }
+}
+
+/// Legacy PCI transport of a virtio device: its registers are in I/O space,
+/// behind BAR 0.
+///
+/// Transitional devices, which QEMU plugs in by default, offer it next to
+/// the modern transport.
+#[derive(Debug, Clone, Copy)]
+struct Transport {
+    base: u16,
+}
+
+impl Transport {
+    /// Returns the legacy transport of `device`, and lets the device master
+    /// the bus. Returns `None` if the device has no I/O BAR 0.
+    fn new(device: &pci::Device) -> Option<Self> {
+        let base = match device.bars[0] {
+            Some(pci::Bar::Io { port, .. }) => port,
+            _ => return None,
+        };
+        device.enable(pci::Command::IO_SPACE | pci::Command::BUS_MASTER);
+        Some(Self { base })
+    }
+
+    /// Resets the device, and acknowledges it.
+    fn reset(&self) {
+        self.write_u8(REG_STATUS, 0);
+        self.add_status(Status::ACKNOWLEDGE | Status::DRIVER);
+    }
+
+    /// Accepts the features of `wanted` which the device offers, and returns
+    /// them.
+    fn negotiate(&self, wanted: u32) -> u32 {
+        let features = self.read_u32(REG_DEVICE_FEATURES) & wanted;
+        self.write_u32(REG_DRIVER_FEATURES, features);
+        features
+    }
+
+    fn add_status(&self, status: Status) {
+        let status = self.read_u8(REG_STATUS) | status.bits();
+        self.write_u8(REG_STATUS, status);
+    }
+
+    /// Selects queue `index`, and returns its size, which is 0 if there is
+    /// no such queue.
+    fn select_queue(&self, index: u16) -> u16 {
+        self.write_u16(REG_QUEUE_SELECT, index);
+        self.read_u16(REG_QUEUE_SIZE)
+    }
+
+    /// Tells the device where the selected queue is, by page number.
+    fn set_queue_pfn(&self, pfn: u32) {
+        self.write_u32(REG_QUEUE_PFN, pfn);
+    }
+
+    /// Tells the device that queue `index` has new buffers.
+    fn notify(&self, index: u16) {
+        self.write_u16(REG_QUEUE_NOTIFY, index);
+    }
+
+    /// Reads the ISR status, which acknowledges the interrupt.
+    fn isr(&self) -> u8 {
+        self.read_u8(REG_ISR)
+    }
+
+    fn read_config_u32(&self, offset: u16) -> u32 {
+        self.read_u32(REG_CONFIG + offset)
+    }
+
+    fn read_config_u64(&self, offset: u16) -> u64 {
+        let low = self.read_config_u32(offset) as u64;
+        let high = self.read_config_u32(offset + 4) as u64;
+        high << 32 | low
+    }
+
+    fn read_u8(&self, register: u16) -> u8 {
+        let mut port = x86_64::instructions::port::Port::<u8>::new(self.base + register);
+        unsafe { port.read() }
+    }
+
+    fn read_u16(&self, register: u16) -> u16 {
+        let mut port = x86_64::instructions::port::Port::<u16>::new(self.base + register);
+        unsafe { port.read() }
+    }
+
+    fn read_u32(&self, register: u16) -> u32 {
+        let mut port = x86_64::instructions::port::Port::<u32>::new(self.base + register);
+        unsafe { port.read() }
+    }
+
+    fn write_u8(&self, register: u16, value: u8) {
+        let mut port = x86_64::instructions::port::Port::<u8>::new(self.base + register);
+        unsafe { port.write(value) }
+    }
+
+    fn write_u16(&self, register: u16, value: u16) {
+        let mut port = x86_64::instructions::port::Port::<u16>::new(self.base + register);
+        unsafe { port.write(value) }
+    }
+
+    fn write_u32(&self, register: u16, value: u32) {
+        let mut port = x86_64::instructions::port::Port::<u32>::new(self.base + register);
+        unsafe { port.write(value) }
+    }
+}
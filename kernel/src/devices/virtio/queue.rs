@@ -0,0 +1,264 @@
+use core::sync::atomic::{fence, Ordering};
+
+use crate::threads::{
+    addr::{vtop, PhysAddr, VirtAddr, PAGE_SIZE},
+    AllocateFlags, PAGE_ALLOCATOR,
+};
+
+use super::Transport;
+
+/// Flag of a descriptor continued by the one in its `next` field.
+const DESC_F_NEXT: u16 = 1;
+
+/// Flag of a descriptor whose buffer the device writes, rather than reads.
+const DESC_F_WRITE: u16 = 2;
+
+/// A descriptor, pointing to a buffer the driver hands to the device.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Descriptor {
+    address: u64,
+    length: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// An element of the used ring: a chain the device is done with.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct UsedElement {
+    /// Head descriptor of the chain.
+    id: u32,
+
+    /// Number of bytes the device wrote to the chain.
+    length: u32,
+}
+
+/// A buffer in physical memory, to hand to the device.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Buffer {
+    pub address: PhysAddr,
+    pub length: u32,
+
+    /// Whether the device writes the buffer, rather than reads it.
+    pub device_writes: bool,
+}
+
+/// A split virtqueue, through which the driver hands chains of buffers to
+/// the device, and gets them back once used.
+///
+/// It takes contiguous pages, laid out as the legacy transport wants:
+///
+/// ```text
+/// +-------------------+---------------+-----+---------------+-----+
+/// | descriptor table  |  available    | pad |  used ring    | pad |
+/// | (16 bytes each)   |  ring         |     |               |     |
+/// +-------------------+---------------+-----+---------------+-----+
+///                                           ^ page aligned
+/// ```
+///
+/// The descriptors which are not in a chain handed to the device are linked
+/// through their `next` field, in a free list.
+#[derive(Debug)]
+pub(super) struct Virtqueue {
+    index: u16,
+    size: u16,
+    base: VirtAddr,
+
+    /// Offset of the used ring from `base`.
+    used_offset: usize,
+
+    /// First descriptor of the free list.
+    free_head: u16,
+    free_cnt: u16,
+
+    /// Index of the available ring the driver fills next.
+    avail_idx: u16,
+
+    /// Index of the used ring the driver reads next.
+    last_used_idx: u16,
+}
+
+impl Virtqueue {
+    /// Largest queue we set up, since the legacy transport does not let us
+    /// shrink the queues the device offers.
+    const MAX_SIZE: u16 = 1024;
+
+    /// Sets up queue `index` of the device behind `transport`. Returns
+    /// `None` if there is no such queue, or no memory for it.
+    pub(super) fn new(transport: &Transport, index: u16) -> Option<Self> {
+        let size = transport.select_queue(index);
+        if size == 0 || size > Self::MAX_SIZE {
+            return None;
+        }
+
+        let (used_offset, length) = Self::layout(size);
+        let pages = PAGE_ALLOCATOR.get_pages(length / PAGE_SIZE, AllocateFlags::ZERO)?;
+
+        let queue = Self {
+            index,
+            size,
+            base: pages.start_address(),
+            used_offset,
+            free_head: 0,
+            free_cnt: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+        for i in 0..size {
+            queue.write_descriptor(
+                i,
+                Descriptor {
+                    address: 0,
+                    length: 0,
+                    flags: 0,
+                    next: i + 1,
+                },
+            );
+        }
+
+        let pfn = vtop(queue.base).as_u64() / PAGE_SIZE as u64;
+        transport.set_queue_pfn(pfn as u32);
+        Some(queue)
+    }
+
+    pub(super) fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Chains `buffers`, and makes the chain available to the device.
+    /// Returns the head descriptor of the chain, which identifies it once
+    /// used, or `None` if too few descriptors are free.
+    ///
+    /// The device is not told; notify it through the transport.
+    pub(super) fn add(&mut self, buffers: &[Buffer]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.free_cnt as usize {
+            return None;
+        }
+
+        let head = self.free_head;
+        let mut index = head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let next = self.read_descriptor(index).next;
+            let is_last = i + 1 == buffers.len();
+
+            let mut flags = 0;
+            if buffer.device_writes {
+                flags |= DESC_F_WRITE;
+            }
+            if !is_last {
+                flags |= DESC_F_NEXT;
+            }
+            self.write_descriptor(
+                index,
+                Descriptor {
+                    address: buffer.address.as_u64(),
+                    length: buffer.length,
+                    flags,
+                    next,
+                },
+            );
+
+            if is_last {
+                self.free_head = next;
+            } else {
+                index = next;
+            }
+        }
+        self.free_cnt -= buffers.len() as u16;
+
+        // The device may look at the ring entry as soon as the index covers
+        // it.
+        let slot = (self.avail_idx % self.size) as usize;
+        unsafe {
+            core::ptr::write_volatile(self.avail_ring().add(slot), head);
+        }
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        unsafe {
+            core::ptr::write_volatile(self.avail_idx_ptr(), self.avail_idx);
+        }
+        fence(Ordering::SeqCst);
+
+        Some(head)
+    }
+
+    /// Takes a chain the device used, and frees its descriptors. Returns its
+    /// head descriptor, and how many bytes the device wrote to it.
+    pub(super) fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let used_idx = unsafe { core::ptr::read_volatile(self.used_idx_ptr()) };
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+
+        let slot = (self.last_used_idx % self.size) as usize;
+        let element = unsafe { core::ptr::read_volatile(self.used_ring().add(slot)) };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        // Put the chain back at the front of the free list.
+        let head = element.id as u16;
+        let mut tail = head;
+        let mut count = 1;
+        loop {
+            let descriptor = self.read_descriptor(tail);
+            if descriptor.flags & DESC_F_NEXT == 0 {
+                self.write_descriptor(
+                    tail,
+                    Descriptor {
+                        next: self.free_head,
+                        ..descriptor
+                    },
+                );
+                break;
+            }
+            tail = descriptor.next;
+            count += 1;
+        }
+        self.free_head = head;
+        self.free_cnt += count;
+
+        Some((head, element.length))
+    }
+
+    /// Returns where the used ring starts, and how many bytes the queue
+    /// takes, for a queue of `size` descriptors.
+    fn layout(size: u16) -> (usize, usize) {
+        let size = size as usize;
+        let avail_end = 16 * size + 6 + 2 * size;
+        let used_offset = crate::div_round_up!(avail_end, PAGE_SIZE) * PAGE_SIZE;
+        let used_end = used_offset + 6 + 8 * size;
+        (
+            used_offset,
+            crate::div_round_up!(used_end, PAGE_SIZE) * PAGE_SIZE,
+        )
+    }
+
+    fn read_descriptor(&self, index: u16) -> Descriptor {
+        assert!(index < self.size);
+        let descriptor = self.base.as_ptr::<Descriptor>();
+        unsafe { core::ptr::read_volatile(descriptor.add(index as usize)) }
+    }
+
+    fn write_descriptor(&self, index: u16, value: Descriptor) {
+        assert!(index < self.size);
+        let descriptor = self.base.as_mut_ptr::<Descriptor>();
+        unsafe { core::ptr::write_volatile(descriptor.add(index as usize), value) }
+    }
+
+    fn avail_idx_ptr(&self) -> *mut u16 {
+        (self.base + 16 * self.size as u64 + 2u64).as_mut_ptr()
+    }
+
+    fn avail_ring(&self) -> *mut u16 {
+        (self.base + 16 * self.size as u64 + 4u64).as_mut_ptr()
+    }
+
+    fn used_idx_ptr(&self) -> *const u16 {
+        (self.base + self.used_offset as u64 + 2u64).as_ptr()
+    }
+
+    fn used_ring(&self) -> *const UsedElement {
+        (self.base + self.used_offset as u64 + 4u64).as_ptr()
+    }
+}
@@ -1,29 +1,136 @@
 use crate::{
-    devices, println,
-    threads::{self},
+    console, debug,
+    devices::{self, block::Kind},
+    logging, println,
+    threads::{self, interrupt::ControllerKind},
 };
 
-/// Initializes the kernel.
-pub fn init(boot_info: &'static bootloader_api::BootInfo) {
+/// Options to boot the kernel with.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Interrupt controller to deliver external interrupts. The APICs fall
+    /// back to the PICs if the ACPI tables do not describe them.
+    pub interrupt_controller: ControllerKind,
+
+    /// Serial port of the console.
+    pub serial: devices::serial::Config,
+
+    /// Levels to log at, as a default level and levels for modules, like
+    /// `warn,kernel::devices=debug`.
+    pub log_filter: &'static str,
+
+    /// Enables the debug monitor, entered with Ctrl-B `m` on the serial port
+    /// once interrupts are enabled, or on panic.
+    pub debug_monitor: bool,
+
+    /// Block device to use for the file system, like `hda1`, instead of the
+    /// first file system partition.
+    pub filesys_device: Option<&'static str>,
+
+    /// Block device to use for scratch space, instead of the first scratch
+    /// partition.
+    pub scratch_device: Option<&'static str>,
+
+    /// Block device to use for swap space, instead of the first swap
+    /// partition.
+    pub swap_device: Option<&'static str>,
+}
+
+impl Options {
+    /// Creates the default options.
+    pub const fn new() -> Self {
+        Self {
+            interrupt_controller: ControllerKind::Apic,
+            serial: devices::serial::Config::new(),
+            log_filter: "info",
+            debug_monitor: false,
+            filesys_device: None,
+            scratch_device: None,
+            swap_device: None,
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Initializes the kernel with the default [`Options`].
+pub fn init(boot_info: &'static mut bootloader_api::BootInfo) {
+    init_with_options(boot_info, Options::default());
+}
+
+/// Initializes the kernel with the given `options`.
+pub fn init_with_options(boot_info: &'static mut bootloader_api::BootInfo, options: Options) {
     // Initialize ourselves as a thread so we can use locks.
     threads::thread_init();
 
+    console::CONSOLE.lock().init(options.serial);
+    logging::init(options.log_filter);
+
+    // Show the console on the screen too, if the bootloader set one up.
+    let framebuffer = core::mem::replace(
+        &mut boot_info.framebuffer,
+        bootloader_api::info::Optional::None,
+    );
+    if let Some(framebuffer) = framebuffer.into_option() {
+        devices::framebuffer::init(framebuffer);
+    }
+    let boot_info: &'static bootloader_api::BootInfo = boot_info;
+
     greet(boot_info);
 
     // Initialize memory system.
     threads::palloc_init(boot_info, usize::MAX);
 
+    // Set up segmentation, with stacks for the exceptions which may strike
+    // on a broken stack.
+    threads::gdt_init();
+
+    // Locate the firmware tables describing the hardware.
+    devices::acpi::init(boot_info);
+
     // Initialize interrupt handlers.
-    threads::interrupt_init();
+    threads::interrupt_init(options.interrupt_controller);
     devices::timer::init();
+    devices::keyboard::init();
+
+    // Find the devices on the PCI buses, for their drivers.
+    devices::pci::init();
+
+    // Now that interrupts are set up, stop busy-waiting on the serial port,
+    // and start receiving input from it.
+    console::CONSOLE.lock().init_queue();
+
+    if options.debug_monitor {
+        debug::monitor::init(options.serial);
+    }
 
     // Start thread scheduler and enable interrupts.
     threads::SCHEDULER.lock().start();
+    devices::clock::init();
+    devices::rtc::init();
+    devices::rng::init();
+
+    // Find the disks, now that they can interrupt.
+    devices::ide::init();
+    devices::virtio::blk::init();
+    locate_block_devices(&options);
 
     println!("Boot complete.");
     println!();
 }
 
+/// Gives the block devices their roles, like Pintos' `locate_block_devices()`.
+fn locate_block_devices(options: &Options) {
+    devices::block::locate(Kind::Kernel, None);
+    devices::block::locate(Kind::Filesys, options.filesys_device);
+    devices::block::locate(Kind::Scratch, options.scratch_device);
+    devices::block::locate(Kind::Swap, options.swap_device);
+}
+
 fn greet(boot_info: &bootloader_api::BootInfo) {
     let free_region = boot_info
         .memory_regions
@@ -1,14 +1,16 @@
 #![no_std]
 #![warn(clippy::all)]
-#![feature(abi_x86_interrupt)]
 
 pub mod console;
+pub mod debug;
 pub mod devices;
 pub mod init;
+pub mod logging;
 pub mod threads;
 pub mod utils;
 
 pub use init::init;
+pub use init::init_with_options;
 
 #[macro_export]
 macro_rules! entry_point {
@@ -0,0 +1,203 @@
+use core::fmt::Write;
+
+use crate::{
+    console::{self, ring::Ring},
+    devices::{rtc, timer::TIMER},
+    println,
+    threads::{interrupt, thread},
+};
+
+/// Records logged so far, like Linux's `dmesg`.
+///
+/// They are kept apart from the console output, and written before it, so
+/// that they survive a console which fails, or whose sinks are disabled.
+static DMESG: interrupt::Mutex<Ring> = interrupt::Mutex::new(Ring::new());
+
+/// Levels to log at, set at boot.
+static FILTERS: interrupt::Mutex<Filters> = interrupt::Mutex::new(Filters::new());
+
+/// The kernel's logger, behind the `log` crate's macros.
+static LOGGER: Logger = Logger;
+
+/// Level to log at, for the module `module` and its submodules.
+#[derive(Debug, Clone, Copy)]
+struct Filter {
+    module: &'static str,
+    level: log::LevelFilter,
+}
+
+/// Levels to log at, per module, parsed from a specification like
+/// `warn,kernel::devices=debug,acpi=off`: a default level, and levels for
+/// modules, separated by commas.
+struct Filters {
+    default: log::LevelFilter,
+    modules: [Filter; Filters::MAX_MODULES],
+    module_count: usize,
+}
+
+impl Filters {
+    /// Maximum number of modules with a level of their own.
+    const MAX_MODULES: usize = 16;
+
+    const fn new() -> Self {
+        Self {
+            default: log::LevelFilter::Info,
+            modules: [Filter {
+                module: "",
+                level: log::LevelFilter::Off,
+            }; Self::MAX_MODULES],
+            module_count: 0,
+        }
+    }
+
+    /// Parses `spec`, and returns the part it did not understand, if any.
+    fn parse(&mut self, spec: &'static str) -> Option<&'static str> {
+        let mut invalid = None;
+
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let parsed = match directive.split_once('=') {
+                None => directive.parse().map(|level| self.default = level).is_ok(),
+                Some(_) if self.module_count == Self::MAX_MODULES => false,
+                Some((module, level)) => level
+                    .trim()
+                    .parse()
+                    .map(|level| {
+                        self.modules[self.module_count] = Filter {
+                            module: module.trim(),
+                            level,
+                        };
+                        self.module_count += 1;
+                    })
+                    .is_ok(),
+            };
+
+            if !parsed {
+                invalid = Some(directive);
+            }
+        }
+        invalid
+    }
+
+    /// Returns the level to log at for `target`, from the filter for the
+    /// longest module containing it.
+    fn level(&self, target: &str) -> log::LevelFilter {
+        self.modules[..self.module_count]
+            .iter()
+            .filter(|filter| contains(filter.module, target))
+            .max_by_key(|filter| filter.module.len())
+            .map_or(self.default, |filter| filter.level)
+    }
+
+    /// Returns the most verbose level of all.
+    fn max_level(&self) -> log::LevelFilter {
+        self.modules[..self.module_count]
+            .iter()
+            .map(|filter| filter.level)
+            .fold(self.default, core::cmp::max)
+    }
+}
+
+/// Returns `true` if `target` is `module` or one of its submodules.
+fn contains(module: &str, target: &str) -> bool {
+    match target.strip_prefix(module) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+/// Writes the log records to [`DMESG`] and the console, prefixed with the
+/// timer tick, the date and time once the real-time clock is read, the level
+/// and the running thread, like
+///
+/// ```text
+/// [    1234] 2024-02-29 13:45:00 INFO  main: kernel::devices::rtc: ...
+/// ```
+///
+/// Records logged in an external interrupt handler, which cannot lock the
+/// console, only go to [`DMESG`].
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= FILTERS.lock().level(metadata.target())
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !<Self as log::Log>::enabled(self, record.metadata()) {
+            return;
+        }
+
+        let line = Line {
+            ticks: TIMER.lock().ticks(),
+            time: rtc::boot_time().map(|_| rtc::DateTime::now()),
+            thread: thread::running_thread().name(),
+            record,
+        };
+
+        let _ = writeln!(DMESG.lock(), "{line}");
+
+        if console::is_emergency() || !interrupt::is_external_handler_context() {
+            println!("{line}");
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// A log record, with its prefix.
+struct Line<'a> {
+    ticks: usize,
+    time: Option<rtc::DateTime>,
+    thread: &'a str,
+    record: &'a log::Record<'a>,
+}
+
+impl core::fmt::Display for Line<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "[{:>8}] ", self.ticks)?;
+        if let Some(time) = self.time {
+            write!(f, "{time} ")?;
+        }
+        write!(
+            f,
+            "{:<5} {}: {}: {}",
+            self.record.level(),
+            self.thread,
+            self.record.target(),
+            self.record.args()
+        )
+    }
+}
+
+/// Installs the kernel's logger behind the `log` crate's macros, logging at
+/// the levels specified by `filters`, like `warn,kernel::devices=debug`.
+///
+/// Logs from other crates are handled as well, filtered by their crate name.
+/// Nothing may be logged with the console locked, since logging prints to it.
+pub fn init(filters: &'static str) {
+    let invalid = {
+        let mut parsed = FILTERS.lock();
+        let invalid = parsed.parse(filters);
+        log::set_max_level(parsed.max_level());
+        invalid
+    };
+
+    log::set_logger(&LOGGER).expect("logger already set");
+
+    if let Some(directive) = invalid {
+        log::warn!("Ignoring invalid log filter '{directive}'.");
+    }
+}
+
+/// Writes the records logged so far, oldest first, to `out`. Those which
+/// did not fit are lost, and non-ASCII characters are shown as `?`.
+///
+/// It does not stop records from being logged meanwhile, so that it can be
+/// called anywhere, e.g. from the debug monitor.
+pub fn dmesg(out: &mut dyn Write) -> core::fmt::Result {
+    let dmesg = unsafe { DMESG.peek() };
+    for byte in dmesg.bytes() {
+        out.write_char(if byte.is_ascii() { byte as char } else { '?' })?;
+    }
+    Ok(())
+}
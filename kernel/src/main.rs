@@ -9,7 +9,13 @@
 ///
 /// See the [`bootloader_api`] crate for more information.
 fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
-    kernel::init(boot_info);
+    kernel::init_with_options(
+        boot_info,
+        kernel::init::Options {
+            debug_monitor: cfg!(feature = "debug-monitor"),
+            ..Default::default()
+        },
+    );
 
     kernel::devices::shutdown::power_off()
 }
@@ -22,8 +28,16 @@ kernel::entry_point!(kernel_main);
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
+    // Print straight to the serial port from now on, since interrupts may
+    // never drain the output queue, and the console may be locked.
+    kernel::console::panic();
+
     // Print the panic message and information.
     kernel::println!("{info}");
+    kernel::debug::print_backtrace();
+
+    // Let the user inspect the system, if they are at the serial port.
+    kernel::debug::monitor::enter_on_panic();
 
     // Shut down the system.
     kernel::devices::shutdown::power_off_with_failure()
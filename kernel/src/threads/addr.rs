@@ -42,6 +42,20 @@ pub fn vtop(vaddr: VirtAddr) -> PhysAddr {
     PhysAddr::new(vaddr.as_u64() - PHYS_BASE)
 }
 
+/// Returns `true` if `vaddr` is mapped in the active page table.
+pub fn is_mapped(vaddr: VirtAddr) -> bool {
+    let (level_4_frame, _) = x86_64::registers::control::Cr3::read();
+    let level_4_table = unsafe {
+        &mut *ptov(level_4_frame.start_address())
+            .as_mut_ptr::<x86_64::structures::paging::PageTable>()
+    };
+    let page_table = unsafe {
+        x86_64::structures::paging::OffsetPageTable::new(level_4_table, VirtAddr::new(PHYS_BASE))
+    };
+
+    x86_64::structures::paging::mapper::Translate::translate_addr(&page_table, vaddr).is_some()
+}
+
 /// Returns the page number.
 pub fn page_number(page: Page) -> u64 {
     const PAGE_BITS: usize = 12;
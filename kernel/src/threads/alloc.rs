@@ -198,6 +198,29 @@ impl Allocator {
         }
     }
 
+    /// Writes the number of free blocks of each size to `out`.
+    ///
+    /// It does not take the locks of the descriptors, so that the debug
+    /// monitor can call it whatever the code it interrupted holds.
+    ///
+    /// # Safety
+    /// This function is unsafe because a free list may be in the middle of an
+    /// update, and is walked anyway. The caller must ensure that the update
+    /// does not go on meanwhile, e.g. with interrupts off.
+    pub unsafe fn write_stats(&self, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+        writeln!(out, "block size  free blocks")?;
+        for descriptor in self.descriptors.iter() {
+            let descriptor = descriptor.steal();
+            writeln!(
+                out,
+                "{:>10}  {:>11}",
+                descriptor.block_size,
+                descriptor.free_list.iter().count()
+            )?;
+        }
+        Ok(())
+    }
+
     /// Finds a descriptor which is suitable for allocating block of `size`.
     fn get_descriptor(&self, size: usize) -> Option<(usize, &Mutex<Descriptor>)> {
         (0..Self::DESCRIPTORS_SIZE)
@@ -301,4 +324,4 @@ unsafe impl core::alloc::GlobalAlloc for Allocator {
 }
 
 #[global_allocator]
-static ALLOCATOR: Allocator = Allocator::new();
+pub static ALLOCATOR: Allocator = Allocator::new();
@@ -0,0 +1,145 @@
+use super::{
+    addr::VirtAddr,
+    interrupt,
+    palloc::{AllocateFlags, PAGE_ALLOCATOR},
+    thread::{self, Thread},
+};
+
+/// Interrupt stack table slot for double faults.
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+
+/// Interrupt stack table slot for non-maskable interrupts.
+pub const NMI_IST_INDEX: u16 = 1;
+
+/// Interrupt stack table slot for machine checks.
+pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+
+/// Segment selectors of the [`Gdt`].
+#[derive(Debug, Clone, Copy)]
+struct Selectors {
+    code: x86_64::structures::gdt::SegmentSelector,
+    data: x86_64::structures::gdt::SegmentSelector,
+    tss: x86_64::structures::gdt::SegmentSelector,
+}
+
+/// Global descriptor table (GDT), and the task-state segment (TSS) it points
+/// to.
+///
+/// In long mode, segmentation is mostly gone: the code segment only selects
+/// 64-bit mode and the privilege level, and the TSS only holds stack pointers.
+/// We need the TSS for its interrupt stack table (IST): exceptions which may
+/// strike on a broken stack, like a double fault after a stack overflow,
+/// switch to a known good stack of their own. Without it, the CPU fails to
+/// push the exception frame and triple-faults.
+pub struct Gdt {
+    table: x86_64::structures::gdt::GlobalDescriptorTable,
+    tss: x86_64::structures::tss::TaskStateSegment,
+    selectors: Option<Selectors>,
+}
+
+impl Gdt {
+    /// Creates an empty [`Gdt`].
+    pub const fn new() -> Self {
+        Self {
+            table: x86_64::structures::gdt::GlobalDescriptorTable::new(),
+            tss: x86_64::structures::tss::TaskStateSegment::new(),
+            selectors: None,
+        }
+    }
+
+    /// Allocates the interrupt stacks, and sets up the kernel segments and
+    /// the TSS.
+    ///
+    /// # Safety
+    /// This function is unsafe because the GDT points to the TSS, so the
+    /// caller must ensure that `self` is never moved.
+    pub unsafe fn init(&mut self) {
+        self.tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            allocate_stack("double fault");
+        self.tss.interrupt_stack_table[NMI_IST_INDEX as usize] = allocate_stack("nmi");
+        self.tss.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] =
+            allocate_stack("machine check");
+
+        let tss = &*(&self.tss as *const x86_64::structures::tss::TaskStateSegment);
+
+        self.table = x86_64::structures::gdt::GlobalDescriptorTable::new();
+        self.selectors = Some(Selectors {
+            code: self
+                .table
+                .add_entry(x86_64::structures::gdt::Descriptor::kernel_code_segment()),
+            data: self
+                .table
+                .add_entry(x86_64::structures::gdt::Descriptor::kernel_data_segment()),
+            tss: self
+                .table
+                .add_entry(x86_64::structures::gdt::Descriptor::tss_segment(tss)),
+        });
+    }
+
+    /// Loads GDT register, reloads the segment registers to point into the
+    /// new table, and loads the task register.
+    pub fn load(&'static self) {
+        let selectors = self.selectors.expect("GDT is not initialized");
+
+        self.table.load();
+        unsafe {
+            set_segment::<x86_64::instructions::segmentation::CS>(selectors.code);
+            set_segment::<x86_64::instructions::segmentation::SS>(selectors.data);
+            set_segment::<x86_64::instructions::segmentation::DS>(selectors.data);
+            set_segment::<x86_64::instructions::segmentation::ES>(selectors.data);
+            x86_64::instructions::tables::load_tss(selectors.tss);
+        }
+    }
+}
+
+impl Default for Gdt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Global descriptor table.
+pub static GDT: interrupt::Mutex<Gdt> = interrupt::Mutex::new(Gdt::new());
+
+/// Replaces the GDT set up by the bootloader with our own.
+///
+/// Must be called after the page allocator is initialized, and before the
+/// interrupt system, whose IDT entries refer to the code segment.
+pub fn init() {
+    unsafe {
+        GDT.lock().init();
+        GDT.peek().load();
+    }
+}
+
+/// Reloads the segment register `S` with `selector`.
+///
+/// # Safety
+/// This function is unsafe because the caller must ensure that `selector`
+/// points to a valid descriptor for `S`.
+unsafe fn set_segment<S: x86_64::instructions::segmentation::Segment>(
+    selector: x86_64::structures::gdt::SegmentSelector,
+) {
+    S::set_reg(selector);
+}
+
+/// Allocates a stack for the interrupt stack table, and returns its top.
+///
+/// The stack is laid out like a thread's, with a running [`Thread`] named
+/// `name` at its bottom. Handlers running on it may then call
+/// [`thread::current_thread()`], which, e.g., the console lock does.
+fn allocate_stack(name: &str) -> VirtAddr {
+    let page = PAGE_ALLOCATOR
+        .get_pages_aligned(
+            Thread::STACK_PAGES,
+            Thread::STACK_PAGES,
+            AllocateFlags::ZERO,
+        )
+        .expect("out of memory for interrupt stacks");
+
+    let thread = unsafe { &mut *page.start_address().as_mut_ptr::<Thread>() };
+    thread.init(name, Thread::PRIORITY_MAX);
+    thread.status = thread::Status::Running;
+
+    page.start_address() + Thread::STACK_SIZE
+}
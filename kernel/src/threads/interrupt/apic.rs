@@ -0,0 +1,435 @@
+use crate::{
+    devices::acpi::{Madt, MadtEntry},
+    threads::addr::{ptov, PhysAddr, VirtAddr},
+};
+
+/// Modes of the local APIC timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TimerMode {
+    /// Interrupts once, when the current count reaches zero.
+    OneShot = 0b00 << 17,
+
+    /// Interrupts every time the current count reaches zero, reloading the
+    /// initial count.
+    Periodic = 0b01 << 17,
+}
+
+/// Divisors of the bus clock, which the local APIC timer counts down at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TimerDivide {
+    By1 = 0b1011,
+    By2 = 0b0000,
+    By4 = 0b0001,
+    By8 = 0b0010,
+    By16 = 0b0011,
+    By32 = 0b1000,
+    By64 = 0b1001,
+    By128 = 0b1010,
+}
+
+/// Local APIC of the current CPU.
+///
+/// Each CPU has its own local APIC, which receives the interrupts routed by
+/// the I/O APICs, and owns a timer. Its registers are memory-mapped, 16 bytes
+/// apart from each other.
+pub struct LocalApic {
+    base: Option<VirtAddr>,
+}
+
+impl LocalApic {
+    /// Local APIC ID register.
+    const ID: usize = 0x20;
+
+    /// Task priority register.
+    const TASK_PRIORITY: usize = 0x80;
+
+    /// End-of-interrupt register.
+    const EOI: usize = 0xb0;
+
+    /// Spurious interrupt vector register.
+    const SPURIOUS_INTERRUPT_VECTOR: usize = 0xf0;
+
+    /// Local vector table entry for the timer.
+    const LVT_TIMER: usize = 0x320;
+
+    /// Timer initial count register.
+    const TIMER_INITIAL_COUNT: usize = 0x380;
+
+    /// Timer current count register.
+    const TIMER_CURRENT_COUNT: usize = 0x390;
+
+    /// Timer divide configuration register.
+    const TIMER_DIVIDE_CONFIGURATION: usize = 0x3e0;
+
+    /// `IA32_APIC_BASE` model specific register.
+    const APIC_BASE_MSR: u32 = 0x1b;
+
+    /// Global enable flag in `IA32_APIC_BASE`.
+    const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+    /// Software enable flag in the spurious interrupt vector register.
+    const SOFTWARE_ENABLE: u32 = 1 << 8;
+
+    /// Mask flag of local vector table entries.
+    const LVT_MASKED: u32 = 1 << 16;
+
+    const fn new() -> Self {
+        Self { base: None }
+    }
+
+    /// Enables the local APIC mapped at `base`, delivering spurious interrupts
+    /// to `spurious_vector`.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that `base` is
+    /// the physical address of the local APIC.
+    unsafe fn init(&mut self, base: PhysAddr, spurious_vector: u8) {
+        self.base = Some(ptov(base));
+
+        let mut msr = x86_64::registers::model_specific::Msr::new(Self::APIC_BASE_MSR);
+        let value = msr.read();
+        msr.write(value | Self::APIC_BASE_ENABLE);
+
+        // Accept interrupts of all priorities.
+        self.write(Self::TASK_PRIORITY, 0);
+
+        self.write(
+            Self::SPURIOUS_INTERRUPT_VECTOR,
+            Self::SOFTWARE_ENABLE | spurious_vector as u32,
+        );
+
+        // The firmware may have left the timer running.
+        self.stop_timer();
+    }
+
+    /// Returns the APIC ID of the current CPU.
+    pub fn id(&self) -> u8 {
+        unsafe { (self.read(Self::ID) >> 24) as u8 }
+    }
+
+    /// Signals the end of the interrupt being serviced.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that this
+    /// function is called at the tail of the external interrupt handler.
+    unsafe fn end_of_interrupt(&mut self) {
+        self.write(Self::EOI, 0);
+    }
+
+    /// Sets the divisor of the bus clock, which the timer counts down at.
+    pub fn set_timer_divide(&mut self, divide: TimerDivide) {
+        unsafe {
+            self.write(Self::TIMER_DIVIDE_CONFIGURATION, divide as u32);
+        }
+    }
+
+    /// Starts the timer, counting down from `initial_count` and interrupting
+    /// to `vector` in `mode`.
+    pub fn start_timer(&mut self, vector: u8, mode: TimerMode, initial_count: u32) {
+        unsafe {
+            self.write(Self::LVT_TIMER, mode as u32 | vector as u32);
+            self.write(Self::TIMER_INITIAL_COUNT, initial_count);
+        }
+    }
+
+    /// Stops and masks the timer.
+    pub fn stop_timer(&mut self) {
+        unsafe {
+            self.write(Self::LVT_TIMER, Self::LVT_MASKED);
+            self.write(Self::TIMER_INITIAL_COUNT, 0);
+        }
+    }
+
+    /// Returns the count the timer started counting down from.
+    pub fn timer_initial_count(&self) -> u32 {
+        unsafe { self.read(Self::TIMER_INITIAL_COUNT) }
+    }
+
+    /// Returns the current count of the timer, which stays at zero once a
+    /// one-shot count is over.
+    pub fn timer_current_count(&self) -> u32 {
+        unsafe { self.read(Self::TIMER_CURRENT_COUNT) }
+    }
+
+    unsafe fn read(&self, register: usize) -> u32 {
+        let base = self.base.expect("Local APIC is not initialized");
+        core::ptr::read_volatile((base + register).as_ptr::<u32>())
+    }
+
+    unsafe fn write(&mut self, register: usize, value: u32) {
+        let base = self.base.expect("Local APIC is not initialized");
+        core::ptr::write_volatile((base + register).as_mut_ptr::<u32>(), value);
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of an I/O APIC redirection table entry.
+    struct Redirection: u64 {
+        /// The input is active low, instead of active high.
+        const ACTIVE_LOW = 1 << 13;
+
+        /// The input is level triggered, instead of edge triggered.
+        const LEVEL_TRIGGERED = 1 << 15;
+
+        /// The interrupt is masked.
+        const MASKED = 1 << 16;
+    }
+}
+
+/// I/O APIC.
+///
+/// Routes its input pins, identified by global system interrupt (GSI) numbers,
+/// to interrupt vectors of the local APICs. Only two registers are
+/// memory-mapped: one selects a register, and the other accesses it.
+#[derive(Clone, Copy)]
+struct IoApic {
+    base: VirtAddr,
+    gsi_base: u32,
+    gsi_count: u32,
+}
+
+impl IoApic {
+    /// Register select.
+    const IOREGSEL: usize = 0x00;
+
+    /// Register window.
+    const IOWIN: usize = 0x10;
+
+    /// Version register, which also holds the number of inputs.
+    const VERSION: u32 = 0x01;
+
+    /// First redirection table register; each entry takes two registers.
+    const REDIRECTION_TABLE: u32 = 0x10;
+
+    /// Creates an I/O APIC mapped at `base`, and masks all its inputs.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that `base` is
+    /// the physical address of an I/O APIC.
+    unsafe fn new(base: PhysAddr, gsi_base: u32) -> Self {
+        let mut io_apic = Self {
+            base: ptov(base),
+            gsi_base,
+            gsi_count: 0,
+        };
+
+        io_apic.gsi_count = ((io_apic.read(Self::VERSION) >> 16) & 0xff) + 1;
+
+        for gsi in gsi_base..gsi_base + io_apic.gsi_count {
+            io_apic.set_redirection(gsi, 0, 0, Redirection::MASKED);
+        }
+
+        io_apic
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.gsi_count).contains(&gsi)
+    }
+
+    /// Masks `gsi` if `masked`, and unmasks it otherwise, keeping its route.
+    unsafe fn set_masked(&mut self, gsi: u32, masked: bool) {
+        let register = Self::REDIRECTION_TABLE + 2 * (gsi - self.gsi_base);
+        let entry = self.read(register);
+        let mask = Redirection::MASKED.bits() as u32;
+
+        if masked {
+            self.write(register, entry | mask);
+        } else {
+            self.write(register, entry & !mask);
+        }
+    }
+
+    unsafe fn is_masked(&self, gsi: u32) -> bool {
+        let register = Self::REDIRECTION_TABLE + 2 * (gsi - self.gsi_base);
+        self.read(register) & Redirection::MASKED.bits() as u32 != 0
+    }
+
+    /// Routes `gsi` to `vector` of the local APIC `apic_id`.
+    unsafe fn set_redirection(&mut self, gsi: u32, vector: u8, apic_id: u8, flags: Redirection) {
+        let register = Self::REDIRECTION_TABLE + 2 * (gsi - self.gsi_base);
+        let entry = flags.bits() | vector as u64 | (apic_id as u64) << 56;
+
+        self.write(register, entry as u32);
+        self.write(register + 1, (entry >> 32) as u32);
+    }
+
+    unsafe fn read(&self, register: u32) -> u32 {
+        core::ptr::write_volatile((self.base + Self::IOREGSEL).as_mut_ptr::<u32>(), register);
+        core::ptr::read_volatile((self.base + Self::IOWIN).as_ptr::<u32>())
+    }
+
+    unsafe fn write(&mut self, register: u32, value: u32) {
+        core::ptr::write_volatile((self.base + Self::IOREGSEL).as_mut_ptr::<u32>(), register);
+        core::ptr::write_volatile((self.base + Self::IOWIN).as_mut_ptr::<u32>(), value);
+    }
+}
+
+/// Advanced Programmable Interrupt Controller: the local APIC of the CPU, and
+/// the I/O APICs routing the external interrupts to it.
+///
+/// Legacy ISA interrupts 0...15 are delivered to interrupt vectors
+/// `offset`...`offset + 15`, the same as the 8259A PICs do, so that handlers
+/// do not care which controller is active.
+pub struct Apic {
+    offset: u8,
+    local: LocalApic,
+    io_apics: [Option<IoApic>; Self::MAX_IO_APICS],
+
+    /// GSIs that ISA interrupts are wired to.
+    isa_gsis: [u32; Self::ISA_INTERRUPTS as usize],
+}
+
+impl Apic {
+    /// Maximum number of I/O APICs we support.
+    const MAX_IO_APICS: usize = 4;
+
+    /// Number of legacy ISA interrupts.
+    const ISA_INTERRUPTS: u8 = 16;
+
+    /// Vector for spurious interrupts. Its lower 4 bits must be set on older
+    /// processors.
+    pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+    /// Creates a new APIC with the given vector offset for ISA interrupts.
+    pub const fn new(offset: u8) -> Self {
+        Self {
+            offset,
+            local: LocalApic::new(),
+            io_apics: [None; Self::MAX_IO_APICS],
+            isa_gsis: [0; Self::ISA_INTERRUPTS as usize],
+        }
+    }
+
+    /// Returns `true` if the CPU has a local APIC.
+    pub fn is_supported() -> bool {
+        const CPUID_APIC: u32 = 1 << 9;
+        core::arch::x86_64::__cpuid(1).edx & CPUID_APIC != 0
+    }
+
+    /// Initializes the APICs described in `madt`, and routes every ISA
+    /// interrupt to the local APIC. They stay masked until enabled with
+    /// [`Apic::set_masked()`].
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that `madt`
+    /// describes the system, and has the address of the local APIC. Also, this
+    /// function should be called only once.
+    pub unsafe fn init(&mut self, madt: &Madt) {
+        let base = madt
+            .local_apic_address()
+            .expect("MADT has no local APIC address");
+        self.local.init(base, Self::SPURIOUS_VECTOR);
+
+        let io_apics = madt.entries().filter_map(|entry| match entry {
+            MadtEntry::IoApic {
+                address, gsi_base, ..
+            } => Some(IoApic::new(PhysAddr::new(address as u64), gsi_base)),
+            _ => None,
+        });
+        for (slot, io_apic) in self.io_apics.iter_mut().zip(io_apics) {
+            *slot = Some(io_apic);
+        }
+
+        let apic_id = self.local.id();
+
+        for irq in 0..Self::ISA_INTERRUPTS {
+            let (gsi, flags) = Self::isa_route(madt, irq);
+            let vector = self.offset + irq;
+            self.isa_gsis[irq as usize] = gsi;
+
+            if let Some(io_apic) = self.io_apic(gsi) {
+                io_apic.set_redirection(gsi, vector, apic_id, flags | Redirection::MASKED);
+            }
+        }
+    }
+
+    /// Masks ISA interrupt `irq` if `masked`, and unmasks it otherwise.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that the APICs
+    /// are initialized, and a handler is registered for `irq` before it is
+    /// unmasked.
+    pub unsafe fn set_masked(&mut self, irq: u8, masked: bool) {
+        assert!(irq < Self::ISA_INTERRUPTS);
+
+        let gsi = self.isa_gsis[irq as usize];
+        if let Some(io_apic) = self.io_apic(gsi) {
+            io_apic.set_masked(gsi, masked);
+        }
+    }
+
+    /// Returns `true` if ISA interrupt line `irq` is masked. Lines wired to
+    /// no I/O APIC count as masked.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that the APICs
+    /// are initialized.
+    pub unsafe fn is_masked(&mut self, irq: u8) -> bool {
+        assert!(irq < Self::ISA_INTERRUPTS);
+
+        let gsi = self.isa_gsis[irq as usize];
+        match self.io_apic(gsi) {
+            Some(io_apic) => io_apic.is_masked(gsi),
+            None => true,
+        }
+    }
+
+    /// Returns the local APIC.
+    pub fn local(&mut self) -> &mut LocalApic {
+        &mut self.local
+    }
+
+    /// Sends an end-of-interrupt signal to the local APIC.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that this
+    /// function is called at the tail of the external interrupt handler.
+    pub unsafe fn end_of_interrupt(&mut self, interrupt_id: u8) {
+        // Spurious interrupts are not in service, so they must not be
+        // acknowledged.
+        if interrupt_id != Self::SPURIOUS_VECTOR {
+            self.local.end_of_interrupt();
+        }
+    }
+
+    /// Returns the GSI that ISA interrupt `irq` is wired to, and its polarity
+    /// and trigger mode.
+    fn isa_route(madt: &Madt, irq: u8) -> (u32, Redirection) {
+        // MPS INTI flags: polarity in bits 0...1, trigger mode in bits 2...3.
+        // "Conforms to the bus" means active high and edge triggered for ISA.
+        const POLARITY_ACTIVE_LOW: u16 = 0b11;
+        const TRIGGER_LEVEL: u16 = 0b11 << 2;
+
+        madt.entries()
+            .find_map(|entry| match entry {
+                MadtEntry::InterruptSourceOverride {
+                    bus: 0,
+                    source,
+                    gsi,
+                    flags,
+                } if source == irq => {
+                    let mut redirection = Redirection::empty();
+                    redirection.set(
+                        Redirection::ACTIVE_LOW,
+                        flags & POLARITY_ACTIVE_LOW == POLARITY_ACTIVE_LOW,
+                    );
+                    redirection.set(
+                        Redirection::LEVEL_TRIGGERED,
+                        flags & TRIGGER_LEVEL == TRIGGER_LEVEL,
+                    );
+                    Some((gsi, redirection))
+                }
+                _ => None,
+            })
+            .unwrap_or((irq as u32, Redirection::empty()))
+    }
+
+    fn io_apic(&mut self, gsi: u32) -> Option<&mut IoApic> {
+        self.io_apics
+            .iter_mut()
+            .flatten()
+            .find(|io_apic| io_apic.handles(gsi))
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{devices::acpi::ACPI, println};
+
+use super::{apic::Apic, mutex::Mutex, pic::Pics};
+
+/// Kinds of interrupt controllers which can deliver external interrupts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerKind {
+    /// Legacy pair of 8259A PICs.
+    Pic,
+
+    /// Local APIC and I/O APICs, as described in the ACPI MADT.
+    Apic,
+}
+
+/// The interrupt controller delivering external interrupts, selected at boot.
+///
+/// Both controllers deliver ISA interrupts 0...15 to the same interrupt
+/// vectors, so only the end-of-interrupt signal depends on which one is
+/// active.
+pub struct Controller {
+    kind: ControllerKind,
+    pics: Pics,
+    apic: Apic,
+}
+
+impl Controller {
+    /// Interrupt vector of ISA interrupt 0.
+    const OFFSET: u8 = 0x20;
+
+    /// Creates a new interrupt controller, which defaults to the PICs.
+    pub const fn new() -> Self {
+        Self {
+            kind: ControllerKind::Pic,
+            pics: Pics::new(Self::OFFSET),
+            apic: Apic::new(Self::OFFSET),
+        }
+    }
+
+    /// Initializes the interrupt controller of `kind`.
+    ///
+    /// If the APICs are requested but the CPU or the ACPI tables do not
+    /// report them, falls back to the PICs.
+    ///
+    /// # Safety
+    /// This function is unsafe because it reprograms the interrupt
+    /// controllers. It should be called only once.
+    pub unsafe fn init(&mut self, kind: ControllerKind) {
+        // Remap the PICs in any case: they may raise spurious interrupts even
+        // when all of their inputs are masked.
+        self.pics.init();
+
+        self.kind = match (kind, ACPI.lock().madt()) {
+            (ControllerKind::Apic, Some(madt))
+                if Apic::is_supported() && madt.local_apic_address().is_some() =>
+            {
+                self.pics.disable();
+                self.apic.init(&madt);
+                ControllerKind::Apic
+            }
+            (ControllerKind::Apic, _) => {
+                println!("APIC is not available, using 8259A PIC instead.");
+                ControllerKind::Pic
+            }
+            (ControllerKind::Pic, _) => ControllerKind::Pic,
+        };
+    }
+
+    /// Returns the active interrupt controller.
+    pub fn kind(&self) -> ControllerKind {
+        self.kind
+    }
+
+    /// Returns the APICs, if they are active.
+    pub fn apic(&mut self) -> Option<&mut Apic> {
+        match self.kind {
+            ControllerKind::Apic => Some(&mut self.apic),
+            ControllerKind::Pic => None,
+        }
+    }
+
+    /// Unmasks ISA interrupt line `irq`, so that it is delivered to vector
+    /// `0x20 + irq`.
+    ///
+    /// Register a handler for the vector first.
+    pub fn enable_irq(&mut self, irq: u8) {
+        unsafe {
+            self.set_masked(irq, false);
+        }
+    }
+
+    /// Masks ISA interrupt line `irq`.
+    pub fn disable_irq(&mut self, irq: u8) {
+        unsafe {
+            self.set_masked(irq, true);
+        }
+    }
+
+    /// Returns `true` if ISA interrupt line `irq` is masked.
+    pub fn is_irq_masked(&mut self, irq: u8) -> bool {
+        unsafe {
+            match self.kind {
+                ControllerKind::Pic => self.pics.is_masked(irq),
+                ControllerKind::Apic => self.apic.is_masked(irq),
+            }
+        }
+    }
+
+    unsafe fn set_masked(&mut self, irq: u8, masked: bool) {
+        match self.kind {
+            ControllerKind::Pic => self.pics.set_masked(irq, masked),
+            ControllerKind::Apic => self.apic.set_masked(irq, masked),
+        }
+    }
+
+    /// Returns `true` if `interrupt_id` is a spurious interrupt, which the
+    /// controller raised without any device requesting it.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that this
+    /// function is called at the head of the external interrupt handler.
+    pub unsafe fn is_spurious(&mut self, interrupt_id: u8) -> bool {
+        match self.kind {
+            ControllerKind::Pic => self.pics.is_spurious(interrupt_id),
+            ControllerKind::Apic => interrupt_id == Apic::SPURIOUS_VECTOR,
+        }
+    }
+
+    /// Completes the spurious interrupt `interrupt_id`.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that
+    /// `interrupt_id` is spurious, as reported by
+    /// [`Controller::is_spurious()`].
+    pub unsafe fn end_of_spurious_interrupt(&mut self, interrupt_id: u8) {
+        match self.kind {
+            ControllerKind::Pic => self.pics.end_of_spurious_interrupt(interrupt_id),
+            // Spurious interrupts of the local APIC need no acknowledgement.
+            ControllerKind::Apic => (),
+        }
+    }
+
+    /// Sends an end-of-interrupt signal to the active controller for the given
+    /// `interrupt_id`.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that this
+    /// function is called at the tail of the external interrupt handler.
+    pub unsafe fn end_of_interrupt(&mut self, interrupt_id: u8) {
+        match self.kind {
+            ControllerKind::Pic => self.pics.end_of_interrupt(interrupt_id),
+            ControllerKind::Apic => self.apic.end_of_interrupt(interrupt_id),
+        }
+    }
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Global interrupt controller.
+pub static CONTROLLER: Mutex<Controller> = Mutex::new(Controller::new());
+
+/// Unmasks ISA interrupt line `irq`, like Linux's `enable_irq()`.
+pub fn enable_irq(irq: u8) {
+    CONTROLLER.lock().enable_irq(irq);
+}
+
+/// Masks ISA interrupt line `irq`, like Linux's `disable_irq()`.
+pub fn disable_irq(irq: u8) {
+    CONTROLLER.lock().disable_irq(irq);
+}
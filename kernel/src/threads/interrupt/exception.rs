@@ -0,0 +1,212 @@
+use crate::{
+    console,
+    debug::Backtrace,
+    devices::shutdown,
+    println,
+    threads::{
+        addr::{self, VirtAddr},
+        thread::Thread,
+    },
+};
+
+use super::{frame::Frame, handler::InterruptHandlersRegistry};
+
+bitflags::bitflags! {
+    /// Error code pushed by the CPU on a page fault.
+    pub struct PageFaultError: u64 {
+        /// The page was present, so the access violated its rights. Otherwise,
+        /// the page was not present.
+        const PRESENT = 1 << 0;
+
+        /// The access was a write. Otherwise, it was a read.
+        const WRITE = 1 << 1;
+
+        /// The access was made in user mode. Otherwise, in kernel mode.
+        const USER = 1 << 2;
+
+        /// A reserved bit was set in a page table entry.
+        const RESERVED_BIT = 1 << 3;
+
+        /// The access was an instruction fetch.
+        const INSTRUCTION_FETCH = 1 << 4;
+    }
+}
+
+/// Descriptor table referenced by a [`SelectorError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorTable {
+    Gdt,
+    Idt,
+    Ldt,
+}
+
+/// Error code pushed by the CPU on an exception related to a segment
+/// selector: #TS, #NP, #SS, and #GP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectorError(u64);
+
+impl SelectorError {
+    /// Creates a new [`SelectorError`] from the `error_code`.
+    pub const fn new(error_code: u64) -> Self {
+        Self(error_code)
+    }
+
+    /// Returns `true` if the exception was caused by an event external to
+    /// the program, such as an interrupt.
+    pub fn is_external(&self) -> bool {
+        self.0 & 0b1 != 0
+    }
+
+    /// Returns the descriptor table holding the selector.
+    pub fn table(&self) -> DescriptorTable {
+        match (self.0 >> 1) & 0b11 {
+            0b00 => DescriptorTable::Gdt,
+            0b10 => DescriptorTable::Ldt,
+            _ => DescriptorTable::Idt,
+        }
+    }
+
+    /// Returns the index of the selector in its descriptor table.
+    pub fn index(&self) -> u16 {
+        ((self.0 >> 3) & 0x1fff) as u16
+    }
+}
+
+impl core::fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // #GP pushes zero if the fault is not related to a selector.
+        if self.0 == 0 {
+            return write!(f, "not segment related");
+        }
+
+        write!(f, "{:?} entry {:#x}", self.table(), self.index())?;
+        if self.is_external() {
+            write!(f, " (external event)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the address whose access caused the last page fault.
+pub fn fault_address() -> VirtAddr {
+    x86_64::registers::control::Cr2::read()
+}
+
+/// Registers the handlers for exceptions which can be decoded, like Pintos'
+/// `exception_init()`. Other exceptions are reported with a register dump.
+pub(super) fn init(registry: &mut InterruptHandlersRegistry) {
+    registry.register(2, fatal_exception, "NMI Interrupt");
+    registry.register(8, fatal_exception, "#DF Double Fault Exception");
+    registry.register(10, selector_fault, "#TS Invalid TSS Exception");
+    registry.register(11, selector_fault, "#NP Segment Not Present");
+    registry.register(12, selector_fault, "#SS Stack Fault Exception");
+    registry.register(13, selector_fault, "#GP General Protection Exception");
+    registry.register(14, page_fault, "#PF Page-Fault Exception");
+    registry.register(18, fatal_exception, "#MC Machine-Check Exception");
+}
+
+/// Page fault handler.
+///
+/// There is no virtual memory yet, so every page fault is a kernel bug.
+fn page_fault(frame: &mut Frame) {
+    let fault_address = fault_address();
+    let error = PageFaultError::from_bits_truncate(frame.error_code().unwrap_or(0));
+
+    println!(
+        "Page fault at {:#x}: {} error {} page in {} context{}.",
+        fault_address.as_u64(),
+        if error.contains(PageFaultError::PRESENT) {
+            "rights violation"
+        } else {
+            "not present"
+        },
+        if error.contains(PageFaultError::INSTRUCTION_FETCH) {
+            "fetching"
+        } else if error.contains(PageFaultError::WRITE) {
+            "writing"
+        } else {
+            "reading"
+        },
+        if error.contains(PageFaultError::USER) {
+            "user"
+        } else {
+            "kernel"
+        },
+        if error.contains(PageFaultError::RESERVED_BIT) {
+            ", with a reserved bit set"
+        } else {
+            ""
+        },
+    );
+
+    kill(frame);
+}
+
+/// Handler for the exceptions reporting a segment selector.
+fn selector_fault(frame: &mut Frame) {
+    let error = SelectorError::new(frame.error_code().unwrap_or(0));
+
+    println!("Segment selector error: {error}.");
+
+    kill(frame);
+}
+
+/// Handler for the exceptions which switch to a stack of their own, since
+/// the stack of the interrupted code may be broken.
+///
+/// The kernel cannot recover from them, so we report the faulting thread and
+/// power off, to let the test runner see the failure.
+fn fatal_exception(frame: &mut Frame) {
+    console::emergency();
+
+    let exception = match frame.vector() {
+        2 => "Non-maskable interrupt",
+        8 => "Double fault",
+        _ => "Machine check",
+    };
+
+    match faulting_thread(frame.rsp) {
+        Some(thread) => println!(
+            "{exception} in thread '{}' at rip={:#x}, rsp={:#x}.",
+            thread.name(),
+            frame.rip,
+            frame.rsp
+        ),
+        None => println!(
+            "{exception} in unknown thread at rip={:#x}, rsp={:#x}. Stack overflow?",
+            frame.rip, frame.rsp
+        ),
+    }
+    println!("{frame}");
+    println!("{}", Backtrace::from_frame(frame));
+
+    shutdown::power_off_with_failure();
+}
+
+/// Returns the thread whose stack `rsp` points into, if it looks intact.
+fn faulting_thread(rsp: u64) -> Option<&'static Thread> {
+    let thread = VirtAddr::try_new(rsp & !Thread::STACK_MASK).ok()?;
+
+    // After a stack overflow, `rsp` may point into the `Thread` at the bottom
+    // of the stack, or even below it, into an unmapped guard page.
+    if rsp - thread.as_u64() < core::mem::size_of::<Thread>() as u64 || !addr::is_mapped(thread) {
+        return None;
+    }
+
+    let thread = unsafe { &*thread.as_ptr::<Thread>() };
+    if thread.is_thread() {
+        Some(thread)
+    } else {
+        None
+    }
+}
+
+/// Reports the fault with a register dump and the call stack, and panics.
+pub(super) fn kill(frame: &Frame) -> ! {
+    // The fault may have struck with the console locked.
+    console::emergency();
+
+    println!("{frame}");
+    println!("{}", Backtrace::from_frame(frame));
+    panic!("Kernel bug - unexpected interrupt in kernel");
+}
@@ -0,0 +1,178 @@
+/// Interrupt stack frame.
+///
+/// The CPU pushes `rip` through `ss`, and the error code for some exceptions.
+/// The entry stubs push the rest: a zero error code if the CPU did not push
+/// one, the vector number, and the general-purpose registers of the
+/// interrupted code.
+///
+/// Handlers may modify the frame, and the interrupted code resumes with the
+/// modified registers.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Frame {
+    // Saved by `interrupt_entry`.
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+
+    // Saved by the `interrupt_stubs`.
+    vector: u64,
+    error_code: u64,
+
+    // Saved by the CPU.
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl Frame {
+    /// Returns the interrupt vector number.
+    pub fn vector(&self) -> u8 {
+        self.vector as u8
+    }
+
+    /// Returns the error code pushed by the CPU, if the exception has one.
+    pub fn error_code(&self) -> Option<u64> {
+        if has_error_code(self.vector()) {
+            Some(self.error_code)
+        } else {
+            None
+        }
+    }
+}
+
+impl core::fmt::Display for Frame {
+    /// Dumps the registers, like Pintos' `intr_dump_frame()`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(
+            f,
+            "Interrupt {:#04x} at rip={:#018x}",
+            self.vector(),
+            self.rip
+        )?;
+        if let Some(error_code) = self.error_code() {
+            writeln!(f, " error={error_code:#018x}")?;
+        }
+        writeln!(
+            f,
+            " rax={:#018x} rbx={:#018x} rcx={:#018x} rdx={:#018x}",
+            self.rax, self.rbx, self.rcx, self.rdx
+        )?;
+        writeln!(
+            f,
+            " rsi={:#018x} rdi={:#018x} rsp={:#018x} rbp={:#018x}",
+            self.rsi, self.rdi, self.rsp, self.rbp
+        )?;
+        writeln!(
+            f,
+            " r8 ={:#018x} r9 ={:#018x} r10={:#018x} r11={:#018x}",
+            self.r8, self.r9, self.r10, self.r11
+        )?;
+        writeln!(
+            f,
+            " r12={:#018x} r13={:#018x} r14={:#018x} r15={:#018x}",
+            self.r12, self.r13, self.r14, self.r15
+        )?;
+        write!(
+            f,
+            " cs={:#06x} ss={:#06x} rflags={:#010x}",
+            self.cs, self.ss, self.rflags
+        )
+    }
+}
+
+/// Returns `true` if the CPU pushes an error code for the exception `vector`.
+fn has_error_code(vector: u8) -> bool {
+    matches!(vector, 8 | 10..=14 | 17 | 21 | 29 | 30)
+}
+
+/// Returns the address of the entry stub for `vector`.
+pub(super) fn stub_address(vector: u8) -> u64 {
+    interrupt_stubs as *const () as u64 + STUB_SIZE * vector as u64
+}
+
+/// Size of each entry stub, in bytes.
+const STUB_SIZE: u64 = 16;
+
+// One entry stub per vector, `STUB_SIZE` bytes apart. Each stub pushes a zero
+// error code if the CPU did not push one, so that all frames look the same,
+// then the vector number, and jumps to `interrupt_entry`.
+//
+// `interrupt_entry` saves the general-purpose registers to complete the
+// `Frame`, and calls the Rust handler with a pointer to it. The CPU aligns
+// the stack to 16 bytes before pushing its part of the frame, and the frame
+// is 22 quad words long, so the stack stays aligned for the call.
+core::arch::global_asm!(
+    r#"
+.balign 16
+.global interrupt_stubs
+interrupt_stubs:
+.set .Lvector, 0
+.rept 256
+    .balign 16
+    .if (.Lvector == 8) || (.Lvector >= 10 && .Lvector <= 14) || (.Lvector == 17) || (.Lvector == 21) || (.Lvector == 29) || (.Lvector == 30)
+    .else
+    push 0
+    .endif
+    push .Lvector
+    jmp interrupt_entry
+.set .Lvector, .Lvector + 1
+.endr
+
+interrupt_entry:
+    push rax
+    push rbx
+    push rcx
+    push rdx
+    push rsi
+    push rdi
+    push rbp
+    push r8
+    push r9
+    push r10
+    push r11
+    push r12
+    push r13
+    push r14
+    push r15
+    cld
+    mov rdi, rsp
+    call {0}
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop r11
+    pop r10
+    pop r9
+    pop r8
+    pop rbp
+    pop rdi
+    pop rsi
+    pop rdx
+    pop rcx
+    pop rbx
+    pop rax
+    add rsp, 16
+    iretq
+"#,
+    sym super::handler::interrupt_handler,
+);
+
+extern "C" {
+    fn interrupt_stubs();
+}
@@ -1,17 +1,62 @@
-use crate::{println, threads::SCHEDULER};
+use crate::{
+    console,
+    devices::clock,
+    println,
+    threads::{gdt, SCHEDULER},
+};
 
-use super::{control::are_disabled, mutex::Mutex, pic::PICS};
+use super::{
+    control::are_disabled, controller::CONTROLLER, exception, frame, frame::Frame, mutex::Mutex,
+};
 
 /// An interrupt handler function.
-pub type InterruptHandler = fn(x86_64::structures::idt::InterruptStackFrame);
+///
+/// The handler receives the frame of the interrupted code, which also holds
+/// the vector number and the error code.
+pub type InterruptHandler = fn(&mut Frame);
+
+/// An interrupt handler function, for an interrupt line shared by several
+/// devices.
+///
+/// The handler returns `true` if its device raised the interrupt, and `false`
+/// otherwise.
+pub type SharedInterruptHandler = fn(&mut Frame) -> bool;
+
+/// Handlers registered for an interrupt.
+#[derive(Copy, Clone)]
+enum Handlers {
+    /// No handler is registered.
+    None,
+
+    /// A single handler owns the interrupt.
+    Exclusive(InterruptHandler),
+
+    /// Handlers share the interrupt line, and are called in the order they
+    /// were registered.
+    Shared([Option<SharedInterruptHandler>; Handlers::MAX_SHARED]),
+}
+
+impl Handlers {
+    /// Maximum number of handlers sharing an interrupt line.
+    const MAX_SHARED: usize = 4;
+}
 
 #[derive(Copy, Clone)]
 struct InterruptHandlerRegistry {
-    /// The handler registered for the interrupt.
-    handler: Option<InterruptHandler>,
+    /// The handlers registered for the interrupt.
+    handlers: Handlers,
 
     /// Name for the interrupt, for debugging purposes.
     name: [u8; Self::NAME_LENGTH],
+
+    /// Number of times the interrupt was raised, excluding spurious ones.
+    count: usize,
+
+    /// Number of times the interrupt was raised spuriously.
+    spurious_count: usize,
+
+    /// Time spent in the handler, in TSC cycles.
+    cycles: u64,
 }
 
 impl InterruptHandlerRegistry {
@@ -20,8 +65,11 @@ impl InterruptHandlerRegistry {
 
     const fn new() -> Self {
         Self {
-            handler: None,
+            handlers: Handlers::None,
             name: [0; Self::NAME_LENGTH],
+            count: 0,
+            spurious_count: 0,
+            cycles: 0,
         }
     }
 
@@ -36,6 +84,7 @@ impl InterruptHandlerRegistry {
 
     fn set_name(&mut self, name: &str) {
         assert!(name.len() <= Self::NAME_LENGTH);
+        self.name = [0; Self::NAME_LENGTH];
         self.name[..name.len()].copy_from_slice(name.as_bytes());
     }
 }
@@ -61,9 +110,28 @@ impl InterruptHandlersRegistry {
         }
     }
 
-    /// Initializes the registry by loading the default interrupt handler.
+    /// Initializes the registry by pointing every IDT entry to its entry
+    /// stub, which calls [`interrupt_handler()`].
     pub fn init(&mut self) {
-        x86_64::set_general_handler!(&mut self.idt, interrupt_handler);
+        for vector in 0..Self::NUM_INTERRUPTS {
+            let vector = vector as u8;
+            let address = x86_64::VirtAddr::new(frame::stub_address(vector));
+            let options = unsafe { self.set_handler_addr(vector, address) };
+
+            // Exceptions which may strike on a broken stack switch to a stack
+            // of their own.
+            let stack_index = match vector {
+                2 => Some(gdt::NMI_IST_INDEX),
+                8 => Some(gdt::DOUBLE_FAULT_IST_INDEX),
+                18 => Some(gdt::MACHINE_CHECK_IST_INDEX),
+                _ => None,
+            };
+            if let (Some(options), Some(stack_index)) = (options, stack_index) {
+                unsafe {
+                    options.set_stack_index(stack_index);
+                }
+            }
+        }
 
         self.registries[0].set_name("#DE Divide Error");
         self.registries[1].set_name("#DB Debug Exception");
@@ -88,6 +156,41 @@ impl InterruptHandlersRegistry {
         for registry in &mut self.registries[Self::EXTERNAL_INTERRUPT_OFFSET..] {
             registry.set_name("unknown");
         }
+
+        exception::init(self);
+    }
+
+    /// Points the IDT entry for `vector` to `address`, and returns the options
+    /// of the entry.
+    ///
+    /// The `x86_64` crate types the entries of exceptions by the handler
+    /// signature they expect, so those are picked out by name. Reserved
+    /// vectors are never raised, and are left empty.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that `address`
+    /// is an entry stub for `vector`.
+    unsafe fn set_handler_addr(
+        &mut self,
+        vector: u8,
+        address: x86_64::VirtAddr,
+    ) -> Option<&mut x86_64::structures::idt::EntryOptions> {
+        let idt = &mut self.idt;
+        let options = match vector {
+            8 => idt.double_fault.set_handler_addr(address),
+            10 => idt.invalid_tss.set_handler_addr(address),
+            11 => idt.segment_not_present.set_handler_addr(address),
+            12 => idt.stack_segment_fault.set_handler_addr(address),
+            13 => idt.general_protection_fault.set_handler_addr(address),
+            14 => idt.page_fault.set_handler_addr(address),
+            17 => idt.alignment_check.set_handler_addr(address),
+            18 => idt.machine_check.set_handler_addr(address),
+            29 => idt.vmm_communication_exception.set_handler_addr(address),
+            30 => idt.security_exception.set_handler_addr(address),
+            15 | 21..=28 | 31 => return None,
+            _ => idt[vector as usize].set_handler_addr(address),
+        };
+        Some(options)
     }
 
     /// Loads IDT register.
@@ -102,38 +205,123 @@ impl InterruptHandlersRegistry {
         interrupt_handler: InterruptHandler,
         name: &str,
     ) {
-        self.registries[interrupt_id].handler = Some(interrupt_handler);
+        self.registries[interrupt_id].handlers = Handlers::Exclusive(interrupt_handler);
         self.registries[interrupt_id].set_name(name);
     }
 
+    /// Registers an interrupt handler to `interrupt_id`, sharing it with the
+    /// other handlers registered by this function. The interrupt keeps the
+    /// `name` of its first handler.
+    ///
+    /// Panics if the interrupt is owned by a handler registered with
+    /// [`InterruptHandlersRegistry::register()`], or if too many handlers
+    /// share it.
+    pub fn register_shared(
+        &mut self,
+        interrupt_id: usize,
+        interrupt_handler: SharedInterruptHandler,
+        name: &str,
+    ) {
+        let registry = &mut self.registries[interrupt_id];
+
+        if let Handlers::None = registry.handlers {
+            registry.set_name(name);
+            registry.handlers = Handlers::Shared([None; Handlers::MAX_SHARED]);
+        }
+
+        let handlers = match &mut registry.handlers {
+            Handlers::Shared(handlers) => handlers,
+            _ => panic!("interrupt {:#04x} is not shared", interrupt_id),
+        };
+
+        let slot = handlers
+            .iter_mut()
+            .find(|handler| handler.is_none())
+            .expect("too many handlers sharing an interrupt");
+        *slot = Some(interrupt_handler);
+    }
+
+    /// Unregisters all handlers of `interrupt_id`.
+    pub fn unregister(&mut self, interrupt_id: usize) {
+        self.registries[interrupt_id].handlers = Handlers::None;
+    }
+
+    /// Unregisters `interrupt_handler`, which shares `interrupt_id` with other
+    /// handlers.
+    pub fn unregister_shared(
+        &mut self,
+        interrupt_id: usize,
+        interrupt_handler: SharedInterruptHandler,
+    ) {
+        let registry = &mut self.registries[interrupt_id];
+
+        if let Handlers::Shared(handlers) = &mut registry.handlers {
+            // Keep the remaining handlers in order.
+            let mut remaining = handlers
+                .iter()
+                .flatten()
+                .filter(|&&handler| !core::ptr::fn_addr_eq(handler, interrupt_handler))
+                .copied();
+            *handlers = core::array::from_fn(|_| remaining.next());
+
+            if handlers.iter().all(Option::is_none) {
+                registry.handlers = Handlers::None;
+            }
+        }
+    }
+
+    /// Prints the number of times each interrupt was raised, and the time
+    /// spent handling it, like Linux's `/proc/interrupts`.
+    pub fn print_stats(&self) {
+        console::with_console(|console| self.write_stats(console))
+            .expect("Failed to write to console");
+    }
+
+    /// Writes the statistics printed by [`Self::print_stats()`] to `out`.
+    pub fn write_stats(&self, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+        writeln!(out, "Interrupts:")?;
+        writeln!(out, "vector      count   spurious          cycles  name")?;
+        for (interrupt_id, registry) in self.registries.iter().enumerate() {
+            if registry.count == 0 && registry.spurious_count == 0 {
+                continue;
+            }
+
+            writeln!(
+                out,
+                "  {interrupt_id:#04x} {:>10} {:>10} {:>15}  {}",
+                registry.count,
+                registry.spurious_count,
+                registry.cycles,
+                registry.name()
+            )?;
+        }
+        Ok(())
+    }
+
     fn is_external_context(&self) -> bool {
         self.is_external_context
     }
 
-    fn handle_internal(
-        &self,
-        frame: x86_64::structures::idt::InterruptStackFrame,
-        interrupt_id: u8,
-    ) {
-        self.handle(frame, interrupt_id);
+    fn handle_internal(&mut self, frame: &mut Frame) {
+        self.handle(frame);
     }
 
     /// External interrupts are special.
     ///
     /// We only handle one at a time, so this function must be called with
     /// interrupts disabled. An external interrupt handler cannot sleep.
-    fn handle_external(
-        &mut self,
-        frame: x86_64::structures::idt::InterruptStackFrame,
-        interrupt_id: u8,
-    ) {
+    fn handle_external(&mut self, frame: &mut Frame) {
         assert!(are_disabled());
         assert!(!self.is_external_context);
 
         self.is_external_context = true;
 
+        // When devices interrupt is hard to predict: feed it to the kernel's
+        // random number generator.
+        crate::devices::rng::add_interrupt_entropy(frame.vector());
+
         // Invoke the interrupt's handler.
-        self.handle(frame, interrupt_id);
+        self.handle(frame);
 
         // Complete the processing of an external interrupt.
         assert!(are_disabled());
@@ -142,14 +330,46 @@ impl InterruptHandlersRegistry {
         self.is_external_context = false;
     }
 
-    fn handle(&self, frame: x86_64::structures::idt::InterruptStackFrame, interrupt_id: u8) {
+    /// Counts a spurious interrupt, which is otherwise ignored.
+    fn handle_spurious(&mut self, interrupt_id: u8) {
+        self.registries[interrupt_id as usize].spurious_count += 1;
+    }
+
+    fn handle(&mut self, frame: &mut Frame) {
+        let interrupt_id = frame.vector();
         let registry = self.registries[interrupt_id as usize];
 
-        if let Some(handler) = registry.handler {
-            handler(frame);
-        } else if interrupt_id == 0x27 || interrupt_id == 0x2f {
-            // There is no handler, but this interrupt can trigger spuriously
-            // due to a hardware fault or hardware race condition. Ignore it.
+        self.registries[interrupt_id as usize].count += 1;
+
+        let start = clock::rdtsc();
+        let handled = match registry.handlers {
+            Handlers::None => false,
+            Handlers::Exclusive(handler) => {
+                handler(frame);
+                true
+            }
+            Handlers::Shared(handlers) => {
+                // More than one device may be requesting the interrupt, so
+                // every handler gets a chance to check its device.
+                let mut handled = false;
+                for handler in handlers.iter().flatten() {
+                    handled |= handler(frame);
+                }
+                handled
+            }
+        };
+        self.registries[interrupt_id as usize].cycles += clock::rdtsc() - start;
+
+        if handled {
+            // Done.
+        } else if (interrupt_id as usize) < Self::EXTERNAL_INTERRUPT_OFFSET {
+            // An exception nobody handles is a kernel bug. Returning would
+            // just run into it again, or carry on in a corrupted state.
+            println!(
+                "Unexpected interrupt {interrupt_id:#04x} {}",
+                registry.name()
+            );
+            exception::kill(frame);
         } else {
             // Handle an unexpected interrupt.
             println!(
@@ -164,28 +384,35 @@ impl InterruptHandlersRegistry {
 pub static REGISTRY: Mutex<InterruptHandlersRegistry> =
     Mutex::new(InterruptHandlersRegistry::new());
 
-/// Handler for internal interrupts. This function is called by the
-/// interrupt handlers registered to IDT. `frame` describes the interrupt
-/// and the interrupted thread's registers.
-fn interrupt_handler(
-    frame: x86_64::structures::idt::InterruptStackFrame,
-    interrupt_id: u8,
-    _error_code: Option<u64>,
-) {
+/// Handler for all interrupts. This function is called by the entry stubs
+/// registered to IDT. `frame` describes the interrupt and the interrupted
+/// thread's registers.
+pub(super) extern "C" fn interrupt_handler(frame: &mut Frame) {
+    let interrupt_id = frame.vector();
     let is_external = interrupt_id >= InterruptHandlersRegistry::EXTERNAL_INTERRUPT_OFFSET as u8;
 
     if is_external {
-        REGISTRY.lock().handle_external(frame, interrupt_id);
+        // The interrupt controller may raise an interrupt due to a hardware
+        // fault or hardware race condition. Ignore it.
+        if unsafe { CONTROLLER.lock().is_spurious(interrupt_id) } {
+            REGISTRY.lock().handle_spurious(interrupt_id);
+            unsafe {
+                CONTROLLER.lock().end_of_spurious_interrupt(interrupt_id);
+            }
+            return;
+        }
+
+        REGISTRY.lock().handle_external(frame);
 
         unsafe {
-            PICS.lock().end_of_interrupt(interrupt_id);
+            CONTROLLER.lock().end_of_interrupt(interrupt_id);
         }
 
         // Enforce preemption, by notifying the scheduler to schedule a new
         // thread if necessary.
         SCHEDULER.lock().preempt_current_thread();
     } else {
-        REGISTRY.lock().handle_internal(frame, interrupt_id);
+        REGISTRY.lock().handle_internal(frame);
     }
 }
 
@@ -1,27 +1,48 @@
+mod apic;
 mod control;
+mod controller;
+mod exception;
+mod frame;
 mod handler;
 mod mutex;
 mod pic;
 
+pub use self::apic::Apic;
+pub use self::apic::LocalApic;
+pub use self::apic::TimerDivide;
+pub use self::apic::TimerMode;
+
 pub use self::control::are_disabled;
 pub use self::control::are_enabled;
 pub use self::control::disable;
 pub use self::control::enable;
 
+pub use self::controller::disable_irq;
+pub use self::controller::enable_irq;
+pub use self::controller::ControllerKind;
+pub use self::controller::CONTROLLER;
+
+pub use self::exception::fault_address;
+pub use self::exception::DescriptorTable;
+pub use self::exception::PageFaultError;
+pub use self::exception::SelectorError;
+
+pub use self::frame::Frame;
+
 pub use self::handler::is_external_handler_context;
 pub use self::handler::InterruptHandler;
+pub use self::handler::SharedInterruptHandler;
 pub use self::handler::REGISTRY;
 
 pub use self::mutex::Mutex;
 pub use self::mutex::MutexGuard;
 
-use self::pic::PICS;
-
-/// Initializes the interrupt system.
-pub fn init() {
+/// Initializes the interrupt system, delivering external interrupts through
+/// the `controller`.
+pub fn init(controller: ControllerKind) {
     // Initialize interrupt controller.
     unsafe {
-        PICS.lock().init();
+        CONTROLLER.lock().init(controller);
     }
 
     // Initialize the interrupt handler registry.
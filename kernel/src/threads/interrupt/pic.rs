@@ -1,5 +1,3 @@
-use super::mutex::Mutex;
-
 struct Pic {
     offset: u8,
     control: x86_64::instructions::port::Port<u8>,
@@ -10,6 +8,24 @@ impl Pic {
     fn can_handle(&self, interrupt_id: u8) -> bool {
         (self.offset..self.offset + 8).contains(&interrupt_id)
     }
+
+    /// Returns `true` if `interrupt_id` is the lowest priority line (IR7) of
+    /// this PIC, which it raises on spurious interrupts.
+    fn is_lowest_priority(&self, interrupt_id: u8) -> bool {
+        interrupt_id == self.offset + 7
+    }
+
+    /// Reads the in-service register, whose bits are set for the lines being
+    /// serviced, which are not yet acknowledged.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that the PIC is
+    /// initialized.
+    unsafe fn in_service(&mut self) -> u8 {
+        // OCW3: read ISR on next read of the control register.
+        self.control.write(0x0b);
+        self.control.read()
+    }
 }
 
 /// 8259A Programmable Interrupt Controller.
@@ -37,6 +53,9 @@ impl Pics {
     /// Slave PIC data register address.
     const PIC1_DATA: u16 = 0xa1;
 
+    /// Interrupt line of the master PIC the slave PIC is attached to.
+    const CASCADE_IRQ: u8 = 2;
+
     /// Creates a new pair of PICs with given register offsets.
     pub const fn new(offset: u8) -> Pics {
         Pics(
@@ -78,9 +97,89 @@ impl Pics {
         slave.data.write(0x02); // ICW3: slave ID is 2.
         slave.data.write(0x01); // ICW4: 8086 mode, normal EOI, non-buffered.
 
-        // Unmask all interrupts.
-        master.data.write(0x00);
-        slave.data.write(0x00);
+        // Leave all interrupts masked until their drivers enable them, except
+        // for the slave PIC cascaded on line IR2. ICW1 cleared the masks.
+        master.data.write(!(1 << Self::CASCADE_IRQ));
+        slave.data.write(0xff);
+    }
+
+    /// Masks interrupt line `irq` if `masked`, and unmasks it otherwise.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that the PICs
+    /// are initialized, and a handler is registered for `irq` before it is
+    /// unmasked.
+    pub unsafe fn set_masked(&mut self, irq: u8, masked: bool) {
+        assert!(irq < 16);
+
+        let Self(master, slave) = self;
+        let (pic, line) = if irq < 8 {
+            (master, irq)
+        } else {
+            (slave, irq - 8)
+        };
+
+        let mask = pic.data.read();
+        if masked {
+            pic.data.write(mask | (1 << line));
+        } else {
+            pic.data.write(mask & !(1 << line));
+        }
+    }
+
+    /// Returns `true` if interrupt line `irq` is masked.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that the PICs
+    /// are initialized.
+    pub unsafe fn is_masked(&mut self, irq: u8) -> bool {
+        assert!(irq < 16);
+
+        let Self(master, slave) = self;
+        let (pic, line) = if irq < 8 {
+            (master, irq)
+        } else {
+            (slave, irq - 8)
+        };
+
+        pic.data.read() & (1 << line) != 0
+    }
+
+    /// Masks all interrupts on both PICs, so that they do not deliver any
+    /// interrupt when another controller is used instead.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that the PICs
+    /// have been initialized, so that spurious interrupts they may still raise
+    /// are delivered to the vectors from `offset`, not to CPU exceptions.
+    pub unsafe fn disable(&mut self) {
+        let Self(master, slave) = self;
+
+        master.data.write(0xff);
+        slave.data.write(0xff);
+    }
+
+    /// Returns `true` if `interrupt_id` is a spurious interrupt.
+    ///
+    /// When an interrupt request goes away before the CPU acknowledges it,
+    /// e.g. due to noise on the line, the PIC still has to deliver something,
+    /// so it delivers its IR7 without marking it in service.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that the PICs
+    /// are initialized.
+    pub unsafe fn is_spurious(&mut self, interrupt_id: u8) -> bool {
+        const IR7: u8 = 1 << 7;
+
+        let Self(master, slave) = self;
+
+        if master.is_lowest_priority(interrupt_id) {
+            master.in_service() & IR7 == 0
+        } else if slave.is_lowest_priority(interrupt_id) {
+            slave.in_service() & IR7 == 0
+        } else {
+            false
+        }
     }
 
     /// Sends an end-of-interrupt signal to the PIC for the given
@@ -105,7 +204,21 @@ impl Pics {
             slave.control.write(0x20);
         }
     }
-}
 
-/// Global PIC registers.
-pub static PICS: Mutex<Pics> = Mutex::new(Pics::new(0x20));
+    /// Completes the spurious interrupt `interrupt_id`, which is not in
+    /// service, so the PIC raising it must not be acknowledged.
+    ///
+    /// The master PIC cannot tell a spurious interrupt of the slave from a real
+    /// one on the cascade line, though, so it is still acknowledged then.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must ensure that
+    /// `interrupt_id` is spurious, as reported by [`Pics::is_spurious()`].
+    pub unsafe fn end_of_spurious_interrupt(&mut self, interrupt_id: u8) {
+        let Self(master, slave) = self;
+
+        if slave.can_handle(interrupt_id) {
+            master.control.write(0x20);
+        }
+    }
+}
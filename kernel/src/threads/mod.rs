@@ -1,15 +1,21 @@
 pub mod addr;
 mod alloc;
+mod gdt;
 pub mod interrupt;
 mod palloc;
 mod scheduler;
 mod sync;
 pub mod thread;
 
+pub use self::alloc::ALLOCATOR;
+pub use self::palloc::AllocateFlags;
+pub use self::palloc::PAGE_ALLOCATOR;
 pub use self::scheduler::SCHEDULER;
 
+pub use self::gdt::init as gdt_init;
 pub use self::interrupt::init as interrupt_init;
 pub use self::palloc::init as palloc_init;
 pub use self::thread::init as thread_init;
 
 pub use self::sync::lock::Mutex;
+pub use self::sync::semaphore::Semaphore;
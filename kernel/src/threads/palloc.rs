@@ -214,6 +214,42 @@ impl PageAllocator {
 
         pool.lock().free(page_start, count);
     }
+
+    /// Writes the number of used pages in each pool to `out`.
+    ///
+    /// It does not take the locks of the pools, so that the debug monitor can
+    /// call it whatever the code it interrupted holds.
+    ///
+    /// # Safety
+    /// This function is unsafe because a pool may be in the middle of an
+    /// update, so the numbers may be off. The caller must ensure that the
+    /// update does not go on meanwhile, e.g. with interrupts off.
+    pub unsafe fn write_stats(&self, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+        for &(name, pool) in self.pools().iter() {
+            let (used, total) = pool.steal().usage();
+            writeln!(out, "{name}: {used} of {total} pages used")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the ranges of allocated pages in each pool to `out`.
+    ///
+    /// # Safety
+    /// Like [`Self::write_stats()`], it does not take the locks of the pools.
+    pub unsafe fn write_allocations(&self, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+        for &(name, pool) in self.pools().iter() {
+            writeln!(out, "{name}:")?;
+            pool.steal().write_allocations(out)?;
+        }
+        Ok(())
+    }
+
+    fn pools(&self) -> [(&'static str, &lock::Mutex<Pool>); 2] {
+        [
+            ("kernel pool", &self.kernel_pool),
+            ("user pool", &self.user_pool),
+        ]
+    }
 }
 
 /// A memory pool.
@@ -287,6 +323,40 @@ impl Pool {
         }
     }
 
+    /// Returns the number of used pages, and the number of pages in the pool.
+    fn usage(&self) -> (usize, usize) {
+        if let Some(PoolInner { used_map, .. }) = self.inner {
+            let used_map = unsafe { &*used_map.as_ptr() };
+            let used = (0..used_map.size()).filter(|&i| used_map.get(i)).count();
+            (used, used_map.size())
+        } else {
+            (0, 0)
+        }
+    }
+
+    /// Writes the ranges of used pages to `out`, one per line.
+    fn write_allocations(&self, out: &mut dyn core::fmt::Write) -> core::fmt::Result {
+        if let Some(PoolInner { used_map, base }) = self.inner {
+            let used_map = unsafe { &*used_map.as_ptr() };
+
+            let mut start = 0;
+            while let Some(first) = used_map.scan(start, 1, true) {
+                let count = used_map.scan(first, 1, false).unwrap_or(used_map.size()) - first;
+
+                let pages = base + first as u64;
+                writeln!(
+                    out,
+                    "  {:#x}-{:#x} ({count} pages)",
+                    pages.start_address().as_u64(),
+                    (pages + count as u64).start_address().as_u64()
+                )?;
+
+                start = first + count;
+            }
+        }
+        Ok(())
+    }
+
     fn free(&mut self, page_start: Page, count: usize) {
         if let Some(PoolInner { used_map, .. }) = self.inner {
             let page_index = self.page_index(page_start).unwrap();
@@ -2,7 +2,9 @@ extern crate alloc;
 
 use core::ptr::NonNull;
 
-use crate::{get_list_element, println, utils::data_structures::linked_list::LinkedList};
+use crate::{
+    devices::timer, get_list_element, println, utils::data_structures::linked_list::LinkedList,
+};
 
 use super::{interrupt, palloc, sync, thread};
 
@@ -94,8 +96,9 @@ impl Scheduler {
                 // Let someone else run.
                 SCHEDULER.lock().block_current_thread();
 
-                // Wait for the next run.
-                x86_64::instructions::hlt();
+                // Wait for the next run, without waking up at every timer
+                // tick if nothing is due.
+                timer::idle();
             }
         };
 
@@ -111,16 +114,17 @@ impl Scheduler {
         idle_started.down();
     }
 
-    /// Called by the timer interrupt handler at each timer tick.
-    /// Thus, this function runs in an external interrupt context.
-    pub fn tick(&mut self) {
+    /// Called by the timer interrupt handler at each timer tick, with the
+    /// number of `ticks` elapsed since the last call. It is more than one if
+    /// the timer skipped interrupts while the CPU was idle.
+    pub fn tick(&mut self, ticks: usize) {
         // Update statistics.
         if self.is_idle_thread() {
-            self.idle_ticks += 1;
+            self.idle_ticks += ticks;
         } else {
-            self.kernel_ticks += 1;
+            self.kernel_ticks += ticks;
         }
-        self.current_thread_ticks += 1;
+        self.current_thread_ticks += ticks;
     }
 
     /// Creates a new kernel thread named `name` with given initial `priority`.
@@ -244,6 +248,13 @@ impl Scheduler {
         self.ready_list.push_back(&mut thread.status_list_node);
     }
 
+    /// Returns all threads, for debugging.
+    pub fn threads(&self) -> impl Iterator<Item = &'static thread::Thread> + '_ {
+        self.all_list
+            .iter_mut()
+            .map(|node| &*get_list_element!(node, thread::Thread, all_list_node))
+    }
+
     /// Prints thread statistics.
     pub fn print_stats(&self) {
         println!(
@@ -93,6 +93,18 @@ impl<T> Mutex<T> {
     pub fn lock(&self) -> MutexGuard<T> {
         MutexGuard::new(self)
     }
+
+    /// Returns a mutable reference to the data without acquiring the lock,
+    /// even if some thread holds it. Meant for printing a panic message,
+    /// when the thread which panicked may hold the console forever.
+    ///
+    /// # Safety
+    /// This function is unsafe because the holder of the lock may be in the
+    /// middle of updating the data, and may go on updating it meanwhile.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn steal(&self) -> &mut T {
+        &mut (*self.data.get()).value
+    }
 }
 
 /// [`Mutex`] is [`Sync`] because the underlying mutable data is protected by a
@@ -17,6 +17,11 @@ impl Id {
 
         Self(THREAD_ID.fetch_add(1, core::sync::atomic::Ordering::Relaxed))
     }
+
+    /// Returns the number of the thread id.
+    pub const fn as_u32(&self) -> u32 {
+        self.0
+    }
 }
 
 impl Default for Id {
@@ -108,6 +113,9 @@ pub struct Thread {
     /// Number of timer ticks since last yield.
     pub ticks: u32,
 
+    /// Timer tick at which the thread should wake up, if it is sleeping.
+    pub wakeup_ticks: usize,
+
     /// The entrypoint function of the thread.
     entrypoint: Option<core::ptr::NonNull<dyn Fn()>>,
 
@@ -164,6 +172,7 @@ impl Thread {
         self.stack = unsafe { (self as *mut Thread).cast::<u8>().add(Self::STACK_SIZE) };
         self.priority = priority;
         self.ticks = 0;
+        self.wakeup_ticks = 0;
         self.entrypoint = None;
         self.all_list_node = linked_list::Node::new();
         self.status_list_node = linked_list::Node::new();
@@ -1,3 +1,4 @@
 pub mod data_structures;
 pub mod offset_of;
+pub mod random;
 pub mod round;
@@ -0,0 +1,172 @@
+/// A fast pseudo-random number generator, xoshiro256**, for reproducible
+/// tests and anything else which is not about security: its output is easy
+/// to predict from a few numbers.
+#[derive(Debug, Clone)]
+pub struct SeededRng {
+    state: [u64; 4],
+}
+
+impl SeededRng {
+    /// Creates a generator from `seed`, spread over its state with
+    /// SplitMix64, as the authors of xoshiro recommend.
+    pub const fn new(seed: u64) -> Self {
+        let mut splitmix = seed;
+        let mut state = [0; 4];
+        let mut i = 0;
+        while i < state.len() {
+            splitmix = splitmix.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = splitmix;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            state[i] = z ^ (z >> 31);
+            i += 1;
+        }
+        Self { state }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Returns a number below `bound`, which must not be 0, without the bias
+    /// of `next_u64() % bound`.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound != 0);
+
+        // Reject the numbers past the last multiple of `bound`.
+        let limit = u64::MAX - u64::MAX % bound;
+        loop {
+            let n = self.next_u64();
+            if n < limit {
+                return n % bound;
+            }
+        }
+    }
+
+    pub fn fill_bytes(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+/// A cryptographically secure pseudo-random number generator, from the
+/// ChaCha20 stream cipher: its output is the keystream of its key, the seed.
+///
+/// After each request, it replaces its key with more of the keystream, so
+/// that the numbers it gave cannot be found again from its state.
+#[derive(Debug, Clone)]
+pub struct ChaChaRng {
+    key: [u32; 8],
+    counter: u64,
+}
+
+impl ChaChaRng {
+    /// "expand 32-byte k", the constant of the first row of the state.
+    const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+    /// Size of a block of keystream, in bytes.
+    const BLOCK_SIZE: usize = 64;
+
+    pub fn new(seed: [u8; 32]) -> Self {
+        let mut key = [0; 8];
+        for (word, bytes) in key.iter_mut().zip(seed.as_chunks::<4>().0) {
+            *word = u32::from_le_bytes(*bytes);
+        }
+        Self { key, counter: 0 }
+    }
+
+    /// Mixes `seed` into the key, e.g. fresh entropy.
+    pub fn reseed(&mut self, seed: [u8; 32]) {
+        for (word, bytes) in self.key.iter_mut().zip(seed.as_chunks::<4>().0) {
+            *word ^= u32::from_le_bytes(*bytes);
+        }
+        self.rekey();
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    pub fn fill_bytes(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(Self::BLOCK_SIZE) {
+            let block = self.block();
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        self.rekey();
+    }
+
+    /// Replaces the key with the next 32 bytes of keystream.
+    fn rekey(&mut self) {
+        let block = self.block();
+        for (word, bytes) in self.key.iter_mut().zip(block.as_chunks::<4>().0) {
+            *word = u32::from_le_bytes(*bytes);
+        }
+    }
+
+    /// Returns the next block of keystream.
+    fn block(&mut self) -> [u8; Self::BLOCK_SIZE] {
+        let mut input = [0; 16];
+        input[0..4].copy_from_slice(&Self::CONSTANTS);
+        input[4..12].copy_from_slice(&self.key);
+        input[12] = self.counter as u32;
+        input[13] = (self.counter >> 32) as u32;
+        self.counter = self.counter.wrapping_add(1);
+
+        let mut x = input;
+        for _ in 0..10 {
+            // Column rounds.
+            quarter_round(&mut x, 0, 4, 8, 12);
+            quarter_round(&mut x, 1, 5, 9, 13);
+            quarter_round(&mut x, 2, 6, 10, 14);
+            quarter_round(&mut x, 3, 7, 11, 15);
+
+            // Diagonal rounds.
+            quarter_round(&mut x, 0, 5, 10, 15);
+            quarter_round(&mut x, 1, 6, 11, 12);
+            quarter_round(&mut x, 2, 7, 8, 13);
+            quarter_round(&mut x, 3, 4, 9, 14);
+        }
+
+        let mut block = [0; Self::BLOCK_SIZE];
+        for ((bytes, word), input) in block.as_chunks_mut::<4>().0.iter_mut().zip(x).zip(input) {
+            *bytes = word.wrapping_add(input).to_le_bytes();
+        }
+        block
+    }
+}
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
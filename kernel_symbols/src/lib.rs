@@ -0,0 +1,133 @@
+//! Symbols of the kernel, for debugging it from the host.
+//!
+//! [`embed()`] embeds the function symbols of a kernel ELF into its
+//! `.ksymtab` section, so that the kernel can symbolize its own backtraces.
+//! See `kernel::debug::symbols` for the layout of the table.
+//!
+//! [`Symbolizer`] annotates the addresses in the kernel's output with the
+//! functions and source lines they belong to, using the DWARF debug info.
+
+use std::path::Path;
+
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+
+mod symbolizer;
+
+pub use symbolizer::Symbolizer;
+
+/// Virtual address where kernel ELF is loaded by bootloader.
+pub const KERNEL_VADDR_OFFSET: u64 = 0x8000000000;
+
+/// Name of the section reserved for the symbol table.
+const SECTION_NAME: &str = ".ksymtab";
+
+/// Marks the start of the symbol table.
+const MAGIC: [u8; 8] = *b"KSYMTAB\0";
+
+/// Size of the table header.
+const HEADER_SIZE: usize = 24;
+
+/// Size of a symbol entry.
+const ENTRY_SIZE: usize = 16;
+
+/// A function symbol of the kernel.
+#[derive(Debug)]
+struct Symbol {
+    address: u64,
+    size: u32,
+    name: String,
+}
+
+/// Copies the kernel ELF at `kernel` to `output`, with its function symbols
+/// embedded into the `.ksymtab` section.
+///
+/// If the kernel has no such section, it is copied as is. If the symbols do
+/// not fit in the section, the ones at the highest addresses are left out.
+pub fn embed(kernel: &Path, output: &Path) -> std::io::Result<()> {
+    let mut image = std::fs::read(kernel)?;
+
+    if let Some((offset, table)) = build_table(&image)? {
+        image[offset..offset + table.len()].copy_from_slice(&table);
+    }
+
+    std::fs::write(output, image)
+}
+
+/// Builds the symbol table of the kernel ELF `image`, and returns it with the
+/// file offset of the section to write it into.
+fn build_table(image: &[u8]) -> std::io::Result<Option<(usize, Vec<u8>)>> {
+    let file = object::File::parse(image).map_err(invalid_data)?;
+
+    let Some(section) = file.section_by_name(SECTION_NAME) else {
+        return Ok(None);
+    };
+    let Some((offset, capacity)) = section.file_range() else {
+        return Ok(None);
+    };
+    if (capacity as usize) < HEADER_SIZE {
+        return Ok(None);
+    }
+
+    let mut symbols = file
+        .symbols()
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.size() != 0)
+        .filter_map(|symbol| {
+            Some(Symbol {
+                address: symbol.address(),
+                size: u32::try_from(symbol.size()).ok()?,
+                name: format!("{:#}", rustc_demangle::demangle(symbol.name().ok()?)),
+            })
+        })
+        .collect::<Vec<_>>();
+    symbols.sort_by_key(|symbol| symbol.address);
+    symbols.dedup_by_key(|symbol| symbol.address);
+
+    let capacity = capacity as usize;
+    let (count, strings) = fit(&symbols, capacity);
+    if count < symbols.len() {
+        eprintln!(
+            "warning: kernel symbol table is full, {} of {} symbols left out",
+            symbols.len() - count,
+            symbols.len()
+        );
+    }
+    let symbols = &symbols[..count];
+
+    let mut table = Vec::with_capacity(capacity);
+    table.extend_from_slice(&MAGIC);
+    table.extend_from_slice(&section.address().to_le_bytes());
+    table.extend_from_slice(&(count as u32).to_le_bytes());
+    table.extend_from_slice(&(strings as u32).to_le_bytes());
+
+    let mut name_offset = 0;
+    for symbol in symbols {
+        table.extend_from_slice(&symbol.address.to_le_bytes());
+        table.extend_from_slice(&symbol.size.to_le_bytes());
+        table.extend_from_slice(&(name_offset as u32).to_le_bytes());
+        name_offset += symbol.name.len() + 1;
+    }
+    for symbol in symbols {
+        table.extend_from_slice(symbol.name.as_bytes());
+        table.push(0);
+    }
+
+    Ok(Some((offset as usize, table)))
+}
+
+/// Returns how many of `symbols` fit in a table of `capacity` bytes, and the
+/// offset of the string table.
+fn fit(symbols: &[Symbol], capacity: usize) -> (usize, usize) {
+    let mut count = symbols.len();
+    loop {
+        let strings = HEADER_SIZE + count * ENTRY_SIZE;
+        let names: usize = symbols[..count].iter().map(|s| s.name.len() + 1).sum();
+        if strings + names <= capacity {
+            return (count, strings);
+        }
+        count = count.saturating_sub(symbols.len() / 16 + 1);
+    }
+}
+
+fn invalid_data(error: object::Error) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+}
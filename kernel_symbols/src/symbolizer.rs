@@ -0,0 +1,118 @@
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use crate::KERNEL_VADDR_OFFSET;
+
+/// Annotates the addresses in the kernel's output with the functions and
+/// source lines they belong to, like Pintos' `backtrace` utility.
+///
+/// Addresses are looked up in the DWARF debug info of the kernel ELF, so the
+/// annotations work even if no symbol table was embedded in the kernel.
+pub struct Symbolizer {
+    loader: addr2line::Loader,
+}
+
+impl Symbolizer {
+    /// Loads the debug info of the kernel ELF at `kernel`.
+    pub fn new(kernel: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let loader = addr2line::Loader::new(kernel).map_err(|error| error.to_string())?;
+        Ok(Self { loader })
+    }
+
+    /// Copies the kernel's `output` to `sink` as it arrives, and follows each
+    /// line with the locations of the kernel addresses in it. Partial lines,
+    /// like prompts, are passed through without waiting for their newline.
+    ///
+    /// A line like
+    ///
+    /// ```text
+    ///   #1  0x0000008000213a4f kernel::threads::init+0x5f
+    /// ```
+    ///
+    /// is followed by
+    ///
+    /// ```text
+    ///       0x0000008000213a4f: kernel::threads::init at kernel/src/threads/init.rs:42
+    /// ```
+    ///
+    /// and by one more line for each function inlined at the address.
+    pub fn annotate(&self, mut output: impl BufRead, mut sink: impl Write) -> std::io::Result<()> {
+        let mut line = Vec::new();
+        loop {
+            let available = output.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+
+            let (chunk, complete) = match available.iter().position(|&byte| byte == b'\n') {
+                Some(end) => (&available[..=end], true),
+                None => (available, false),
+            };
+            sink.write_all(chunk)?;
+            line.extend_from_slice(chunk);
+            let consumed = chunk.len();
+            output.consume(consumed);
+
+            if complete {
+                for address in addresses(&String::from_utf8_lossy(&line)) {
+                    for annotation in self.locate(address) {
+                        writeln!(sink, "      {address:#018x}: {annotation}")?;
+                    }
+                }
+                line.clear();
+            }
+            sink.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the functions and source lines at `address`, innermost inlined
+    /// function first. Returns nothing if `address` is not in the kernel.
+    fn locate(&self, address: u64) -> Vec<String> {
+        let Some(probe) = address.checked_sub(KERNEL_VADDR_OFFSET) else {
+            return Vec::new();
+        };
+        let Ok(mut frames) = self.loader.find_frames(probe) else {
+            return Vec::new();
+        };
+
+        let mut annotations = Vec::new();
+        while let Ok(Some(frame)) = frames.next() {
+            let function = match frame.function.as_ref().map(|f| f.demangle()) {
+                Some(Ok(name)) => name.into_owned(),
+                _ => continue,
+            };
+            let location = match frame.location {
+                Some(addr2line::Location {
+                    file: Some(file),
+                    line: Some(line),
+                    ..
+                }) => format!(" at {file}:{line}"),
+                Some(addr2line::Location {
+                    file: Some(file), ..
+                }) => format!(" at {file}"),
+                _ => String::new(),
+            };
+            let prefix = if annotations.is_empty() {
+                ""
+            } else {
+                "(inlined by) "
+            };
+            annotations.push(format!("{prefix}{function}{location}"));
+        }
+        annotations
+    }
+}
+
+/// Returns the hexadecimal numbers with a `0x` prefix in `line`.
+fn addresses(line: &str) -> impl Iterator<Item = u64> + '_ {
+    line.match_indices("0x").filter_map(move |(start, _)| {
+        let digits = &line[start + 2..];
+        let end = digits
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .unwrap_or(digits.len());
+        u64::from_str_radix(&digits[..end], 16).ok()
+    })
+}
@@ -29,3 +29,18 @@ macro_rules! fail {
         panic!("test failed");
     };
 }
+
+/// Prints the panic message, and the backtrace, without waiting for the
+/// console.
+pub fn report_panic(info: &core::panic::PanicInfo) {
+    kernel::console::panic();
+    kernel::println!("{info}");
+    kernel::debug::print_backtrace();
+}
+
+/// Reports a panic in a test kernel, then powers off with a failure. The
+/// tests' panic handlers call this.
+pub fn panic(info: &core::panic::PanicInfo) -> ! {
+    report_panic(info);
+    kernel::devices::shutdown::power_off_with_failure()
+}
@@ -17,10 +17,6 @@ fn main() {
     cmd.arg("-serial");
     cmd.arg("stdio");
 
-    // This enables `isa-debug-exit` device.
-    cmd.arg("-device");
-    cmd.arg("isa-debug-exit,iobase=0xf4,iosize=0x04");
-
     if run_options.opt_present("h") {
         let brief = format!("Usage: {program} [options]");
         print!("{}", opts.usage(&brief));
@@ -28,9 +24,6 @@ fn main() {
     }
 
     if run_options.opt_present("gdb") {
-        /// Virtual address where kernel ELF is loaded by bootloader.
-        const KERNEL_VADDR_OFFSET: u64 = 0x8000000000;
-
         cmd.arg("-s");
         cmd.arg("-S");
 
@@ -38,10 +31,22 @@ fn main() {
         println!(
             "gdb -ex \"target remote :1234\" -ex \"exec-file {kernel}\" -ex \"add-symbol-file {kernel} -o {offset:#0x}\"",
             kernel = env!("KERNEL_PATH"),
-            offset = KERNEL_VADDR_OFFSET
+            offset = kernel_symbols::KERNEL_VADDR_OFFSET
         );
     }
 
+    // Annotate the addresses in backtraces and register dumps with their
+    // source lines, as the output arrives.
+    let symbolizer = kernel_symbols::Symbolizer::new(env!("KERNEL_PATH").as_ref()).ok();
+    if symbolizer.is_some() {
+        cmd.stdout(std::process::Stdio::piped());
+    }
+
     let mut child = cmd.spawn().unwrap();
+    if let (Some(symbolizer), Some(stdout)) = (symbolizer, child.stdout.take()) {
+        symbolizer
+            .annotate(std::io::BufReader::new(stdout), std::io::stdout())
+            .unwrap();
+    }
     child.wait().unwrap();
 }
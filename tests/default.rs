@@ -29,3 +29,219 @@ fn heap() {
         tests_runner::TestOptions::default(),
     );
 }
+
+#[test]
+fn apic() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_apic"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn clock() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_clock"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn tickless() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_tickless"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn exception() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_exception"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn stack_overflow() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_stack_overflow"),
+        tests_runner::TestOptions {
+            expect_failure: true,
+            expected_output: &["Double fault in ", "Interrupt 0x08", "Call stack:"],
+            ..tests_runner::TestOptions::default()
+        },
+    );
+}
+
+#[test]
+fn irq() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_irq"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn serial_queue() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_serial_queue"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn input() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_input"),
+        tests_runner::TestOptions {
+            input: b"raw\ncookxx\x7f\x7fed\n",
+            ..tests_runner::TestOptions::default()
+        },
+    );
+}
+
+#[test]
+fn serial_config() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_serial_config"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn console_sinks() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_console_sinks"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn panic_console() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_panic_console"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn logging() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_logging"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn keyboard() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_keyboard"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn rtc() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_rtc"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn speaker() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_speaker"),
+        tests_runner::TestOptions::default(),
+    );
+}
+
+#[test]
+fn acpi() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_acpi"),
+        tests_runner::TestOptions {
+            acpi_power_off: true,
+            ..tests_runner::TestOptions::default()
+        },
+    );
+}
+
+#[test]
+fn pci() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_pci"),
+        tests_runner::TestOptions {
+            qemu_args: &[
+                "-nic",
+                "none",
+                "-device",
+                "e1000",
+                "-device",
+                "virtio-rng-pci",
+            ],
+            ..tests_runner::TestOptions::default()
+        },
+    );
+}
+
+#[test]
+fn ide() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_ide"),
+        tests_runner::TestOptions {
+            qemu_args: &["-snapshot"],
+            ..tests_runner::TestOptions::default()
+        },
+    );
+}
+
+#[test]
+fn virtio_blk() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_virtio_blk"),
+        tests_runner::TestOptions {
+            scratch_disks: &[("virtio", 1 << 20)],
+            ..tests_runner::TestOptions::default()
+        },
+    );
+}
+
+#[test]
+fn virtio_blk_read_only() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_virtio_blk_read_only"),
+        tests_runner::TestOptions {
+            qemu_args: &[
+                "-drive",
+                "driver=null-co,read-zeroes=on,size=1M,if=virtio,readonly=on",
+            ],
+            expect_failure: true,
+            expected_output: &["vda: writing a read-only disk"],
+            ..tests_runner::TestOptions::default()
+        },
+    );
+}
+
+#[test]
+fn block() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_block"),
+        tests_runner::TestOptions {
+            scratch_disks: &[("virtio", 1 << 20), ("virtio", 1 << 20)],
+            ..tests_runner::TestOptions::default()
+        },
+    );
+}
+
+#[test]
+fn rng() {
+    tests_runner::run_test_kernel(
+        env!("CARGO_BIN_FILE_TESTS_DEFAULT_rng"),
+        tests_runner::TestOptions {
+            qemu_args: &["-cpu", "max"],
+            ..tests_runner::TestOptions::default()
+        },
+    );
+}
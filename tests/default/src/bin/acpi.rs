@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    let acpi = kernel::devices::acpi::ACPI.lock();
+    assert!(acpi.is_present());
+    assert!(acpi.madt().is_some());
+
+    // QEMU describes its power management hardware.
+    let fadt = acpi.fadt().unwrap();
+    let dsdt = fadt.dsdt().unwrap();
+    assert_eq!(&dsdt.signature(), b"DSDT");
+
+    let (pm1a, _) = fadt.pm1_control_ports();
+    assert!(pm1a.is_some());
+
+    assert!(fadt.s5_sleep_types().is_some());
+    drop(acpi);
+
+    // Power off through the PM1 control registers, rather than the
+    // `isa-debug-exit` device: the runner expects QEMU to exit cleanly, right
+    // after this line.
+    kernel::println!("done");
+    kernel::devices::serial::flush();
+    kernel::devices::acpi::power_off();
+
+    panic!("ACPI power-off returned");
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    let options = kernel::init::Options {
+        interrupt_controller: kernel::threads::interrupt::ControllerKind::Apic,
+        ..Default::default()
+    };
+    kernel::init_with_options(boot_info, options);
+
+    assert_eq!(
+        kernel::threads::interrupt::CONTROLLER.lock().kind(),
+        kernel::threads::interrupt::ControllerKind::Apic
+    );
+
+    // The local APIC timer drives the ticks, with the PIT's line masked.
+    assert!(kernel::threads::interrupt::CONTROLLER
+        .lock()
+        .is_irq_masked(0));
+    let start = kernel::devices::timer::TIMER.lock().ticks();
+    kernel::devices::timer::sleep(10);
+    assert!(kernel::devices::timer::TIMER.lock().elapsed(start) >= 10);
+
+    kernel::devices::shutdown::power_off()
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
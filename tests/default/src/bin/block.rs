@@ -0,0 +1,160 @@
+#![no_std]
+#![no_main]
+
+extern crate kernel;
+
+use kernel::devices::block::{BlockDevice, Kind, SECTOR_SIZE};
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init_with_options(
+        boot_info,
+        kernel::init::Options {
+            scratch_device: Some("hda"),
+            ..kernel::init::Options::default()
+        },
+    );
+
+    // Roles named in the options are given at boot.
+    let scratch = kernel::devices::block::get_role(Kind::Scratch).unwrap();
+    assert_eq!(scratch.name(), "hda");
+    assert_eq!(scratch.kind(), Kind::Raw);
+
+    // The runner plugs in two blank disks, which have no partitions yet.
+    let vda = kernel::devices::block::find("vda").unwrap();
+    let vdb = kernel::devices::block::find("vdb").unwrap();
+    assert_eq!(vda.kind(), Kind::Raw);
+    assert_eq!(vda.sector_count(), 2048);
+    assert_eq!(vda.sector_size(), SECTOR_SIZE);
+    assert!(kernel::devices::block::find("vda1").is_none());
+
+    // An MBR with a primary partition, an extended partition holding two
+    // logical partitions, and another primary partition.
+    let mut table = [0; SECTOR_SIZE];
+    mbr_entry(&mut table, 0, 0x21, 16, 512);
+    mbr_entry(&mut table, 1, 0x05, 528, 1024);
+    mbr_entry(&mut table, 2, 0x83, 1600, 100);
+    vda.write(0, &table);
+
+    // Logical partitions are relative to their table, and the next table to
+    // the extended partition.
+    let mut table = [0; SECTOR_SIZE];
+    mbr_entry(&mut table, 0, 0x23, 8, 256);
+    mbr_entry(&mut table, 1, 0x05, 300, 200);
+    vda.write(528, &table);
+
+    let mut table = [0; SECTOR_SIZE];
+    mbr_entry(&mut table, 0, 0x22, 8, 100);
+    vda.write(828, &table);
+
+    kernel::devices::block::scan_partitions(vda);
+    for &(name, kind, sector_cnt) in &[
+        ("vda1", Kind::Filesys, 512),
+        ("vda2", Kind::Swap, 256),
+        ("vda3", Kind::Scratch, 100),
+        ("vda4", Kind::Foreign, 100),
+    ] {
+        let partition = kernel::devices::block::find(name).unwrap();
+        kernel::println!("{}: {:?}, {} sectors", name, partition.kind(), sector_cnt);
+        assert_eq!(partition.kind(), kind);
+        assert_eq!(partition.sector_count(), sector_cnt);
+    }
+    assert!(kernel::devices::block::find("vda5").is_none());
+
+    // Partitions are windows into the disk.
+    let vda1 = kernel::devices::block::find("vda1").unwrap();
+    let vda3 = kernel::devices::block::find("vda3").unwrap();
+    let mut sector = [0; SECTOR_SIZE];
+    for (i, byte) in sector.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    vda1.write(0, &sector);
+    vda3.write(99, &sector);
+
+    let mut read = [0; SECTOR_SIZE];
+    vda.read(16, &mut read);
+    assert_eq!(read, sector);
+    vda.read(836 + 99, &mut read);
+    assert_eq!(read, sector);
+
+    assert_eq!(vda1.read_count(), 0);
+    assert_eq!(vda1.write_count(), 1);
+    vda1.read(0, &mut read);
+    assert_eq!(vda1.read_count(), 1);
+
+    // Roles go to the first partition of their kind, or the one named.
+    kernel::devices::block::locate(Kind::Filesys, None);
+    assert_eq!(
+        kernel::devices::block::get_role(Kind::Filesys)
+            .unwrap()
+            .name(),
+        "vda1"
+    );
+    kernel::devices::block::locate(Kind::Swap, Some("vda3"));
+    assert_eq!(
+        kernel::devices::block::get_role(Kind::Swap).unwrap().name(),
+        "vda3"
+    );
+
+    // A GPT disk, behind its protective MBR, with Linux swap space in its
+    // second entry.
+    let mut table = [0; SECTOR_SIZE];
+    mbr_entry(&mut table, 0, 0xee, 1, 2047);
+    vdb.write(0, &table);
+
+    let mut header = [0; SECTOR_SIZE];
+    header[0..8].copy_from_slice(b"EFI PART");
+    header[72..80].copy_from_slice(&2u64.to_le_bytes());
+    header[80..84].copy_from_slice(&4u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    vdb.write(1, &header);
+
+    let mut entries = [0; SECTOR_SIZE];
+    entries[128..144].copy_from_slice(&[
+        0x6d, 0xfd, 0x57, 0x06, 0xab, 0xa4, 0xc4, 0x43, 0x84, 0xe5, 0x09, 0x33, 0xc8, 0x4b, 0x4f,
+        0x4f,
+    ]);
+    entries[160..168].copy_from_slice(&34u64.to_le_bytes());
+    entries[168..176].copy_from_slice(&99u64.to_le_bytes());
+
+    // Malformed entries, which end before they start, or past the end of
+    // the disk once their size wraps around, are skipped.
+    for (index, first, last) in [(2, 200u64, 100u64), (3, 1000, u64::MAX)] {
+        let entry = &mut entries[index * 128..(index + 1) * 128];
+        entry[0] = 0xaf;
+        entry[32..40].copy_from_slice(&first.to_le_bytes());
+        entry[40..48].copy_from_slice(&last.to_le_bytes());
+    }
+    vdb.write(2, &entries);
+
+    kernel::devices::block::scan_partitions(vdb);
+    assert!(kernel::devices::block::find("vdb1").is_none());
+    let vdb2 = kernel::devices::block::find("vdb2").unwrap();
+    assert_eq!(vdb2.kind(), Kind::Swap);
+    assert_eq!(vdb2.sector_count(), 66);
+    assert!(kernel::devices::block::find("vdb3").is_none());
+    assert!(kernel::devices::block::find("vdb4").is_none());
+
+    kernel::devices::block::BLOCKS.lock().print_stats();
+
+    kernel::println!("done");
+
+    kernel::devices::shutdown::power_off();
+}
+
+/// Fills entry `index` of the MBR partition table in `table`.
+fn mbr_entry(table: &mut [u8; SECTOR_SIZE], index: usize, kind: u8, start: u32, size: u32) {
+    let entry = &mut table[446 + index * 16..446 + (index + 1) * 16];
+    entry[4] = kind;
+    entry[8..12].copy_from_slice(&start.to_le_bytes());
+    entry[12..16].copy_from_slice(&size.to_le_bytes());
+    table[510] = 0x55;
+    table[511] = 0xaa;
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
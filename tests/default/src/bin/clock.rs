@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // The clock never goes backwards.
+    let mut last = kernel::devices::clock::Instant::now();
+    for _ in 0..1000 {
+        let now = kernel::devices::clock::Instant::now();
+        assert!(now >= last);
+        last = now;
+    }
+
+    // Sleeps shorter than a tick busy-wait, longer ones block in between.
+    for (us, max_us) in [(500, 10_000), (25_000, 45_000)] {
+        let start = kernel::devices::clock::Instant::now();
+        kernel::devices::timer::usleep(us);
+        let elapsed = start.elapsed().as_micros() as u64;
+
+        assert!(elapsed >= us);
+        assert!(elapsed < max_us);
+    }
+
+    // Busy-waits end with interrupts off too, while the ticks stop.
+    kernel::threads::interrupt::disable();
+    for _ in 0..10 {
+        kernel::devices::timer::usleep(9_000);
+    }
+    kernel::threads::interrupt::enable();
+
+    kernel::devices::shutdown::power_off()
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // Output goes to the ring, as long as it is enabled.
+    kernel::console::ring::RING.lock().clear();
+    kernel::println!("kept");
+    assert!(kernel::console::CONSOLE.lock().set_enabled("ring", false));
+    kernel::println!("dropped");
+    assert!(kernel::console::CONSOLE.lock().set_enabled("ring", true));
+    assert!(ring_equals(b"kept\n"));
+
+    assert!(!kernel::console::CONSOLE
+        .lock()
+        .set_enabled("nonexistent", true));
+
+    // The framebuffer interprets escape sequences rather than drawing them.
+    if kernel::console::CONSOLE.lock().is_enabled("framebuffer") {
+        kernel::print!("\x1b[2J\x1b[H\x1b[1;31mred\x1b[0m\tx\x08y");
+        let position = kernel::devices::framebuffer::FRAMEBUFFER
+            .lock()
+            .as_ref()
+            .unwrap()
+            .position();
+        kernel::println!();
+        assert_eq!(position, (9, 0));
+    }
+
+    kernel::println!("done");
+
+    kernel::devices::shutdown::power_off();
+}
+
+fn ring_equals(expected: &[u8]) -> bool {
+    let ring = kernel::console::ring::RING.lock();
+    ring.len() == expected.len() && ring.bytes().zip(expected.iter()).all(|(a, &b)| a == b)
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
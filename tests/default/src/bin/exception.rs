@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+/// Address of a page which is not mapped.
+const UNMAPPED_ADDRESS: u64 = 0xdead_b000;
+
+static BREAKPOINTS: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // Breakpoints are traps, so the handler returns right after `int3`.
+    kernel::threads::interrupt::REGISTRY
+        .lock()
+        .register(3, breakpoint, "#BP Breakpoint Exception");
+    unsafe {
+        core::arch::asm!("int3");
+    }
+    assert_eq!(BREAKPOINTS.load(core::sync::atomic::Ordering::SeqCst), 1);
+
+    // Page faults would retry the faulting instruction, so the handler
+    // finishes the test instead of returning.
+    kernel::threads::interrupt::REGISTRY.lock().register(
+        14,
+        page_fault,
+        "#PF Page-Fault Exception",
+    );
+    unsafe {
+        core::ptr::write_volatile(UNMAPPED_ADDRESS as *mut u64, 0);
+    }
+
+    panic!("Page fault did not happen");
+}
+
+fn breakpoint(frame: &mut kernel::threads::interrupt::Frame) {
+    assert_eq!(frame.vector(), 3);
+    assert_eq!(frame.error_code(), None);
+
+    BREAKPOINTS.fetch_add(1, core::sync::atomic::Ordering::SeqCst);
+}
+
+fn page_fault(frame: &mut kernel::threads::interrupt::Frame) {
+    assert_eq!(frame.vector(), 14);
+    assert_eq!(
+        kernel::threads::interrupt::fault_address().as_u64(),
+        UNMAPPED_ADDRESS
+    );
+
+    let error =
+        kernel::threads::interrupt::PageFaultError::from_bits_truncate(frame.error_code().unwrap());
+    assert_eq!(error, kernel::threads::interrupt::PageFaultError::WRITE);
+
+    kernel::devices::shutdown::power_off()
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
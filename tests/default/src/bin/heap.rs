@@ -35,6 +35,5 @@ kernel::entry_point!(kernel_main);
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    kernel::println!("{info}");
-    kernel::devices::shutdown::power_off_with_failure()
+    kernel_test::panic(info)
 }
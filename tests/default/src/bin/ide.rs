@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+extern crate kernel;
+
+use kernel::devices::block::BlockDevice;
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // QEMU attaches the boot image as the master of the first channel.
+    let disk = kernel::devices::ide::find("hda").unwrap();
+    kernel::println!(
+        "{}: {} sectors, model \"{}\"",
+        disk.name(),
+        disk.sector_count(),
+        disk.model()
+    );
+    assert_eq!(disk.model(), "QEMU HARDDISK");
+    assert!(disk.sector_count() > 0);
+    assert!(kernel::devices::ide::find("hdd").is_none());
+
+    // The boot sector ends with the boot signature.
+    let mut sector = [0; kernel::devices::block::SECTOR_SIZE];
+    disk.read(0, &mut sector);
+    assert_eq!(sector[510..512], [0x55, 0xaa]);
+
+    // Write a pattern to the last sector, and read it back. The disk is a
+    // snapshot, but restore it anyway.
+    let last = disk.sector_count() - 1;
+    let mut saved = [0; kernel::devices::block::SECTOR_SIZE];
+    disk.read(last, &mut saved);
+
+    let mut pattern = [0; kernel::devices::block::SECTOR_SIZE];
+    for (i, byte) in pattern.iter_mut().enumerate() {
+        *byte = (i * 7 + 3) as u8;
+    }
+    disk.write(last, &pattern);
+    disk.read(last, &mut sector);
+    assert_eq!(sector, pattern);
+
+    disk.write(last, &saved);
+    disk.read(last, &mut sector);
+    assert_eq!(sector, saved);
+
+    kernel::println!("done");
+
+    kernel::devices::shutdown::power_off();
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // The runner types "raw\n", then "cooked" with two typos fixed by
+    // backspaces.
+
+    // Raw mode returns bytes as received.
+    for &expected in b"raw\n" {
+        assert_eq!(kernel::devices::input::getc(), expected);
+    }
+
+    // Cooked mode returns the line as edited.
+    kernel::devices::input::set_cooked(true);
+    let mut line = [0; 16];
+    let length = kernel::devices::input::read_line(&mut line);
+    assert_eq!(&line[..length], b"cooked");
+
+    kernel::println!();
+    kernel::println!("input ok");
+
+    kernel::devices::shutdown::power_off();
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Vector of ISA interrupt 5, which nothing else uses.
+const VECTOR: usize = 0x25;
+
+static FIRST_CALLS: AtomicUsize = AtomicUsize::new(0);
+static SECOND_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    // The PIT drives the ticks on the PICs, so masking its line stops them.
+    let options = kernel::init::Options {
+        interrupt_controller: kernel::threads::interrupt::ControllerKind::Pic,
+        ..Default::default()
+    };
+    kernel::init_with_options(boot_info, options);
+
+    // Lines of the slave PIC stay masked until their drivers enable them.
+    // The boot disk's driver enables IRQ 14, and its secondary channel 15.
+    for irq in 8..14 {
+        assert!(
+            kernel::threads::interrupt::CONTROLLER
+                .lock()
+                .is_irq_masked(irq),
+            "IRQ {} is unmasked",
+            irq
+        );
+    }
+
+    // Both handlers sharing the line are called, whichever handles it.
+    kernel::threads::interrupt::REGISTRY
+        .lock()
+        .register_shared(VECTOR, first, "first device");
+    kernel::threads::interrupt::REGISTRY
+        .lock()
+        .register_shared(VECTOR, second, "second device");
+    raise();
+    assert_eq!(FIRST_CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(SECOND_CALLS.load(Ordering::SeqCst), 1);
+
+    kernel::threads::interrupt::REGISTRY
+        .lock()
+        .unregister_shared(VECTOR, second);
+    raise();
+    assert_eq!(FIRST_CALLS.load(Ordering::SeqCst), 2);
+    assert_eq!(SECOND_CALLS.load(Ordering::SeqCst), 1);
+
+    kernel::threads::interrupt::REGISTRY
+        .lock()
+        .unregister(VECTOR);
+
+    // The timer does not tick while its line is masked. The monotonic clock
+    // may be counting timer ticks, so the real-time clock times the wait: two
+    // changes of its seconds are at least a second apart.
+    kernel::threads::interrupt::disable_irq(0);
+    assert!(kernel::threads::interrupt::CONTROLLER
+        .lock()
+        .is_irq_masked(0));
+    let start = kernel::devices::timer::TIMER.lock().ticks();
+    let mut time = kernel::devices::rtc::read();
+    for _ in 0..2 {
+        loop {
+            let now = kernel::devices::rtc::read();
+            if now != time {
+                time = now;
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+    assert_eq!(kernel::devices::timer::TIMER.lock().ticks(), start);
+
+    kernel::threads::interrupt::enable_irq(0);
+    kernel::devices::timer::sleep(1);
+    assert!(kernel::devices::timer::TIMER.lock().elapsed(start) >= 1);
+
+    kernel::devices::shutdown::power_off()
+}
+
+/// Raises the interrupt by software.
+fn raise() {
+    unsafe {
+        core::arch::asm!("int 0x25");
+    }
+}
+
+fn first(_frame: &mut kernel::threads::interrupt::Frame) -> bool {
+    FIRST_CALLS.fetch_add(1, Ordering::SeqCst);
+    false
+}
+
+fn second(_frame: &mut kernel::threads::interrupt::Frame) -> bool {
+    SECOND_CALLS.fetch_add(1, Ordering::SeqCst);
+    true
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
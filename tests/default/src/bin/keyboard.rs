@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    let mut keyboard = kernel::devices::keyboard::Keyboard::new();
+
+    // 'a', then with shift, caps lock, or both.
+    assert_eq!(type_char(&mut keyboard, &[0x1e, 0x9e]), b'a');
+    assert_eq!(type_char(&mut keyboard, &[0x2a, 0x1e, 0x9e, 0xaa]), b'A');
+    assert_eq!(type_char(&mut keyboard, &[0x3a, 0xba, 0x1e, 0x9e]), b'A');
+    assert_eq!(type_char(&mut keyboard, &[0x36, 0x1e, 0x9e, 0xb6]), b'a');
+    assert_eq!(type_char(&mut keyboard, &[0x3a, 0xba, 0x1e, 0x9e]), b'a');
+
+    // Shifted symbols, and control characters.
+    assert_eq!(type_char(&mut keyboard, &[0x2a, 0x02, 0x82, 0xaa]), b'!');
+    assert_eq!(type_char(&mut keyboard, &[0x1d, 0x2e, 0xae, 0x9d]), 0x03);
+    assert_eq!(type_char(&mut keyboard, &[0x1c, 0x9c]), b'\r');
+
+    // Extended keys.
+    assert_eq!(keyboard.receive(0xe0), None);
+    assert_eq!(
+        keyboard.receive(0x48),
+        Some(kernel::devices::keyboard::KeyEvent {
+            key: kernel::devices::keyboard::Key::Up,
+            pressed: true,
+        })
+    );
+    assert_eq!(keyboard.receive(0xe0), None);
+    assert_eq!(
+        keyboard.receive(0xc8),
+        Some(kernel::devices::keyboard::KeyEvent {
+            key: kernel::devices::keyboard::Key::Up,
+            pressed: false,
+        })
+    );
+    assert_eq!(type_char(&mut keyboard, &[0xe0, 0x53, 0xe0, 0xd3]), 0x7f);
+
+    // Keys typed go through IRQ 1 into the input buffer: 'h', 'i' with
+    // shift, Enter, and the up arrow.
+    for &code in &[
+        0x23, 0xa3, 0x2a, 0x17, 0x97, 0xaa, 0x1c, 0x9c, 0xe0, 0x48, 0xe0, 0xc8,
+    ] {
+        send_scan_code(code);
+    }
+    for &expected in b"hI\r\x1b[A" {
+        assert_eq!(kernel::devices::input::getc(), expected);
+    }
+
+    kernel::devices::shutdown::power_off();
+}
+
+/// Feeds `scan_codes` to `keyboard`, and returns the only character typed.
+fn type_char(keyboard: &mut kernel::devices::keyboard::Keyboard, scan_codes: &[u8]) -> u8 {
+    let mut typed = None;
+    for &code in scan_codes {
+        if let Some(kernel::devices::keyboard::KeyEvent {
+            key: kernel::devices::keyboard::Key::Char(c),
+            pressed: true,
+        }) = keyboard.receive(code)
+        {
+            assert_eq!(typed, None);
+            typed = Some(c);
+        }
+    }
+    typed.unwrap()
+}
+
+/// Makes the 8042 controller act as if the keyboard sent `code`, with its
+/// "write keyboard output buffer" command. It interrupts on IRQ 1, as for a
+/// key stroke.
+fn send_scan_code(code: u8) {
+    const DATA_PORT: u16 = 0x60;
+    const COMMAND_PORT: u16 = 0x64;
+    const INPUT_FULL: u8 = 0b10;
+    const OUTPUT_FULL: u8 = 0b01;
+    const WRITE_KEYBOARD_OUTPUT: u8 = 0xd2;
+
+    let mut data = x86_64::instructions::port::Port::<u8>::new(DATA_PORT);
+    let mut command = x86_64::instructions::port::Port::<u8>::new(COMMAND_PORT);
+
+    unsafe {
+        // Wait for the interrupt handler to take the previous code.
+        while command.read() & (INPUT_FULL | OUTPUT_FULL) != 0 {
+            core::hint::spin_loop();
+        }
+        command.write(WRITE_KEYBOARD_OUTPUT);
+        while command.read() & INPUT_FULL != 0 {
+            core::hint::spin_loop();
+        }
+        data.write(code);
+    }
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
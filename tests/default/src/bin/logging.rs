@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init_with_options(
+        boot_info,
+        kernel::init::Options {
+            log_filter: "warn,logging=debug,logging::quiet=off",
+            ..kernel::init::Options::default()
+        },
+    );
+
+    log::debug!("debug shown");
+    log::trace!("trace hidden");
+    log::info!(target: "other", "info hidden");
+    log::warn!(target: "other", "warn shown");
+    log::error!(target: "logging::quiet", "error hidden");
+    log::error!(target: "logging::quieter", "error shown");
+
+    // Records survive disabling the console sinks.
+    kernel::console::CONSOLE
+        .lock()
+        .set_enabled(kernel::console::Console::SERIAL, false);
+    log::warn!("after the console");
+    kernel::console::CONSOLE
+        .lock()
+        .set_enabled(kernel::console::Console::SERIAL, true);
+
+    let mut dmesg = Buffer::new();
+    kernel::logging::dmesg(&mut dmesg).unwrap();
+    let dmesg = dmesg.as_str();
+
+    assert!(dmesg.contains("DEBUG main: logging: debug shown"));
+    assert!(dmesg.contains("WARN  main: other: warn shown"));
+    assert!(dmesg.contains("error shown"));
+    assert!(dmesg.contains("after the console"));
+    assert!(!dmesg.contains("hidden"));
+
+    kernel::devices::shutdown::power_off();
+}
+
+/// Fixed-size buffer to write the log into.
+struct Buffer {
+    bytes: [u8; 4096],
+    len: usize,
+}
+
+impl Buffer {
+    fn new() -> Self {
+        Self {
+            bytes: [0; 4096],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+}
+
+impl core::fmt::Write for Buffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(core::fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -14,6 +14,6 @@ kernel::entry_point!(kernel_main);
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    kernel::println!("{info}");
+    kernel_test::report_panic(info);
     kernel::devices::shutdown::power_off()
 }
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+/// Sink which panics when written to, with the console locked.
+struct PanickingSink;
+
+impl kernel::console::Sink for PanickingSink {
+    fn write_str(&self, s: &str) {
+        if s.contains("boom") {
+            panic!("{} while printing", "Panicked");
+        }
+    }
+}
+
+static SINK: PanickingSink = PanickingSink;
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    kernel::console::CONSOLE.lock().register("panicking", &SINK);
+    kernel::println!("boom");
+
+    kernel::devices::shutdown::power_off_with_failure();
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    // The console is still locked by the panicking sink, so this must not
+    // wait for it.
+    kernel_test::report_panic(info);
+    kernel::devices::shutdown::power_off()
+}
@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+
+static PROBED: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+/// Takes QEMU's e1000 network card.
+static E1000_DRIVER: kernel::devices::pci::Driver = kernel::devices::pci::Driver {
+    name: "test-e1000",
+    matches: &[kernel::devices::pci::Match::Id {
+        vendor_id: 0x8086,
+        device_id: 0x100e,
+    }],
+    probe,
+};
+
+/// Matches the e1000 too, but comes too late.
+static LATE_DRIVER: kernel::devices::pci::Driver = kernel::devices::pci::Driver {
+    name: "test-late",
+    matches: &[kernel::devices::pci::Match::Class {
+        class: 0x02,
+        subclass: 0x00,
+    }],
+    probe,
+};
+
+fn probe(device: &kernel::devices::pci::Device) -> bool {
+    PROBED.fetch_add(1, core::sync::atomic::Ordering::SeqCst);
+    device.enable(kernel::devices::pci::Command::BUS_MASTER);
+    true
+}
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // The host bridge of QEMU's i440FX machine.
+    let host = kernel::devices::pci::Address::new(0, 0, 0);
+    assert_eq!(host.read_u16(0x00), 0x8086);
+    assert_eq!(host.read_u16(0x02), 0x1237);
+
+    {
+        let pci = kernel::devices::pci::PCI.lock();
+        for device in pci.devices() {
+            kernel::println!("{}: {:x?}", device.address, device.bars);
+        }
+
+        // The IDE controller of the PIIX3, with its bus master registers.
+        let ide = pci
+            .devices()
+            .find(|device| device.class == 0x01 && device.subclass == 0x01)
+            .unwrap();
+        assert!(matches!(
+            ide.bars[4],
+            Some(kernel::devices::pci::Bar::Io { size: 16, .. })
+        ));
+
+        // The standard VGA card, with its framebuffer.
+        let vga = pci
+            .devices()
+            .find(|device| device.vendor_id == 0x1234 && device.device_id == 0x1111)
+            .unwrap();
+        assert!(matches!(
+            vga.bars[0],
+            Some(kernel::devices::pci::Bar::Memory {
+                size: 0x100_0000,
+                prefetchable: true,
+                ..
+            })
+        ));
+
+        // The network card, with an interrupt line.
+        let e1000 = pci
+            .devices()
+            .find(|device| device.vendor_id == 0x8086 && device.device_id == 0x100e)
+            .unwrap();
+        assert!(matches!(
+            e1000.bars[0],
+            Some(kernel::devices::pci::Bar::Memory { size: 0x2_0000, .. })
+        ));
+        assert!(e1000.interrupt_line.is_some());
+        assert_eq!(e1000.interrupt_pin, Some(1));
+
+        // The virtio device has vendor-specific capabilities, describing its
+        // registers.
+        let virtio = pci
+            .devices()
+            .find(|device| device.vendor_id == 0x1af4)
+            .unwrap();
+        assert!(virtio
+            .find_capability(kernel::devices::pci::Capability::VENDOR_SPECIFIC)
+            .is_some());
+    }
+
+    // A driver gets the devices it matches, and they are theirs.
+    kernel::devices::pci::register_driver(&E1000_DRIVER);
+    kernel::devices::pci::register_driver(&LATE_DRIVER);
+    assert_eq!(PROBED.load(core::sync::atomic::Ordering::SeqCst), 1);
+
+    let pci = kernel::devices::pci::PCI.lock();
+    let e1000 = pci
+        .devices()
+        .find(|device| device.vendor_id == 0x8086 && device.device_id == 0x100e)
+        .unwrap();
+    assert_eq!(e1000.driver(), Some("test-e1000"));
+    assert!(e1000
+        .command()
+        .contains(kernel::devices::pci::Command::BUS_MASTER));
+    drop(pci);
+
+    kernel::println!("done");
+
+    kernel::devices::shutdown::power_off();
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
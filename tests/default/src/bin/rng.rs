@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+extern crate kernel;
+
+use kernel::utils::random::{ChaChaRng, SeededRng};
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // The same seed gives the same numbers, and another seed others.
+    let mut a = SeededRng::new(42);
+    let mut b = SeededRng::new(42);
+    let mut c = SeededRng::new(43);
+    for _ in 0..100 {
+        let n = a.next_u64();
+        assert_eq!(n, b.next_u64());
+        assert_ne!(n, c.next_u64());
+    }
+    for bound in 1..100 {
+        assert!(a.below(bound) < bound);
+    }
+
+    // The first block of the keystream of the zero key, from RFC 7539.
+    let mut block = [0; 64];
+    ChaChaRng::new([0; 32]).fill_bytes(&mut block);
+    assert_eq!(
+        block[..16],
+        [
+            0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86,
+            0xbd, 0x28
+        ]
+    );
+    assert_eq!(
+        block[48..],
+        [
+            0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c, 0xc3, 0x87, 0xb6, 0x69, 0xb2, 0xee,
+            0x65, 0x86
+        ]
+    );
+
+    // Each request rekeys, so the same request gives other bytes.
+    let mut chacha = ChaChaRng::new([7; 32]);
+    let (mut first, mut second) = ([0; 32], [0; 32]);
+    chacha.fill_bytes(&mut first);
+    chacha.fill_bytes(&mut second);
+    assert_ne!(first, second);
+
+    // The runner asks for a CPU with RDRAND.
+    kernel::println!(
+        "rdrand: {}, rdseed: {}",
+        kernel::devices::rng::has_rdrand(),
+        kernel::devices::rng::has_rdseed()
+    );
+    assert!(kernel::devices::rng::has_rdrand());
+    assert!(kernel::devices::rng::hardware_u64().is_some());
+
+    // Timer interrupts feed the pool.
+    let interrupts = kernel::devices::rng::interrupt_count();
+    kernel::devices::timer::sleep(10);
+    assert!(kernel::devices::rng::interrupt_count() > interrupts);
+
+    // The kernel's generator, reseeded along the way.
+    let mut previous = kernel::devices::rng::next_u64();
+    for _ in 0..200 {
+        let n = kernel::devices::rng::next_u64();
+        assert_ne!(n, previous);
+        previous = n;
+    }
+    let mut bytes = [0; 256];
+    kernel::devices::rng::fill_bytes(&mut bytes);
+    assert!(bytes.iter().any(|&byte| byte != bytes[0]));
+
+    kernel::println!("done");
+
+    kernel::devices::shutdown::power_off();
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // Conversions to and from Unix time.
+    let epoch = kernel::devices::rtc::DateTime::from_unix(0);
+    assert_eq!(
+        epoch,
+        kernel::devices::rtc::DateTime {
+            year: 1970,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        }
+    );
+    let leap_day = kernel::devices::rtc::DateTime {
+        year: 2000,
+        month: 2,
+        day: 29,
+        hour: 0,
+        minute: 0,
+        second: 0,
+    };
+    assert_eq!(leap_day.to_unix(), 951_782_400);
+    assert_eq!(
+        kernel::devices::rtc::DateTime::from_unix(1_792_326_896),
+        kernel::devices::rtc::DateTime {
+            year: 2026,
+            month: 10,
+            day: 18,
+            hour: 12,
+            minute: 34,
+            second: 56,
+        }
+    );
+    for time in (0..4_102_444_800).step_by(86_399 * 7) {
+        assert_eq!(
+            kernel::devices::rtc::DateTime::from_unix(time).to_unix(),
+            time
+        );
+    }
+
+    // QEMU starts the real-time clock at the host's time.
+    let now = kernel::devices::rtc::read();
+    kernel::println!("RTC: {now}");
+    assert!(now.year >= 2024);
+    assert!((1..=12).contains(&now.month));
+    assert!((1..=31).contains(&now.day));
+    assert!(now.hour < 24 && now.minute < 60 && now.second < 60);
+
+    // The Unix time follows the monotonic clock.
+    let boot_time = kernel::devices::rtc::boot_time().unwrap();
+    let before = kernel::devices::rtc::now();
+    assert!(before >= boot_time);
+    kernel::devices::timer::msleep(1_100);
+    assert!(kernel::devices::rtc::now() > before);
+
+    kernel::println!("done");
+
+    kernel::devices::shutdown::power_off();
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init_with_options(
+        boot_info,
+        kernel::init::Options {
+            serial: kernel::devices::serial::Config {
+                baud_rate: 115_200,
+                fifo: Some(kernel::devices::serial::FifoTrigger::Bytes14),
+                ..kernel::devices::serial::Config::new()
+            },
+            ..kernel::init::Options::default()
+        },
+    );
+
+    // QEMU emulates a 16550A on COM1. Testing it again resets it, so keep the
+    // console out of the way meanwhile.
+    kernel::threads::interrupt::disable();
+    kernel::devices::serial::flush();
+    let mut com1 = kernel::devices::serial::Serial::new(kernel::devices::serial::Config {
+        baud_rate: 115_200,
+        ..kernel::devices::serial::Config::new()
+    });
+    com1.init_poll();
+    assert!(com1.is_present());
+    assert!(com1.has_fifo());
+    kernel::devices::serial::notify();
+    kernel::threads::interrupt::enable();
+
+    // Only COM1 is connected, so the others fail their self-test, and what is
+    // sent to them is dropped instead of hanging.
+    let mut com2 = kernel::devices::serial::Serial::new(kernel::devices::serial::Config {
+        port: kernel::devices::serial::Port::Com2,
+        ..kernel::devices::serial::Config::new()
+    });
+    com2.init_poll();
+    assert!(!com2.is_present());
+    <kernel::devices::serial::Serial as core::fmt::Write>::write_str(&mut com2, "lost").unwrap();
+
+    kernel::println!("done");
+
+    kernel::devices::shutdown::power_off();
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // Much more output than the transmit queue holds, so that the thread has
+    // to sleep until the serial interrupt drains it.
+    assert!(kernel::threads::interrupt::are_enabled());
+    for i in 0..64 {
+        kernel::println!("queued line {i:2}: the quick brown fox jumps over the lazy dog");
+    }
+
+    // With interrupts off, the queue is flushed and output is polled.
+    kernel::threads::interrupt::disable();
+    for i in 0..4 {
+        kernel::println!("polled line {i:2}: the quick brown fox jumps over the lazy dog");
+    }
+    kernel::threads::interrupt::enable();
+
+    kernel::println!("done");
+
+    kernel::devices::shutdown::power_off();
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // The timer channel reads back as configured.
+    let (status, _) = kernel::devices::pit::PIT
+        .lock()
+        .read_status(kernel::devices::pit::Channel::OUT0);
+    assert!(status.mode.is_some());
+
+    // Turning the speaker on runs channel 2 in square wave mode.
+    kernel::devices::speaker::on(1000);
+    assert!(kernel::devices::speaker::is_on());
+
+    let (status, _) = kernel::devices::pit::PIT
+        .lock()
+        .read_status(kernel::devices::pit::Channel::OUT2);
+    assert_eq!(status.mode, Some(kernel::devices::pit::Mode::SquareWave));
+
+    // Its counter runs, and its output goes up and down.
+    let first = kernel::devices::pit::PIT
+        .lock()
+        .read_count(kernel::devices::pit::Channel::OUT2);
+    let (mut high, mut low) = (false, false);
+    for _ in 0..10_000 {
+        if kernel::devices::speaker::output() {
+            high = true;
+        } else {
+            low = true;
+        }
+    }
+    let second = kernel::devices::pit::PIT
+        .lock()
+        .read_count(kernel::devices::pit::Channel::OUT2);
+    assert_ne!(first, second);
+    assert!(high && low);
+
+    kernel::devices::speaker::off();
+    assert!(!kernel::devices::speaker::is_on());
+
+    // Frequencies out of range turn the speaker off.
+    kernel::devices::speaker::on(1000);
+    kernel::devices::speaker::on(5);
+    assert!(!kernel::devices::speaker::is_on());
+
+    // A beep lasts as long as asked, and leaves the speaker off.
+    let start = kernel::devices::clock::Instant::now();
+    kernel::devices::speaker::beep(
+        kernel::devices::speaker::BEEP_FREQUENCY,
+        core::time::Duration::from_millis(50),
+    );
+    assert!(start.elapsed() >= core::time::Duration::from_millis(50));
+    assert!(!kernel::devices::speaker::is_on());
+
+    kernel::devices::shutdown::power_off();
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // The double fault handler reports the overflow from its own stack, and
+    // powers off with failure, which the runner expects.
+    //
+    // Keep the timer from interrupting with a corrupted thread.
+    kernel::threads::interrupt::disable();
+    recurse(0);
+
+    panic!("Stack overflow did not happen");
+}
+
+/// Recurses until the stack overflows into the guard page below it.
+#[allow(unconditional_recursion)]
+fn recurse(depth: u64) -> u64 {
+    let buffer = core::hint::black_box([depth; 64]);
+    recurse(depth + 1) + buffer[0]
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+/// Number of ticks to sleep for.
+const TICKS: usize = 100;
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    let (start, skipped) = {
+        let mut timer = kernel::devices::timer::TIMER.lock();
+        (timer.ticks(), timer.skipped_ticks())
+    };
+    let time = kernel::devices::clock::Instant::now();
+
+    // Only the idle thread runs meanwhile, and it stops the periodic tick.
+    kernel::devices::timer::sleep(TICKS);
+
+    let (elapsed, skipped) = {
+        let mut timer = kernel::devices::timer::TIMER.lock();
+        (timer.elapsed(start), timer.skipped_ticks() - skipped)
+    };
+    let time = time.elapsed();
+
+    // The ticks are all counted, even without an interrupt for each.
+    assert!(elapsed >= TICKS);
+    assert!(elapsed < TICKS + 10);
+    assert!(time.as_millis() as usize >= TICKS * 1000 / kernel::devices::timer::FREQUENCY);
+
+    // The local APIC timer waits for the whole sleep with one interrupt.
+    assert!(skipped >= TICKS - 10);
+
+    kernel::devices::shutdown::power_off()
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+extern crate kernel;
+
+use kernel::devices::block::BlockDevice;
+
+/// Number of threads keeping requests outstanding at once.
+const THREAD_CNT: u32 = 4;
+
+/// Sectors each thread writes and reads back.
+const SECTORS_PER_THREAD: u32 = 32;
+
+static DONE: kernel::threads::Semaphore = kernel::threads::Semaphore::new(0);
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // The runner plugs in a blank 1 MiB disk.
+    let disk = kernel::devices::virtio::blk::find("vda").unwrap();
+    kernel::println!("{}: {} sectors", disk.name(), disk.sector_count());
+    assert_eq!(disk.sector_count(), 2048);
+    assert!(!disk.is_read_only());
+    assert!(kernel::devices::virtio::blk::find("vdb").is_none());
+
+    let mut sector = [0xff; kernel::devices::block::SECTOR_SIZE];
+    disk.read(0, &mut sector);
+    assert!(sector.iter().all(|&byte| byte == 0));
+
+    // Several threads write and read back sectors of their own, so that
+    // their requests are in flight together.
+    for i in 0..THREAD_CNT {
+        kernel::threads::SCHEDULER.lock().spawn(
+            move || {
+                let disk = kernel::devices::virtio::blk::find("vda").unwrap();
+                let first = i * SECTORS_PER_THREAD;
+                for sector_no in first..first + SECTORS_PER_THREAD {
+                    disk.write(sector_no, &pattern(sector_no));
+                }
+
+                let mut sector = [0; kernel::devices::block::SECTOR_SIZE];
+                for sector_no in first..first + SECTORS_PER_THREAD {
+                    disk.read(sector_no, &mut sector);
+                    assert_eq!(sector, pattern(sector_no));
+                }
+                DONE.up();
+            },
+            "virtio",
+            kernel::threads::thread::Thread::PRIORITY_DEFAULT,
+        );
+    }
+    for _ in 0..THREAD_CNT {
+        DONE.down();
+    }
+
+    // The last sector is reachable, too.
+    let last = disk.sector_count() - 1;
+    disk.write(last, &pattern(last));
+    disk.read(last, &mut sector);
+    assert_eq!(sector, pattern(last));
+
+    kernel::println!("done");
+
+    kernel::devices::shutdown::power_off();
+}
+
+/// Returns the bytes written to sector `sector_no`.
+fn pattern(sector_no: u32) -> [u8; kernel::devices::block::SECTOR_SIZE] {
+    let mut sector = [0; kernel::devices::block::SECTOR_SIZE];
+    for (i, byte) in sector.iter_mut().enumerate() {
+        *byte = (i as u32 ^ sector_no.wrapping_mul(31)) as u8;
+    }
+    sector
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+extern crate kernel;
+
+use kernel::devices::block::BlockDevice;
+
+fn kernel_main(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
+    kernel::init(boot_info);
+
+    // The runner plugs in a read-only disk, which reads back zeros.
+    let disk = kernel::devices::virtio::blk::find("vda").unwrap();
+    assert!(disk.is_read_only());
+
+    let mut sector = [0xff; kernel::devices::block::SECTOR_SIZE];
+    disk.read(0, &mut sector);
+    assert!(sector.iter().all(|&byte| byte == 0));
+
+    // Writing it panics, which the runner expects, before the request is
+    // sent to the device.
+    disk.write(0, &sector);
+
+    kernel::devices::shutdown::power_off();
+}
+
+kernel::entry_point!(kernel_main);
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info)
+}
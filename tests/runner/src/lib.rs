@@ -15,6 +15,10 @@ const QEMU_EXIT_CODE_SUCCESS: i32 = 0x63;
 // In constrast, the kernel writes `0x42` to the I/O port on failure.
 const QEMU_EXIT_CODE_FAILURE: i32 = 0x85;
 
+/// Line a kernel powering off through ACPI prints last, to tell the runner that
+/// it finished the test.
+pub const ACPI_POWER_OFF_MARKER: &str = "done";
+
 const QEMU_ARGS: &[&str] = &[
     // This enables `isa-debug-exit` device.
     "-device",
@@ -31,17 +35,52 @@ const QEMU_ARGS: &[&str] = &[
 
 pub struct TestOptions {
     pub gdb: bool,
+
+    /// Bytes to type into the serial port of the kernel.
+    pub input: &'static [u8],
+
+    /// Extra arguments to QEMU, e.g. to plug in more devices.
+    pub qemu_args: &'static [&'static str],
+
+    /// Blank disks to plug in, as the QEMU interface they are on, e.g.
+    /// `"virtio"`, and their size in bytes.
+    pub scratch_disks: &'static [(&'static str, u64)],
+
+    /// Whether the kernel powers off through ACPI rather than through
+    /// `isa-debug-exit`, so that QEMU exits cleanly, with code 0. The kernel
+    /// must print [`ACPI_POWER_OFF_MARKER`] right before, since QEMU also
+    /// exits with code 0 on a triple fault or a reset.
+    pub acpi_power_off: bool,
+
+    /// Whether the kernel is expected to power off with failure, e.g. on a
+    /// fault it cannot recover from.
+    pub expect_failure: bool,
+
+    /// Text the kernel is expected to print, in this order.
+    pub expected_output: &'static [&'static str],
 }
 
 impl TestOptions {
     pub fn default() -> TestOptions {
-        TestOptions { gdb: false }
+        TestOptions {
+            gdb: false,
+            input: b"",
+            qemu_args: &[],
+            scratch_disks: &[],
+            acpi_power_off: false,
+            expect_failure: false,
+            expected_output: &[],
+        }
     }
 }
 
 /// Runs a kernel on QEMU with test setup.
 pub fn run_test_kernel(kernel_binary_path: &str, options: TestOptions) {
-    let kernel_binary = PathBuf::from(kernel_binary_path);
+    // Embed the symbols into a copy of the kernel, so that it can print
+    // symbolized backtraces.
+    let kernel_binary = PathBuf::from(kernel_binary_path).with_extension("ksym");
+    kernel_symbols::embed(&PathBuf::from(kernel_binary_path), &kernel_binary).unwrap();
+
     let kernel_bios = kernel_binary.with_extension("mbr");
     bootloader::BiosBoot::new(&kernel_binary)
         .create_disk_image(&kernel_bios)
@@ -51,11 +90,17 @@ pub fn run_test_kernel(kernel_binary_path: &str, options: TestOptions) {
     cmd.arg("-drive");
     cmd.arg(format!("format=raw,file={}", kernel_bios.display()));
     cmd.args(QEMU_ARGS);
+    cmd.args(options.qemu_args);
 
-    if options.gdb {
-        /// Virtual address where kernel ELF is loaded by bootloader.
-        const KERNEL_VADDR_OFFSET: u64 = 0x8000000000;
+    for (i, &(interface, size)) in options.scratch_disks.iter().enumerate() {
+        let disk = kernel_binary.with_extension(format!("scratch{i}"));
+        std::fs::File::create(&disk).unwrap().set_len(size).unwrap();
 
+        cmd.arg("-drive");
+        cmd.arg(format!("format=raw,if={interface},file={}", disk.display()));
+    }
+
+    if options.gdb {
         cmd.arg("-s");
         cmd.arg("-S");
 
@@ -63,16 +108,52 @@ pub fn run_test_kernel(kernel_binary_path: &str, options: TestOptions) {
         println!(
             "gdb -ex \"target remote :1234\" -ex \"exec-file {kernel}\" -ex \"add-symbol-file {kernel} -o {offset:#0x}\"",
             kernel = kernel_binary_path,
-            offset = KERNEL_VADDR_OFFSET
+            offset = kernel_symbols::KERNEL_VADDR_OFFSET
         );
     }
 
-    let child_output = cmd.output().unwrap();
-    std::io::stdout().write_all(&child_output.stdout).unwrap();
+    // The serial port is connected to stdin, so that is where the input goes.
+    // QEMU reads it only as fast as the kernel receives it.
+    cmd.stdin(std::process::Stdio::piped());
+    cmd.stdout(std::process::Stdio::piped());
+    cmd.stderr(std::process::Stdio::piped());
+
+    let mut child = cmd.spawn().unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(options.input)
+        .unwrap();
+    let child_output = child.wait_with_output().unwrap();
+    // Annotate the addresses in backtraces and register dumps with their
+    // source lines.
+    match kernel_symbols::Symbolizer::new(&kernel_binary) {
+        Ok(symbolizer) => symbolizer
+            .annotate(&child_output.stdout[..], std::io::stdout())
+            .unwrap(),
+        Err(_) => std::io::stdout().write_all(&child_output.stdout).unwrap(),
+    }
     std::io::stderr().write_all(&child_output.stderr).unwrap();
 
+    let stdout = String::from_utf8_lossy(&child_output.stdout);
+    let mut rest = &stdout[..];
+    for expected in options.expected_output {
+        match rest.find(expected) {
+            Some(start) => rest = &rest[start + expected.len()..],
+            None => panic!("Expected output {:?} is missing", expected),
+        }
+    }
+
     match child_output.status.code() {
-        Some(QEMU_EXIT_CODE_SUCCESS) => (),
+        Some(QEMU_EXIT_CODE_SUCCESS) if !options.acpi_power_off && !options.expect_failure => (),
+        Some(0) if options.acpi_power_off => assert!(
+            stdout
+                .lines()
+                .any(|line| line.trim_end() == ACPI_POWER_OFF_MARKER),
+            "QEMU exited before the test finished"
+        ),
+        Some(QEMU_EXIT_CODE_FAILURE) if options.expect_failure => (),
         Some(QEMU_EXIT_CODE_FAILURE) => panic!("Test failed"),
         Some(code) => panic!("QEMU exited with code {}", code),
         None => panic!("QEMU was killed by a signal"),